@@ -0,0 +1,124 @@
+//! Generate the static atom set for EU4 and the table of binary tokens.
+//!
+//! The atoms come from `atoms.txt` and the binary tokens from
+//! `binary_tokens.txt` (or the file named by `EU4_BINARY_TOKENS`). Both files
+//! have one entry per line, and ignore blank lines and lines starting with `#`.
+//!
+//! The binary token list isn't shipped with the crate, as the IDs aren't
+//! published by Paradox. Without one, binary saves can only be read with a
+//! table loaded at runtime, so the build warns about it.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Read the non-comment lines of a list file.
+fn read_lines(path: &Path) -> Vec<(usize, String)> {
+    println!("cargo:rerun-if-changed={}", path.display());
+    let text = std::fs::read_to_string(path)
+        .unwrap_or_else(|err| panic!("Can't read {}: {}", path.display(), err));
+    text.lines()
+        .map(str::trim)
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(i, line)| (i + 1, line.to_owned()))
+        .collect()
+}
+
+/// Read the binary tokens, failing if an ID isn't hexadecimal with a `0x`
+/// prefix, or if an ID or a name is used twice, since the name of a token
+/// must give back its ID when writing binary files.
+fn read_tokens(path: &Path) -> Vec<(u16, String)> {
+    let mut seen = HashMap::new();
+    let mut seen_names = HashMap::new();
+    let mut tokens = Vec::new();
+    for (line_no, line) in read_lines(path) {
+        let location = format!("{}:{}", path.display(), line_no);
+        let mut pieces = line.split_whitespace();
+        let (id, name) = match (pieces.next(), pieces.next(), pieces.next()) {
+            (Some(id), Some(name), None) => (id, name),
+            _ => panic!("{}: expected an ID and a name", location)
+        };
+        let id = id.strip_prefix("0x")
+            .and_then(|hex| u16::from_str_radix(hex, 16).ok())
+            .unwrap_or_else(|| panic!("{}: invalid token ID {} (expected a \
+                                       hexadecimal ID like 0x2c4a)",
+                                      location, id));
+        if let Some(previous) = seen.insert(id, location.clone()) {
+            panic!("{}: duplicate token ID {:#06x} (first defined at {})",
+                   location, id, previous);
+        }
+        let previous = seen_names.insert(name.to_owned(), location.clone());
+        if let Some(previous) = previous {
+            panic!("{}: duplicate token name {} (first defined at {})",
+                   location, name, previous);
+        }
+        tokens.push((id, name.to_owned()));
+    }
+    tokens.sort();
+    tokens
+}
+
+fn main() {
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    println!("cargo:rerun-if-env-changed=EU4_BINARY_TOKENS");
+    let token_path = env::var_os("EU4_BINARY_TOKENS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| manifest_dir.join("binary_tokens.txt"));
+
+    let atoms = read_lines(&manifest_dir.join("atoms.txt"));
+    let tokens = read_tokens(&token_path);
+    if tokens.is_empty() {
+        println!("cargo:warning={} has no binary tokens, so binary saves can \
+                  only be read with a token table loaded at runtime. Set \
+                  EU4_BINARY_TOKENS to the path of a token list (see \
+                  binary_tokens.txt).", token_path.display());
+    }
+
+    let out_path = PathBuf::from(env::var("OUT_DIR").unwrap())
+        .join("eu4_binary.rs");
+    let mut out = BufWriter::new(File::create(out_path).unwrap());
+    string_cache_codegen::AtomType::new("Eu4Atom", "eu4_atom!")
+        .atoms(atoms.iter().map(|(_, atom)| atom))
+        .atoms(tokens.iter().map(|(_, name)| name))
+        .write_to(&mut out)
+        .unwrap();
+
+    writeln!(out, "
+/// The IDs and names of the tokens in binary saves, sorted by ID.
+pub const BINARY_TOKENS: &[(u16, &str)] = &[").unwrap();
+    for (id, name) in &tokens {
+        writeln!(out, "    ({:#06x}, {:?}),", id, name).unwrap();
+    }
+    writeln!(out, "];
+
+/// The indices of the entries of `BINARY_TOKENS`, sorted by name.
+const BINARY_TOKENS_BY_NAME: &[usize] = &[").unwrap();
+    let mut by_name: Vec<_> = (0..tokens.len()).collect();
+    by_name.sort_by_key(|&index| &tokens[index].1);
+    for index in by_name {
+        writeln!(out, "    {},", index).unwrap();
+    }
+    writeln!(out, "];
+
+pub struct Eu4Trait;
+
+impl paradox::GameTrait for Eu4Trait {{
+    type Static = Eu4AtomStaticSet;
+
+    fn get_binary_token(id: u16) -> Option<Eu4Atom> {{
+        BINARY_TOKENS.binary_search_by_key(&id, |&(id, _)| id)
+            .ok()
+            .map(|index| Eu4Atom::from(BINARY_TOKENS[index].1))
+    }}
+
+    fn get_binary_id(name: &str) -> Option<u16> {{
+        BINARY_TOKENS_BY_NAME
+            .binary_search_by_key(&name, |&index| BINARY_TOKENS[index].1)
+            .ok()
+            .map(|index| BINARY_TOKENS[BINARY_TOKENS_BY_NAME[index]].0)
+    }}
+}}").unwrap();
+}
@@ -0,0 +1,8 @@
+use paradox::ParseError;
+use paradox::ironmelt_typed;
+
+pub fn main() -> Result<(), ParseError> {
+    let path = std::path::Path::new("/tmp/TrailOfTears.eu4-37");
+    let out = std::path::Path::new("/tmp/melted.zip");
+    ironmelt_typed::<eu4::Eu4Trait, eu4::Gamestate>(path, out, None)
+}
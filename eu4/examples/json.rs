@@ -0,0 +1,32 @@
+//! Convert a save game (or a text file, such as one from `common`) to JSON.
+//!
+//! Usage: `json <file> [<token directory>]`. The token directory holds the
+//! token tables used to read binary saves, one file per game version.
+
+use paradox::{file_to_json, savegame_to_json, ParseError, TokenTables};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+pub fn main() -> Result<(), ParseError> {
+    let mut args = std::env::args().skip(1);
+    let path = match args.next() {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: json <file> [<token directory>]");
+            std::process::exit(1);
+        }
+    };
+    let tables = match args.next() {
+        Some(dir) => TokenTables::load_directory(Path::new(&dir))?,
+        None => TokenTables::default()
+    };
+    let path = Path::new(&path);
+    let mut out = BufWriter::new(std::io::stdout());
+    if path.extension() == Some("txt".as_ref()) {
+        file_to_json::<eu4::Eu4Trait>(path, &mut out)?;
+    } else {
+        savegame_to_json::<eu4::Eu4Trait>(path, &mut out, &tables)?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
@@ -0,0 +1,28 @@
+//! Run a query against a save game, printing one match per line.
+//!
+//! Usage: `query <save file> <query>`, such as
+//! `query Mamluks.eu4 'provinces[owner=MCH].{name, base_tax}'`.
+
+use paradox::{load_savegame, GameData, ParseError};
+use std::path::Path;
+
+pub fn main() -> Result<(), ParseError> {
+    let mut args = std::env::args().skip(1);
+    let (path, query) = match (args.next(), args.next()) {
+        (Some(path), Some(query)) => (path, query),
+        _ => {
+            eprintln!("usage: query <save file> <query>");
+            std::process::exit(1);
+        }
+    };
+    let mut game_data = GameData::load(&std::env::current_dir()?)?;
+    let save: eu4::Eu4Value =
+        load_savegame::<eu4::Eu4Trait, _>(Path::new(&path), &mut game_data)?;
+    for row in save.query(&query)? {
+        let values: Vec<_> = row.values.iter()
+            .map(|value| value.map_or(String::new(), |v| v.to_string()))
+            .collect();
+        println!("{}\t{}", row.keys.join("."), values.join("\t"));
+    }
+    Ok(())
+}
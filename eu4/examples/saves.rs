@@ -0,0 +1,26 @@
+//! List the saves in the game's save directory, newest first.
+//!
+//! Usage: `saves [<token directory>]`. The token directory holds the token
+//! tables used to read binary saves, one file per game version.
+
+use paradox::{GameData, ParseError, TokenTables};
+use std::path::Path;
+
+pub fn main() -> Result<(), ParseError> {
+    let tables = match std::env::args().nth(1) {
+        Some(dir) => TokenTables::load_directory(Path::new(&dir))?,
+        None => TokenTables::default()
+    };
+    let mut game_data = GameData::load(&std::env::current_dir()?)?;
+    for save in eu4::scan_save_games(&mut game_data, Some(&tables))? {
+        let name = save.path.file_name().unwrap_or_default().to_string_lossy();
+        match save.meta {
+            Ok(meta) => println!("{}\t{}\t{} ({})\t{}\t{}", name, meta.date,
+                                 meta.displayed_country_name, meta.player,
+                                 if meta.is_ironman { "ironman" } else { "" },
+                                 meta.savegame_versions.join(", ")),
+            Err(err) => println!("{}\terror: {}", name, err),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,9 @@
+use paradox::ParseError;
+use paradox::unmelt;
+
+pub fn main() -> Result<(), ParseError> {
+    let path = std::path::Path::new("/tmp/melted.zip");
+    let out = std::path::Path::new("/tmp/unmelted.eu4");
+    unmelt::<eu4::Eu4Trait>(path, out, None)?;
+    Ok(())
+}
@@ -1,9 +1,10 @@
-use paradox::{ParadoxParse};
-use crate::{Modifiers, Weight};
+use paradox::{ParadoxParse, ParadoxWrite};
+use crate::{Modifiers, MonarchPower, Weight};
 
-#[derive(ParadoxParse, Default)]
+#[derive(ParadoxParse, ParadoxWrite, Default)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct AdvisorType {
-    pub monarch_power: (),
+    pub monarch_power: MonarchPower,
     pub skill_scaled_modifier: Modifiers,
     pub chance: (), // XXX: this is a province Weight,
     pub ai_will_do: Weight,
@@ -1,12 +1,76 @@
+use crate::{Eu4Atom, Eu4Trait};
+use paradox::{Operator, ParadoxParse, ParadoxWrite, ParseError, Parser, Token};
+use paradox::Writer;
+use std::convert::TryFrom;
+
+/// A trigger block, such as the `potential` or `allow` of a policy. The clauses
+/// are kept as written; they are not yet evaluated.
+#[derive(Default, Debug)]
+pub struct Condition {
+    pub clauses: Vec<Clause>,
+}
+
+/// A single `key op value` entry in a trigger block, e.g. `adm_tech >= 10`.
+#[derive(Debug)]
+pub struct Clause {
+    pub key: Eu4Atom,
+    pub op: Operator,
+    pub value: ConditionValue,
+}
+
+#[derive(Debug)]
+pub enum ConditionValue {
+    Value(Eu4Atom),
+    Block(Condition),
+}
+
+impl ParadoxParse<Eu4Trait> for Condition {
+    fn read(&mut self, parser: &mut Parser<Eu4Trait>) -> Result<(), ParseError> {
+        parser.parse_key_scope(|key, op, parser| {
+            let value = if matches!(parser.peek()?, Some(Token::LBrace)) {
+                let mut block = Condition::default();
+                block.read(parser)?;
+                ConditionValue::Block(block)
+            } else {
+                match parser.get_token()? {
+                    Some(token) => ConditionValue::Value(Eu4Atom::try_from(token)?),
+                    None => return Err(ParseError::eof()),
+                }
+            };
+            self.clauses.push(Clause { key, op, value });
+            Ok(())
+        })
+    }
+}
+
+impl ParadoxWrite<Eu4Trait> for Condition {
+    fn write(&self, writer: &mut Writer<Eu4Trait>) -> Result<(), ParseError> {
+        writer.write_key_scope(|writer| {
+            for clause in &self.clauses {
+                let value: &dyn ParadoxWrite<Eu4Trait> = match &clause.value {
+                    ConditionValue::Value(value) => value,
+                    ConditionValue::Block(block) => block,
+                };
+                writer.write_field_with(&clause.key, clause.op, value)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn is_default(&self) -> bool {
+        self.clauses.is_empty()
+    }
+}
+
 pub type Factor = ();
 
-/*#[derive(ParadoxParse, Default)]
+/*#[derive(ParadoxParse, ParadoxWrite, Default)]
 pub struct Factor {
     pub factor: FixedPoint,
     #[modifiers] pub condition: Vec<CountryCondition>,
 }*/
 
-/*#[derive(ParadoxParse, Default)]
+/*#[derive(ParadoxParse, ParadoxWrite, Default)]
 pub struct Weight {
     pub factor: FixedPoint,
     #[repeated] pub modifier: Vec<Factor>,
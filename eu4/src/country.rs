@@ -1,5 +1,5 @@
 use crate::{Eu4Atom, Eu4Trait};
-use paradox::{ParadoxParse, ParseError, Parser};
+use paradox::{ParadoxParse, ParadoxWrite, ParseError, Parser};
 use std::collections::HashMap;
 
 type ParseResult = Result<(), ParseError>;
@@ -9,7 +9,7 @@ pub struct CountryMap(HashMap<Eu4Atom, Country>);
 
 impl ParadoxParse<Eu4Trait> for CountryMap {
     fn read(&mut self, parser: &mut Parser<Eu4Trait>) -> ParseResult {
-        parser.parse_key_scope(|key, parser| {
+        parser.parse_key_scope(|key, _, parser| {
             let mut filename = String::default();
             filename.read(parser)?;
             let path = format!("common/{}", filename);
@@ -18,7 +18,7 @@ impl ParadoxParse<Eu4Trait> for CountryMap {
             data.parse_directory(&path, &mut result)?;
             let id = key.clone();
             if self.0.insert(id, result).is_some() {
-                return Err(ParseError::Constraint(
+                return Err(ParseError::constraint(
                         format!("Duplicate tag {}", key)));
             }
             Ok(())
@@ -44,7 +44,8 @@ impl ParadoxParse<Eu4Trait> for CountryMap {
     }
 }
 
-#[derive(ParadoxParse, Default)]
+#[derive(ParadoxParse, ParadoxWrite, Default)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct Country {
     pub color: crate::RgbColor,
     pub graphical_culture: String,
@@ -70,7 +71,8 @@ pub struct Country {
 #[derive(Default)]
 pub struct Area {}
 
-#[derive(ParadoxParse, Default)]
+#[derive(ParadoxParse, ParadoxWrite, Default)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct Region {
     #[optional] pub areas: Vec<Eu4Atom>,
     #[optional] pub monsoon: [(); 2]
@@ -85,7 +87,8 @@ pub struct Continent {}
 #[derive(Default)]
 pub struct Climate {}
 
-#[derive(ParadoxParse, Default)]
+#[derive(ParadoxParse, ParadoxWrite, Default)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct ClimateList {
     pub equator_y_on_province_image: u32,
 
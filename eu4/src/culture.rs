@@ -1,30 +1,36 @@
-use crate::{Country, Eu4Atom, Modifiers};
-use paradox::ParadoxParse;
+use crate::{Eu4Atom, Modifiers};
+use paradox::{ParadoxParse, ParadoxWrite};
 use std::collections::HashMap;
 
 pub type CultureGroupList = HashMap<Eu4Atom, CultureGroup>;
 
-#[derive(ParadoxParse, Default)]
-pub struct CultureGroup {
+/// The graphics and names that a culture can either set itself or take from
+/// its culture group.
+#[derive(ParadoxParse, ParadoxWrite, Default)]
+#[paradox(game = "crate::Eu4Trait")]
+pub struct CultureCommon {
     #[optional] pub graphical_culture: String,
     #[optional] pub second_graphical_culture: String,
     #[optional] pub dynasty_names: Vec<String>,
     #[optional] pub female_names: Vec<String>,
     #[optional] pub male_names: Vec<String>,
+}
+
+#[derive(ParadoxParse, ParadoxWrite, Default)]
+#[paradox(game = "crate::Eu4Trait")]
+pub struct CultureGroup {
+    #[paradox(flatten)] pub common: CultureCommon,
 
     #[collect]
     pub cultures: HashMap<Eu4Atom, Culture>
 }
 
-#[derive(ParadoxParse, Default)]
+#[derive(ParadoxParse, ParadoxWrite, Default)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct Culture {
-    #[optional] pub graphical_culture: String,
-    #[optional] pub second_graphical_culture: String,
-    #[optional] pub dynasty_names: Vec<String>,
-    #[optional] pub female_names: Vec<String>,
-    #[optional] pub male_names: Vec<String>,
+    #[paradox(flatten)] pub common: CultureCommon,
     #[optional] pub primary: Eu4Atom,
-    
+
     #[optional] pub country: Modifiers,
     #[optional] pub province: Modifiers,
 }
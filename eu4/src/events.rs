@@ -1,7 +1,8 @@
-use crate::Factor;
-use paradox::{ParadoxParse};
+use crate::{Condition, Factor};
+use paradox::{ParadoxParse, ParadoxWrite};
 
-#[derive(ParadoxParse, Default)]
+#[derive(ParadoxParse, ParadoxWrite, Default)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct CountryEvent {
     pub id: String,
     pub title: String,
@@ -14,10 +15,11 @@ pub struct CountryEvent {
 
     #[optional] pub mean_time_to_happen: MeanTimeToHappen,
 
-    pub trigger: (), // Vec<CountryCondition>,
+    pub trigger: Condition,
 }
 
-#[derive(ParadoxParse, Default)]
+#[derive(ParadoxParse, ParadoxWrite, Default)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct EventList {
     #[repeated]
     pub namespace: Vec<String>,
@@ -26,7 +28,8 @@ pub struct EventList {
     pub country_event: Vec<CountryEvent>
 }
 
-#[derive(ParadoxParse, Default)]
+#[derive(ParadoxParse, ParadoxWrite, Default)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct MeanTimeToHappen {
     #[optional] pub days: u32,
     #[optional] pub months: u32,
@@ -3,6 +3,7 @@ use paradox::{BoxedValue, TypeDefinition};
 use std::collections::HashMap;
 
 #[derive(paradox::GameData)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct GameData {
     pub base_info: paradox::GameData,
 
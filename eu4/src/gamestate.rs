@@ -1,13 +1,18 @@
-use crate::{Eu4Atom, GameData, Modifiers};
-use paradox::{Date, FixedPoint, ParadoxParse};
+use crate::{Eu4Atom, Eu4Value, GameData, Modifiers};
+use paradox::{Date, FixedPoint, ParadoxMerge, ParadoxParse, ParadoxSchema};
+use paradox::ParadoxWrite;
+use paradox::ParseError;
+use paradox::{SaveFile, TokenTables};
 use std::collections::HashMap;
+use std::path::PathBuf;
 
 //type CountryRef = IdRef<crate::Country>;
 type CountryRef = Eu4Atom;
 
-#[derive(ParadoxParse, Default)]
+#[derive(ParadoxParse, ParadoxSchema, ParadoxWrite, ParadoxMerge, Default)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct Gamestate {
-    #[optional] pub players_countries: (),
+    pub players_countries: Option<Vec<String>>,
     pub gameplaysettings: (),
     pub used_client_names: Vec<String>,
     pub speed: i32,
@@ -67,15 +72,25 @@ pub struct Gamestate {
     pub trade_company_manager: (),
     pub tech_level_dates: (), // it's a [(String, Date); 3]
     pub idea_dates: HashMap<Eu4Atom, Date>,
-    #[repeated] pub checksum: Vec<String>,
 
     // meta file
-    #[optional] pub date: Date,
+    #[paradox(flatten)] pub meta: SaveMeta,
+
+    // ai file
+    #[optional] pub ai: (),
+}
+
+/// The metadata of a save, from its `meta` file. This is enough to show a
+/// list of saves without loading them.
+#[derive(ParadoxParse, ParadoxSchema, ParadoxWrite, ParadoxMerge, Default)]
+#[paradox(game = "crate::Eu4Trait")]
+pub struct SaveMeta {
+    pub date: Date,
     #[optional] pub save_game: String,
-    #[optional] pub player: CountryRef,
+    pub player: CountryRef,
     #[optional] pub displayed_country_name: String,
     #[optional] pub savegame_version: (),
-    #[optional] pub savegame_versions: Vec<String>,
+    pub savegame_versions: Vec<String>,
     #[optional] pub dlc_enabled: Vec<String>,
     #[optional] pub is_ironman: bool,
     #[optional] pub multi_player: bool,
@@ -83,24 +98,39 @@ pub struct Gamestate {
     #[optional] pub campaign_id: String,
     #[optional] pub campaign_length: i32,
     #[optional] pub campaign_stats: (),
+    #[repeated] pub checksum: Vec<String>,
+}
 
-    // ai file
-    #[optional] pub ai: (),
+/// Get the directory where the game writes its saves.
+pub fn get_save_dir() -> PathBuf {
+    paradox::get_default_save_dir().join("Europa Universalis IV/save games")
+}
+
+/// Load the metadata of all the saves in the game's save directory, newest
+/// first.
+pub fn scan_save_games(game_data: &mut paradox::GameData,
+                       tables: Option<&TokenTables<crate::Eu4AtomStaticSet>>)
+        -> Result<Vec<SaveFile<SaveMeta>>, ParseError> {
+    paradox::scan_save_dir::<crate::Eu4Trait, _>(&get_save_dir(), "eu4",
+                                                 game_data, tables)
 }
 
-#[derive(ParadoxParse, Default)]
+#[derive(ParadoxParse, ParadoxSchema, ParadoxWrite, Default)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct Trade {
     #[repeated] pub node: Vec<TradeNode>,
 }
 
-#[derive(ParadoxParse, Default)]
+#[derive(ParadoxParse, ParadoxSchema, ParadoxWrite, Default)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct TradeIncoming {
     pub add: FixedPoint,
     pub value: FixedPoint,
     pub from: i32,
 }
 
-#[derive(ParadoxParse, Default)]
+#[derive(ParadoxParse, ParadoxSchema, ParadoxWrite, Default)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct TradeNode {
     pub definitions: Eu4Atom,
     #[optional] pub current: FixedPoint,
@@ -130,13 +160,35 @@ pub struct TradeNode {
     #[collect] pub country_info: Vec<(Eu4Atom, CountryTradeNode)>,
 }
 
-#[derive(ParadoxParse, Default)]
+#[allow(dead_code)] // Not yet used by CountryTradeNode.
+#[derive(ParadoxParse, ParadoxSchema, ParadoxWrite, Default)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct CountryTradeNodeModifier {
 }
 
-#[derive(ParadoxParse, Default)]
+/// What a country's merchant in a trade node is doing.
+#[derive(ParadoxParse, ParadoxSchema, ParadoxWrite, Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[paradox(game = "crate::Eu4Trait")]
+pub enum TradeNodeAction {
+    #[default]
+    #[paradox(value = "0")] Collect,
+    #[paradox(value = "1")] Steer,
+}
+
+/// The trade policy of a merchant. Policies added by mods are kept as `Other`.
+#[derive(ParadoxParse, ParadoxSchema, ParadoxWrite, Clone, Debug, Default, Eq, PartialEq)]
+#[paradox(game = "crate::Eu4Trait")]
+pub enum TradingPolicy {
+    #[default] MaximizeProfit,
+    TransferTradePower,
+    PropagateReligion,
+    Other(Eu4Atom),
+}
+
+#[derive(ParadoxParse, ParadoxSchema, ParadoxWrite, Default)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct CountryTradeNode {
-    #[optional] pub r#type: i32,
+    #[optional] #[paradox(rename = "type")] pub action: TradeNodeAction,
     #[optional] pub val: FixedPoint,
     #[optional] pub potential: FixedPoint,
     #[optional] pub prev: FixedPoint,
@@ -157,14 +209,15 @@ pub struct CountryTradeNode {
     #[optional] pub t_from: HashMap<CountryRef, FixedPoint>,
     #[optional] pub t_out: FixedPoint,
     #[optional] pub t_to: HashMap<CountryRef, FixedPoint>,
-    #[optional] pub trading_policy: Eu4Atom,
+    pub trading_policy: Option<TradingPolicy>,
     #[optional] pub trading_policy_date: Date,
     #[repeated] pub modifier: Vec<()>,
     #[optional] pub privateer_mission: FixedPoint,
     #[optional] pub privateer_money: FixedPoint,
 }
 
-#[derive(ParadoxParse, Default)]
+#[derive(ParadoxParse, ParadoxSchema, ParadoxWrite, Default)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct AppliedModifiers {
     pub modifier: Eu4Atom,
     pub date: Date,
@@ -173,13 +226,16 @@ pub struct AppliedModifiers {
     #[optional] pub parliament_modifier: bool,
 }
 
-#[derive(ParadoxParse, Default)]
+#[derive(ParadoxParse, ParadoxSchema, ParadoxWrite, Default)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct ActivePolicy {
     pub policy: Eu4Atom,
     pub date: Date,
 }
 
-#[derive(ParadoxParse, Default)]
+#[allow(dead_code)] // Not yet used by Gamestate.
+#[derive(ParadoxParse, ParadoxSchema, ParadoxWrite, Default)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct HreInfo {
     pub emperor: CountryRef,
     pub imperial_influence: FixedPoint,
@@ -197,7 +253,8 @@ pub struct HreInfo {
     #[repeated] pub previous_incident: Vec<()>,
 }
 
-#[derive(ParadoxParse, Default)]
+#[derive(ParadoxParse, ParadoxSchema, ParadoxWrite, Default)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct Country {
     #[optional] pub human: bool,
     #[optional] pub was_player: bool,
@@ -552,6 +609,9 @@ pub struct Country {
     #[optional] pub tariff: FixedPoint,
     #[repeated] pub ancestor: Vec<()>,
     #[optional] pub client_parent: Eu4Atom,
+
+    // Keys added by newer versions of the game.
+    #[extra] pub extra: Eu4Value,
 }
 
 impl Country {
@@ -640,11 +700,9 @@ impl Country {
         // More complex static modifiers
         for subject_tag in &self.subjects {
             let subject = &gamestate.countries[subject_tag];
-            if subject.colonial_parent == *tag {
-                if subject.num_of_cities >= 10 {
-                    apply_static!(large_colonial_nation);
-                }
-                // XXX: colony type modifiers...
+            // XXX: colony type modifiers...
+            if subject.colonial_parent == *tag && subject.num_of_cities >= 10 {
+                apply_static!(large_colonial_nation);
             }
         }
 
@@ -674,7 +732,7 @@ impl Country {
         // Government
         // XXX: government rank
         for reform in &self.government.reform_stack.reforms {
-            mods.add_modifiers(&data.government_reforms[&reform].modifiers);
+            mods.add_modifiers(&data.government_reforms[reform].modifiers);
         }
         // XXX: estates, factions, estate privileges
         // XXX: advisors
@@ -704,9 +762,21 @@ impl Country {
     }
 }
 
-#[derive(ParadoxParse, Default)]
+#[derive(ParadoxParse, ParadoxSchema, ParadoxWrite, Clone, Debug, Default, Eq, PartialEq)]
+#[paradox(game = "crate::Eu4Trait")]
+pub enum GovernmentType {
+    #[default] Monarchy,
+    Republic,
+    Theocracy,
+    Tribal,
+    Native,
+    Other(Eu4Atom),
+}
+
+#[derive(ParadoxParse, ParadoxSchema, ParadoxWrite, Default)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct CountryGovernment {
-    government: Eu4Atom,
+    government: GovernmentType,
     reform_stack: CountryReformStack,
     #[optional] cossacks_mechanic: (),
     #[optional] feudal_theocracy_mechanic: (),
@@ -716,13 +786,15 @@ pub struct CountryGovernment {
     #[optional] tribal_federation_mechanic: (),
 }
 
-#[derive(ParadoxParse, Default)]
+#[derive(ParadoxParse, ParadoxSchema, ParadoxWrite, Default)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct CountryReformStack {
     reforms: Vec<Eu4Atom>,
     #[optional] history: Vec<Eu4Atom>,
 }
 
-#[derive(ParadoxParse, Default)]
+#[derive(ParadoxParse, ParadoxSchema, ParadoxWrite, Default)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct Province {
     #[optional] pub flags: HashMap<Eu4Atom, Date>,
     pub name: String,
@@ -822,21 +894,228 @@ pub struct Province {
     #[optional] pub variables: (),
     #[optional] pub change_culture_construction: (),
     #[optional] pub centralize_state_construction: (),
+
+    // Keys added by newer versions of the game.
+    #[extra] pub extra: Eu4Value,
 }
 
-#[derive(ParadoxParse, Default)]
+#[derive(ParadoxParse, ParadoxSchema, ParadoxWrite, Default)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct TradeLeague {
     id: i32,
     members: Vec<CountryRef>
 }
 
-#[derive(ParadoxParse, Default)]
+#[derive(ParadoxParse, ParadoxSchema, ParadoxWrite, Default)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct Statistics {
     #[repeated] pub ledger_data: Vec<LedgerData>,
 }
 
-#[derive(ParadoxParse, Default)]
+#[derive(ParadoxParse, ParadoxSchema, ParadoxWrite, Default)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct LedgerData {
     pub name: CountryRef,
     #[optional] pub data: HashMap<Eu4Atom, i32>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use paradox::{Parser, TextLexer, TextWriter, Writer};
+    use std::path::Path;
+
+    fn round_trip<T>(input: &str) -> (T, String)
+        where T: ParadoxParse<crate::Eu4Trait> + ParadoxWrite<crate::Eu4Trait> + Default
+    {
+        let mut value = T::default();
+        let mut lexer = TextLexer::new(input.as_bytes(), "input".into());
+        let mut data = paradox::GameData::load(Path::new(".")).unwrap();
+        Parser::new(&mut lexer, &mut data).parse(&mut value).unwrap();
+
+        let mut output = Vec::new();
+        let mut emitter = TextWriter::new(&mut output);
+        Writer::new(&mut emitter).write(&value).unwrap();
+        (value, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn enums() {
+        let input = "type = 1\ntrading_policy = \"maximize_profit\"\n";
+        let (node, output) = round_trip::<CountryTradeNode>(input);
+        assert_eq!(node.action, TradeNodeAction::Steer);
+        assert_eq!(node.trading_policy, Some(TradingPolicy::MaximizeProfit));
+        assert_eq!(output, "type = 1\ntrading_policy = maximize_profit\n");
+
+        let (node, _) = round_trip::<CountryTradeNode>(
+            "trading_policy = modded_policy");
+        assert_eq!(node.action, TradeNodeAction::Collect);
+        assert_eq!(node.trading_policy,
+                   Some(TradingPolicy::Other("modded_policy".into())));
+
+        #[derive(ParadoxParse, ParadoxWrite, Default)]
+        #[paradox(game = "crate::Eu4Trait")]
+        struct Effects {
+            #[repeated] effect: Vec<Effect>,
+        }
+
+        #[derive(ParadoxParse, ParadoxWrite, Default, Debug, PartialEq)]
+        #[paradox(game = "crate::Eu4Trait")]
+        enum Effect {
+            #[default] None,
+            AddTreasury(FixedPoint),
+            #[paradox(value = "add_adm_power")] AddPower(i32),
+        }
+
+        let input = "effect = {\n\tadd_treasury = 1.500\n}\n\
+                     effect = {\n\tadd_adm_power = 50\n}\n";
+        let (effects, output) = round_trip::<Effects>(input);
+        assert_eq!(effects.effect, vec![
+            Effect::AddTreasury(FixedPoint::from(1.5)),
+            Effect::AddPower(50),
+        ]);
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn generic_game() {
+        use paradox::{GameTrait, ParserAtom};
+
+        #[derive(ParadoxParse, ParadoxWrite, ParadoxMerge)]
+        struct Named<G: GameTrait> {
+            name: ParserAtom<G>,
+            #[optional] value: i32,
+        }
+
+        impl <G: GameTrait> Default for Named<G> {
+            fn default() -> Self {
+                Self { name: Default::default(), value: 0 }
+            }
+        }
+
+        let input = "name = test\nvalue = 2\n";
+        let (named, output) = round_trip::<Named<crate::Eu4Trait>>(input);
+        assert_eq!(&*named.name, "test");
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn field_attributes() {
+        use paradox::{DiagnosticKind, ParseOptions};
+
+        #[derive(ParadoxParse, ParadoxWrite, Default)]
+        #[paradox(game = "crate::Eu4Trait")]
+        struct Inner {
+            id: i32,
+            #[paradox(alias = "old_name")] name: String,
+        }
+
+        #[derive(ParadoxParse, ParadoxWrite, Default)]
+        #[paradox(game = "crate::Eu4Trait")]
+        struct Outer {
+            #[paradox(rename = "type")] kind: Eu4Atom,
+            #[paradox(flatten)] inner: Inner,
+            #[paradox(default = 5)] size: i32,
+        }
+
+        let (outer, output) = round_trip::<Outer>("type = a id = 1 old_name = x");
+        assert_eq!(&*outer.kind, "a");
+        assert_eq!(outer.inner.name, "x");
+        assert_eq!(outer.size, 5);
+        assert_eq!(output, "type = a\nid = 1\nname = \"x\"\nsize = 5\n");
+
+        let parse = |input: &str, options| {
+            let mut outer = Outer::default();
+            let mut lexer = TextLexer::new(input.as_bytes(), "input".into());
+            let mut data = paradox::GameData::load(Path::new(".")).unwrap();
+            Parser::new(&mut lexer, &mut data)
+                .with_options(options)
+                .parse_with_diagnostics(&mut outer)
+                .map(|diagnostics| {
+                    let found: Vec<_> = diagnostics.iter()
+                        .map(|d| (d.kind, d.class.rsplit("::").next().unwrap()
+                                  .to_owned(), d.field.clone()))
+                        .collect();
+                    (outer, found)
+                })
+        };
+        let (_, found) = parse("type = a name = x size = 2 extra = 1",
+                               ParseOptions::lenient()).unwrap();
+        assert_eq!(found, [
+            (DiagnosticKind::UnknownField, "Outer".into(), "extra".into()),
+            (DiagnosticKind::MissingField, "Inner".into(), "id".into()),
+        ]);
+
+        // A repeated field is reported, and the last value is kept, unless
+        // validation is strict.
+        let input = "type = a id = 1 name = x id = 2";
+        let (outer, found) = parse(input, ParseOptions::lenient()).unwrap();
+        assert_eq!(outer.inner.id, 2);
+        assert_eq!(found, [
+            (DiagnosticKind::DuplicateField, "Inner".into(), "id".into()),
+        ]);
+        let err = parse(input, ParseOptions::strict()).err().unwrap();
+        assert!(err.to_string().contains("Inner/id"), "{}", err);
+    }
+
+    #[test]
+    fn extra_fields() {
+        #[derive(ParadoxParse, ParadoxWrite, ParadoxMerge, Default)]
+        #[paradox(game = "crate::Eu4Trait")]
+        struct Versioned {
+            name: String,
+            #[extra] extra: Eu4Value,
+        }
+
+        let input = "name = \"a\"\nadded = {\n\tx = 1\n\ty = { 1 2 }\n}\n\
+                     level > 3\nadded = yes\n";
+        let mut value = Versioned::default();
+        let mut lexer = TextLexer::new(input.as_bytes(), "input".into());
+        let mut data = paradox::GameData::load(Path::new(".")).unwrap();
+        let diagnostics = Parser::new(&mut lexer, &mut data)
+            .with_options(paradox::ParseOptions::strict())
+            .parse_with_diagnostics(&mut value).unwrap();
+        assert!(diagnostics.is_empty());
+        assert_eq!(value.name, "a");
+        let keys: Vec<_> = value.extra.entries()
+            .map(|entry| &*entry.key).collect();
+        assert_eq!(keys, ["added", "level", "added"]);
+
+        let (_, output) = round_trip::<Versioned>(input);
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn schema() {
+        use paradox::{Cardinality, ParadoxSchema, Schema};
+
+        let node = match CountryTradeNode::schema() {
+            Schema::Struct(schema) => schema,
+            _ => panic!("expected a struct schema"),
+        };
+        assert_eq!(node.name, "CountryTradeNode");
+        let action = node.field("type").unwrap();
+        assert_eq!(action.name, "action");
+        assert_eq!(action.cardinality, Cardinality::Optional);
+        assert!(node.field("action").is_none());
+        match action.schema() {
+            Schema::Enum(schema) => {
+                assert_eq!(schema.values, ["0", "1"]);
+                assert!(!schema.has_other);
+            },
+            _ => panic!("expected an enum schema"),
+        }
+
+        // Fields of the flattened SaveMeta are found through Gamestate.
+        let gamestate = Gamestate::schema();
+        let date = gamestate.child("date").unwrap();
+        assert!(matches!(date, Schema::Scalar("Date")));
+        let versions = gamestate.child("savegame_versions").unwrap();
+        assert!(matches!(versions, Schema::List(_)));
+        assert!(gamestate.child("no_such_key").is_none());
+
+        // Unknown keys of a country are kept by its #[extra] field.
+        let country = Country::schema();
+        assert!(matches!(country.child("no_such_key"), Some(Schema::Any)));
+    }
+}
@@ -4,6 +4,9 @@ type RgbColor = Vec<u32>; // XXX: Lies
 
 include!(concat!(env!("OUT_DIR"), "/eu4_binary.rs"));
 
+/// A generic document tree for EU4 files.
+pub type Eu4Value = paradox::Value<Eu4AtomStaticSet>;
+
 mod advisors;
 mod conditions;
 mod country;
@@ -24,6 +27,7 @@ pub use events::*;
 pub use misc::*;
 pub use modifiers::*;
 pub use game::*;
-pub use gamestate::Gamestate;
+pub use gamestate::{get_save_dir, scan_save_games, Gamestate, SaveMeta};
+pub use gamestate::{GovernmentType, TradeNodeAction, TradingPolicy};
 pub use religion::*;
 pub use trade::*;
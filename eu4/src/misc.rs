@@ -1,11 +1,12 @@
-use crate::{Eu4Atom, Modifiers, Weight};
-use paradox::ParadoxParse;
+use crate::{Condition, Eu4Atom, Modifiers, Weight};
+use paradox::{ParadoxParse, ParadoxWrite};
 
-#[derive(ParadoxParse, Default)]
+#[derive(ParadoxParse, ParadoxWrite, Default)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct IdeaGroup {
     #[optional] pub start: Modifiers,
     pub bonus: Modifiers,
-    #[optional] pub trigger: (),
+    #[optional] pub trigger: Condition,
     #[optional] pub free: bool,
     #[optional] pub ai_will_do: Weight,
     #[optional] pub important: bool,
@@ -27,24 +28,36 @@ impl IdeaGroup {
     }
 }
 
-#[derive(ParadoxParse, Default)]
+/// The three kinds of monarch points.
+#[derive(ParadoxParse, ParadoxWrite, Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[paradox(game = "crate::Eu4Trait")]
+pub enum MonarchPower {
+    #[default]
+    #[paradox(value = "ADM")] Adm,
+    #[paradox(value = "DIP")] Dip,
+    #[paradox(value = "MIL")] Mil,
+}
+
+#[derive(ParadoxParse, ParadoxWrite, Default)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct Policy {
-    monarch_power: Eu4Atom,
-    potential: (), // Condition
-    allow: (), // Condition
+    pub monarch_power: MonarchPower,
+    potential: Condition,
+    allow: Condition,
     ai_will_do: Weight,
 
     #[modifiers] pub modifiers: Modifiers,
 }
 
-#[derive(ParadoxParse, Default)]
+#[derive(ParadoxParse, ParadoxWrite, Default)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct GovernmentReform {
     #[optional] icon: String,
     #[optional] pub modifiers: Modifiers,
     #[optional] ai: (),
-    #[optional] potential: (),
+    #[optional] potential: Condition,
     #[optional] conditional: (),
-    #[optional] trigger: (),
+    #[optional] trigger: Condition,
 
     #[optional] allow_normal_conversion: bool,
     #[optional] allow_convert: bool,
@@ -58,7 +71,7 @@ pub struct GovernmentReform {
 
     #[optional] valid_for_nation_designer: bool,
     #[optional] nation_designer_cost: i32,
-    #[optional] nation_designer_trigger: (),
+    #[optional] nation_designer_trigger: Condition,
     #[optional] custom_attributes: (),
 
     #[optional] assimilation_cultures: (),
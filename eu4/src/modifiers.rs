@@ -1,5 +1,6 @@
 use crate::{Eu4Atom, Eu4Trait};
-use paradox::{FixedPoint, ParadoxParse, Parser, ParseError};
+use paradox::{FixedPoint, ParadoxParse, ParadoxSchema, ParadoxWrite, Parser};
+use paradox::{ParseError, Schema, Token, Writer};
 
 /*paradox::modifier_list! {
     modifier(Country, army_tradition, FixedPoint);
@@ -453,7 +454,8 @@ use paradox::{FixedPoint, ParadoxParse, Parser, ParseError};
     modifier(Province, state_governing_cost_increase, i32);
 }*/
 
-#[derive(ParadoxParse, Default)]
+#[derive(ParadoxParse, ParadoxWrite, Default)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct EventModifier {
     #[optional] pub picture: String,
     #[optional] pub expire_message_type: String,
@@ -529,7 +531,7 @@ pub struct Modifiers {
 impl Modifiers {
     pub fn read_field(&mut self, key: Eu4Atom,
                       parser: &mut Parser<Eu4Trait>) -> Result<(), ParseError> {
-        let value = parser.get_token()?.ok_or(ParseError::Eof)?;
+        let value = parser.get_token()?.ok_or_else(ParseError::eof)?;
         let str_value = value.try_to_string()?;
         let value = if str_value == "yes" {
             ModifierValue::Bool(true)
@@ -538,13 +540,30 @@ impl Modifiers {
         } else {
             ModifierValue::FixedPoint(
                 str_value.parse()
-                    .map_err(|e| ParseError::Conversion(Box::new(e)))?
+                    .map_err(|e| ParseError::from(
+                        Box::new(e) as Box<dyn std::error::Error + Send + Sync>))?
             )
         };
         self[&key] = value;
         Ok(())
     }
 
+    /// Write out each modifier as a `key = value` entry. This is the inverse of
+    /// `read_field`.
+    pub fn write_fields(&self, writer: &mut Writer<Eu4Trait>)
+            -> Result<(), ParseError> {
+        for (key, value) in &self.modifiers {
+            writer.write_atom(key)?;
+            writer.write_token(Token::Eq)?;
+            writer.write_token(match *value {
+                ModifierValue::Bool(b) => Token::Bool(b),
+                ModifierValue::Integer(i) => Token::Integer(i),
+                ModifierValue::FixedPoint(f) => Token::Fixed(f),
+            })?;
+        }
+        Ok(())
+    }
+
     pub fn add_modifiers(&mut self, other: &Modifiers) -> &mut Self {
         for (key, value) in &other.modifiers {
             let our_mod = &mut self[key];
@@ -587,8 +606,25 @@ impl core::ops::IndexMut<&'_ Eu4Atom> for Modifiers {
 
 impl ParadoxParse<Eu4Trait> for Modifiers {
     fn read(&mut self, parser: &mut Parser<Eu4Trait>) -> Result<(), ParseError> {
-        parser.parse_key_scope(|key, parser| {
+        parser.parse_key_scope(|key, _, parser| {
             self.read_field(key, parser)
         })
     }
 }
+
+/// Modifiers are a map from the modifier's name to a number or a boolean.
+impl ParadoxSchema for Modifiers {
+    fn schema() -> Schema {
+        Schema::Map(Box::new(Schema::Scalar("ModifierValue")))
+    }
+}
+
+impl ParadoxWrite<Eu4Trait> for Modifiers {
+    fn write(&self, writer: &mut Writer<Eu4Trait>) -> Result<(), ParseError> {
+        writer.write_key_scope(|writer| self.write_fields(writer))
+    }
+
+    fn is_default(&self) -> bool {
+        self.modifiers.is_empty()
+    }
+}
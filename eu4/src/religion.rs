@@ -1,11 +1,12 @@
-use crate::{Eu4Atom, LocalizationKey, ProvinceRef, RgbColor};
+use crate::{Condition, Eu4Atom, LocalizationKey, ProvinceRef, RgbColor};
 use crate::modifiers::Modifiers;
-use paradox::{Date, ParadoxParse};
+use paradox::{Date, ParadoxParse, ParadoxWrite};
 use std::collections::HashMap;
 
 pub type ReligionList = HashMap<Eu4Atom, ReligiousGroup>;
 
-#[derive(ParadoxParse, Default, Debug)]
+#[derive(ParadoxParse, ParadoxWrite, Default, Debug)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct ReligiousGroup {
     // Inherited properties
     #[optional]
@@ -32,7 +33,8 @@ pub struct ReligiousGroup {
     pub religions: HashMap<Eu4Atom, Religion>
 }
 
-#[derive(ParadoxParse, Default, Debug)]
+#[derive(ParadoxParse, ParadoxWrite, Default, Debug)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct Religion {
     pub color: RgbColor,
     pub icon: i32, // XXX: icon reference?
@@ -88,11 +90,12 @@ pub struct Religion {
     #[optional] pub uses_piety: bool,
 }
 
-#[derive(ParadoxParse, Default, Debug)]
+#[derive(ParadoxParse, ParadoxWrite, Default, Debug)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct ReligiousSchool {
-    pub can_invite_scholar: (),
+    pub can_invite_scholar: Condition,
     pub on_invite_scholar: (), // Vec<CountryEffect>,
-    pub potential_invite_scholar: (),
+    pub potential_invite_scholar: Condition,
     pub invite_scholar_modifier_display: String, // XXX: EventModifier
     pub picture: String, // XXX: gfx reference or something?
 
@@ -1,13 +1,15 @@
 use crate::{
+    Condition,
     Eu4Atom,
     Eu4Trait,
     Modifiers,
     ProvinceRef,
     RgbColor
 };
-use paradox::{FixedPoint, ParadoxParse, ParseError, Parser};
+use paradox::{FixedPoint, ParadoxParse, ParadoxWrite, ParseError, Parser, Writer};
 
-#[derive(ParadoxParse, Default, Debug)]
+#[derive(ParadoxParse, ParadoxWrite, Default, Debug)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct TradeNode {
     location: ProvinceRef,
     #[optional]
@@ -23,14 +25,16 @@ pub struct TradeNode {
     pub outgoing: Vec<TradeEdge>,
 }
 
-#[derive(ParadoxParse, Default, Debug)]
+#[derive(ParadoxParse, ParadoxWrite, Default, Debug)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct TradeEdge {
     pub name: Eu4Atom, // XXX: tradenode ref
     path: Vec<ProvinceRef>,
     control: Vec<f64>
 }
 
-#[derive(ParadoxParse, Default, Debug)]
+#[derive(ParadoxParse, ParadoxWrite, Default, Debug)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct TradeGood {
     color: [paradox::FixedPoint; 3],
     #[optional] modifier: Modifiers,
@@ -38,14 +42,15 @@ pub struct TradeGood {
     #[optional] is_latent: bool,
     #[optional] is_valuable: bool,
     #[optional] rnw_latent_chance: u32,
-    #[optional] trigger: (),
+    #[optional] trigger: Condition,
     #[optional] chance: (),
 }
 
-#[derive(ParadoxParse, Default)]
+#[derive(ParadoxParse, ParadoxWrite, Default)]
+#[paradox(game = "crate::Eu4Trait")]
 pub struct TradePolicy {
-    #[optional] can_select: (),
-    #[optional] can_maintain: (),
+    #[optional] can_select: Condition,
+    #[optional] can_maintain: Condition,
     button_gfx: String,
     #[optional] center_of_reformation: bool,
     #[optional] unique: bool,
@@ -68,7 +73,7 @@ struct ConfusingThing {
 
 impl ParadoxParse<Eu4Trait> for ConfusingThing {
     fn read(&mut self, parser: &mut Parser<Eu4Trait>) -> Result<(), ParseError> {
-        parser.parse_key_scope(|key, parser| {
+        parser.parse_key_scope(|key, _, parser| {
             match key {
                 eu4_atom!("power_modifier") => self.modifier.read(parser),
                 _ => ().read(parser),
@@ -76,3 +81,39 @@ impl ParadoxParse<Eu4Trait> for ConfusingThing {
         })
     }
 }
+
+impl ParadoxWrite<Eu4Trait> for ConfusingThing {
+    fn write(&self, writer: &mut Writer<Eu4Trait>) -> Result<(), ParseError> {
+        writer.write_key_scope(|writer| {
+            writer.write_field("power_modifier", &self.modifier)
+        })
+    }
+
+    fn is_default(&self) -> bool {
+        self.modifier == FixedPoint::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use paradox::{TextWriter, TextLexer};
+    use std::path::Path;
+
+    #[test]
+    fn write_trade_node() {
+        let input = "location = 1\nmembers = { 1 2 3 }\n\
+            outgoing = {\n\tname = a\n\tpath = { 1 2 }\n\tcontrol = { 0.50000 }\n}\n\
+            outgoing = {\n\tname = b\n\tpath = { }\n\tcontrol = { }\n}\n";
+        let mut node = TradeNode::default();
+        let mut lexer = TextLexer::new(input.as_bytes(), "input".into());
+        let mut data = paradox::GameData::load(Path::new(".")).unwrap();
+        Parser::new(&mut lexer, &mut data).parse(&mut node).unwrap();
+        assert_eq!(node.outgoing.len(), 2);
+
+        let mut output = Vec::new();
+        let mut emitter = TextWriter::new(&mut output);
+        Writer::new(&mut emitter).write(&node).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), input);
+    }
+}
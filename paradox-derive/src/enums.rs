@@ -0,0 +1,228 @@
+//! Deriving the traits for enums.
+//!
+//! Unit variants are read from a single value, which is the variant name in
+//! snake case unless it is given with `#[paradox(value = "...")]`. Integers
+//! are matched by their text, so `#[paradox(value = "1")]` matches `1`.
+//!
+//! Newtype variants are read from a block with a single `key = value` entry,
+//! where the key (chosen the same way) picks the variant. A newtype variant
+//! named `Other` instead catches any value that no unit variant matches.
+
+use crate::{game_trait, paradox_args, Error};
+use proc_macro2::{Ident, TokenStream};
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+use syn::{DataEnum, DeriveInput, Fields, Type, Variant};
+
+pub(crate) enum VariantKind<'a> {
+    Unit(String),
+    Newtype(String, &'a Type),
+    Other,
+}
+
+pub(crate) struct EnumVariant<'a> {
+    pub ident: &'a Ident,
+    pub kind: VariantKind<'a>,
+    pub is_default: bool,
+}
+
+fn snake_case(name: &str) -> String {
+    let mut result = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            result.push('_');
+        }
+        result.extend(c.to_lowercase());
+    }
+    result
+}
+
+fn handle_variant(variant: &Variant) -> Result<EnumVariant<'_>, Error> {
+    let ident = &variant.ident;
+    let mut value = snake_case(&ident.to_string());
+    for arg in paradox_args(&variant.attrs)? {
+        match arg.name.to_string().as_str() {
+            "value" => value = arg.lit_str()?.value(),
+            _ => return Err(arg.unknown())
+        }
+    }
+    let kind = match &variant.fields {
+        Fields::Unit => VariantKind::Unit(value),
+        Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+            if ident == "Other" {
+                VariantKind::Other
+            } else {
+                VariantKind::Newtype(value, &fields.unnamed[0].ty)
+            }
+        },
+        _ => return Err(Error::new(variant.span(),
+                                   "Only unit and newtype variants are supported"))
+    };
+    let is_default = variant.attrs.iter()
+        .any(|attr| attr.path.is_ident("default"));
+    Ok(EnumVariant { ident, kind, is_default })
+}
+
+pub(crate) fn handle_variants(data: &DataEnum) -> Result<Vec<EnumVariant<'_>>, Error> {
+    data.variants.iter().map(handle_variant).collect()
+}
+
+pub fn implement_parse_method(input: &DeriveInput, data: &DataEnum)
+        -> Result<TokenStream, Error> {
+    let game = game_trait(input)?;
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) =
+        input.generics.split_for_impl();
+    let variants = handle_variants(data)?;
+
+    let mut values = Vec::new();
+    let mut keys = Vec::new();
+    let mut fallback = quote! {
+        parser.validation_error(paradox::DiagnosticKind::InvalidValue,
+                                class_name, &value, "unknown value", false,
+                                None)?
+    };
+    for variant in &variants {
+        let ident = variant.ident;
+        match &variant.kind {
+            VariantKind::Unit(value) => values.push(quote_spanned!{ident.span() =>
+                #value => *self = Self::#ident
+            }),
+            VariantKind::Newtype(key, ty) => keys.push(quote_spanned!{ident.span() =>
+                #key => {
+                    let mut value: #ty = Default::default();
+                    paradox::ParadoxParse::<#game>::read(&mut value, parser)?;
+                    *self = Self::#ident(value);
+                }
+            }),
+            VariantKind::Other => fallback = quote_spanned!{ident.span() =>
+                *self = Self::#ident(From::from(&*value))
+            },
+        }
+    }
+
+    // Blocks are only valid if there are newtype variants to read them into.
+    let read_block = if keys.is_empty() {
+        quote! {
+            parser.validation_error(paradox::DiagnosticKind::InvalidValue,
+                                    class_name, "value",
+                                    "expected a value, found a block", false,
+                                    Some(token))?;
+        }
+    } else {
+        quote! {
+            parser.unget(token)?;
+            let mut seen = false;
+            parser.parse_key_scope(|key, op, parser| {
+                if seen {
+                    parser.validation_error(
+                        paradox::DiagnosticKind::DuplicateField, class_name,
+                        &key, "multiple definitions found", false, None)?;
+                }
+                seen = true;
+                if op != paradox::Operator::Eq {
+                    parser.validation_error(
+                        paradox::DiagnosticKind::InvalidValue, class_name,
+                        &key, &format!("unexpected operator {}", op),
+                        false, None)?;
+                }
+                match &*key {
+                    #( #keys, )*
+                    _ => {
+                        let token = parser.get_token()?;
+                        parser.validation_error(
+                            paradox::DiagnosticKind::UnknownField, class_name,
+                            &key, "unknown variant", false, token)?;
+                    }
+                }
+                Ok(())
+            })?;
+        }
+    };
+
+    Ok(quote! {
+        #[automatically_derived]
+        impl #impl_generics paradox::ParadoxParse<#game> for #name #ty_generics
+                #where_clause {
+            fn read(&mut self, parser: &mut paradox::Parser<#game>)
+                    -> Result<(), paradox::ParseError> {
+                let class_name = std::any::type_name::<Self>();
+                let token = parser.get_token()?
+                    .ok_or_else(paradox::ParseError::eof)?;
+                let value = match token {
+                    paradox::Token::LBrace => {
+                        #read_block
+                        return Ok(());
+                    },
+                    token => <paradox::ParserAtom<#game> as
+                        std::convert::TryFrom<_>>::try_from(token)?,
+                };
+                match &*value {
+                    #( #values, )*
+                    _ => #fallback
+                }
+                Ok(())
+            }
+        }
+    })
+}
+
+pub fn implement_write_method(input: &DeriveInput, data: &DataEnum)
+        -> Result<TokenStream, Error> {
+    let game = game_trait(input)?;
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) =
+        input.generics.split_for_impl();
+    let variants = handle_variants(data)?;
+
+    let arms = variants.iter().map(|variant| {
+        let ident = variant.ident;
+        match &variant.kind {
+            VariantKind::Unit(value) => match value.parse::<i32>() {
+                Ok(int) => quote! {
+                    Self::#ident => writer.write_token(paradox::Token::Integer(#int))
+                },
+                Err(_) => quote! {
+                    Self::#ident => writer.write_atom(#value)
+                },
+            },
+            VariantKind::Newtype(key, _) => quote! {
+                Self::#ident(value) => writer.write_key_scope(|writer| {
+                    writer.write_field(#key, value)
+                })
+            },
+            VariantKind::Other => quote! {
+                Self::#ident(value) =>
+                    paradox::ParadoxWrite::<#game>::write(value, writer)
+            },
+        }
+    });
+
+    // Only a unit variant can be the default, so that is what is checked.
+    let is_default = variants.iter()
+        .find(|variant| variant.is_default)
+        .filter(|variant| matches!(variant.kind, VariantKind::Unit(_)))
+        .map(|variant| {
+            let ident = variant.ident;
+            quote! {
+                fn is_default(&self) -> bool {
+                    matches!(self, Self::#ident)
+                }
+            }
+        });
+
+    Ok(quote! {
+        #[automatically_derived]
+        impl #impl_generics paradox::ParadoxWrite<#game> for #name #ty_generics
+                #where_clause {
+            fn write(&self, writer: &mut paradox::Writer<#game>)
+                    -> Result<(), paradox::ParseError> {
+                match self {
+                    #( #arms, )*
+                }
+            }
+
+            #is_default
+        }
+    })
+}
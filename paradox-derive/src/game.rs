@@ -4,7 +4,7 @@ use quote::ToTokens;
 use syn::spanned::Spanned;
 use syn::{Data, DeriveInput, Field, Lit, Meta, Type};
 
-use crate::{Error};
+use crate::{game_trait, Error};
 
 fn get_tag(field: &Field, tag: &'static str) -> Option<Lit> {
     field.attrs.iter()
@@ -20,6 +20,7 @@ fn get_tag(field: &Field, tag: &'static str) -> Option<Lit> {
 }
 
 pub(crate) fn implement_game(input: &DeriveInput) -> Result<TokenStream, Error> {
+    let game = game_trait(input)?;
     let name = &input.ident;
     let fields = match &input.data {
         Data::Struct(data) => &data.fields,
@@ -31,7 +32,7 @@ pub(crate) fn implement_game(input: &DeriveInput) -> Result<TokenStream, Error>
 
     for field in fields {
         let ty_str = match &field.ty {
-            &Type::Path(ref p) => p.path.clone().into_token_stream().to_string(),
+            Type::Path(p) => p.path.clone().into_token_stream().to_string(),
             _ => "".into()
         };
 
@@ -43,7 +44,7 @@ pub(crate) fn implement_game(input: &DeriveInput) -> Result<TokenStream, Error>
             continue;
         }
 
-        if let Some(path) = get_tag(&field, "parse") {
+        if let Some(path) = get_tag(field, "parse") {
             let field_data = (&field.ident, path);
             eager_fields.push(field_data);
         } else {
@@ -61,14 +62,13 @@ pub(crate) fn implement_game(input: &DeriveInput) -> Result<TokenStream, Error>
     let new_fields_parse = eager_fields.iter()
         .map(|(name, path)| quote! {
             result.#base_field
-                .parse_directory::<crate::Eu4Trait>(#path, &mut result.#name)?;
+                .parse_directory::<#game>(#path, &mut result.#name)?;
         });
     let new_method = quote_spanned! { input.span() =>
         pub fn new(game_dir: &std::path::Path
                    ) -> Result<Self, paradox::ParseError> {
-            let base_info = paradox::GameData::load(game_dir)?;
             let mut result = Self {
-                base_info,
+                #base_field: paradox::GameData::load(game_dir)?,
                 #( #new_fields_init ),*
             };
             #( #new_fields_parse )*
@@ -84,6 +84,6 @@ pub(crate) fn implement_game(input: &DeriveInput) -> Result<TokenStream, Error>
         }
     };
 
-    Ok(TokenStream::from(expanded))
+    Ok(expanded)
 }
 
@@ -1,14 +1,20 @@
 extern crate proc_macro;
 
+mod enums;
 mod game;
+mod merge;
+mod schema;
 mod scopes;
+mod write;
 
 use proc_macro2::{Ident, Span, TokenStream};
-use quote::{format_ident, quote, quote_spanned};
+use quote::{quote, quote_spanned};
 use quote::ToTokens;
 use syn::parse::{Parse, ParseStream};
 use syn::spanned::Spanned;
-use syn::{parse_macro_input, Data, DeriveInput, Field, Token, Type};
+use syn::punctuated::Punctuated;
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Expr, ExprLit, Field};
+use syn::{Lit, LitStr, Token, Type, TypeParamBound};
 
 #[derive(Debug)]
 struct Error(TokenStream);
@@ -25,11 +31,18 @@ impl From<syn::Error> for Error {
     }
 }
 
-struct FieldHandler<'a> {
-    name: &'a Ident,
-    body: TokenStream,
-    check_name: Option<Ident>,
-    is_default: bool
+/// The code to read a single field, split by where it goes in the derived
+/// `ParadoxParseFields` impl.
+struct FieldHandler {
+    /// The arm matching the keys of the field.
+    arm: Option<TokenStream>,
+    /// The code to try keys that no arm matched. Flattened structs get the
+    /// first go at these, and then a `#[collect]`, `#[modifiers]` or `#[extra]`
+    /// field takes whatever is left.
+    flattened: Option<TokenStream>,
+    catch_all: Option<TokenStream>,
+    /// The code run at the end of the block, for fields that weren't seen.
+    finish: Option<TokenStream>,
 }
 
 fn stringify(ident: &Ident) -> String {
@@ -43,169 +56,354 @@ fn has_tag(field: &Field, tag: &'static str) -> bool {
         .any(|attr| attr.path.is_ident(tag))
 }
 
-fn handle_field<'a>(field: &'a Field) -> FieldHandler<'a> {
+/// An argument of a `#[paradox(...)]` attribute, either a flag like `flatten`
+/// or a setting like `value = "..."`.
+struct ParadoxArg {
+    name: Ident,
+    value: Option<Expr>
+}
+
+impl Parse for ParadoxArg {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = input.parse()?;
+        let value = if input.peek(Token![=]) {
+            input.parse::<Token![=]>()?;
+            Some(input.parse()?)
+        } else {
+            None
+        };
+        Ok(Self { name, value })
+    }
+}
+
+impl ParadoxArg {
+    fn lit_str(&self) -> Result<LitStr, Error> {
+        match &self.value {
+            Some(Expr::Lit(ExprLit { lit: Lit::Str(value), .. })) =>
+                Ok(value.clone()),
+            _ => Err(Error::new(self.name.span(), "expected a string"))
+        }
+    }
+
+    fn unknown(&self) -> Error {
+        Error::new(self.name.span(), "unknown paradox argument")
+    }
+}
+
+fn paradox_args(attrs: &[Attribute]) -> Result<Vec<ParadoxArg>, Error> {
+    let mut args = Vec::new();
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("paradox")) {
+        type ArgList = Punctuated<ParadoxArg, Token![,]>;
+        args.extend(attr.parse_args_with(ArgList::parse_terminated)?);
+    }
+    Ok(args)
+}
+
+/// The settings of a field from its `#[paradox(...)]` attributes.
+#[derive(Default)]
+struct FieldArgs {
+    /// The key of the field, if it isn't the field's name.
+    rename: Option<LitStr>,
+    /// Other keys that are read into the field.
+    aliases: Vec<LitStr>,
+    /// The value of the field if it isn't in the input.
+    default: Option<TokenStream>,
+    /// Whether the fields of this field are read as part of the outer block.
+    flatten: bool,
+}
+
+fn field_args(field: &Field) -> Result<FieldArgs, Error> {
+    let mut args = FieldArgs::default();
+    for arg in paradox_args(&field.attrs)? {
+        match (arg.name.to_string().as_str(), &arg.value) {
+            ("rename", _) => args.rename = Some(arg.lit_str()?),
+            ("alias", _) => args.aliases.push(arg.lit_str()?),
+            ("default", Some(value)) =>
+                args.default = Some(value.into_token_stream()),
+            ("default", None) =>
+                args.default = Some(quote! { Default::default() }),
+            ("flatten", None) => args.flatten = true,
+            _ => return Err(arg.unknown())
+        }
+    }
+    Ok(args)
+}
+
+/// The key of a field in the game files.
+fn field_key(field: &Field, args: &FieldArgs) -> String {
+    match &args.rename {
+        Some(rename) => rename.value(),
+        None => stringify(field.ident.as_ref().expect("unnamed field?"))
+    }
+}
+
+/// The game that the traits are derived for. This is either given with
+/// `#[paradox(game = "crate::Eu4Trait")]` on the type, or is a type parameter
+/// bounded by `GameTrait`, in which case the traits are derived for every game.
+fn game_trait(input: &DeriveInput) -> Result<TokenStream, Error> {
+    let mut game = None;
+    for arg in paradox_args(&input.attrs)? {
+        match arg.name.to_string().as_str() {
+            "game" => game = Some(arg.lit_str()?.parse::<syn::Path>()?),
+            _ => return Err(arg.unknown())
+        }
+    }
+    if let Some(game) = game {
+        return Ok(game.into_token_stream());
+    }
+
+    let param = input.generics.type_params().find(|param| {
+        param.bounds.iter().any(|bound| match bound {
+            TypeParamBound::Trait(bound) => bound.path.segments.last()
+                .is_some_and(|segment| segment.ident == "GameTrait"),
+            _ => false
+        })
+    });
+    match param {
+        Some(param) => Ok(param.ident.to_token_stream()),
+        None => Err(Error::new(input.span(),
+            "Missing #[paradox(game = \"...\")] or GameTrait type parameter"))
+    }
+}
+
+/// Whether a field may be left out of the input: either it is tagged as
+/// optional, or it is an `Option`.
+fn is_optional(field: &Field) -> bool {
+    let is_option = match &field.ty {
+        Type::Path(p) => p.path.segments.last()
+            .is_some_and(|segment| segment.ident == "Option"),
+        _ => false
+    };
+    is_option || has_tag(field, "optional")
+}
+
+fn handle_field(field: &Field, game: &TokenStream)
+        -> Result<FieldHandler, Error> {
     let name = &field.ident.as_ref().expect("unnamed field?");
-    let stringy_name = stringify(name);
+    let args = field_args(field)?;
+    let key = field_key(field, &args);
+    let mut handler = FieldHandler {
+        arm: None, flattened: None, catch_all: None, finish: None
+    };
 
     // Get the type as a string. This isn't fully accurate, but it's good enough
     // for any checks we need to do.
     let ty = match &field.ty {
-        &Type::Path(ref p) => p.path.clone().into_token_stream().to_string(),
+        Type::Path(p) => p.path.clone().into_token_stream().to_string(),
         _ => "".into()
     };
 
-    // This type of field sets the default body instead.
+    if args.flatten {
+        handler.flattened = Some(quote_spanned!{field.span() =>
+            if paradox::ParadoxParseFields::<#game>::read_field(
+                    &mut self.#name, key, op, parser, seen)? {
+                return Ok(true);
+            }
+        });
+        handler.finish = Some(quote_spanned!{field.span() =>
+            paradox::ParadoxParseFields::<#game>::finish_fields(
+                &mut self.#name, parser, seen)?;
+        });
+        return Ok(handler);
+    }
+
+    // This type of field reads the keys that no other field matches.
     if has_tag(field, "collect") {
-        let make_key = quote_spanned!{field.ty.span() => (*key).into() };
-        let body = if ty.contains("HashMap") {
+        let make_key = quote_spanned!{field.ty.span() => (**key).into() };
+        handler.catch_all = Some(if ty.contains("HashMap") {
             quote_spanned!{ field.span() =>
-                key => {
-                    use std::collections::hash_map::Entry;
-                    let entry = self.#name.entry(#make_key);
-                    match entry {
-                        Entry::Occupied(ref e) =>
-                            parser.validation_error(class_name, &format!("{:?}", e.key()),
-                                "multiple definitions found", false, None)?,
-                        _ => ()
-                    }
-                    entry.or_default().read(parser)?;
-                },
+                use std::collections::hash_map::Entry;
+                let entry = self.#name.entry(#make_key);
+                match entry {
+                    Entry::Occupied(ref e) =>
+                        parser.validation_error(
+                            paradox::DiagnosticKind::DuplicateField,
+                            class_name, &format!("{:?}", e.key()),
+                            "multiple definitions found", false, None)?,
+                    _ => ()
+                }
+                entry.or_default().read(parser)?;
             }
         } else {
             quote_spanned!{ field.span() =>
-                key => {
-                    self.#name.push((#make_key, Default::default()));
-                    self.#name.last_mut().unwrap()
-                        .1.read(parser)?;
-                }
+                self.#name.push((#make_key, Default::default()));
+                self.#name.last_mut().unwrap()
+                    .1.read(parser)?;
             }
-        };
-        return FieldHandler { name, body, check_name: None, is_default: true };
+        });
+        return Ok(handler);
+    }
+
+    // This type of field keeps the keys that no other field matches, with
+    // their operators and values, in a type like `Value`.
+    if has_tag(field, "extra") {
+        handler.catch_all = Some(quote_spanned!{ field.span() =>
+            return paradox::ParadoxParseFields::<#game>::read_field(
+                &mut self.#name, key, op, parser, seen);
+        });
+        return Ok(handler);
     }
 
-    // This type of field sets the default body instead.
+    // This type of field reads the keys that no other field matches.
     if has_tag(field, "modifiers") {
-        let body = quote_spanned!{ field.span() =>
-            key => {
-                self.#name.read_field(key, parser)?;
-            },
-        };
-        return FieldHandler { name, body, check_name: None, is_default: true };
+        handler.catch_all = Some(quote_spanned!{ field.span() =>
+            self.#name.read_field(key.clone(), parser)?;
+        });
+        return Ok(handler);
     }
 
-    // Handle optional and repeated conditions: we build a list of boolean
-    // checks, one for each field, to let us know how many times we've seen
-    // them before.
-    let check_name = if has_tag(field, "optional") || has_tag(field, "repeated") {
-        None
+    // Required fields are checked for duplicates and for being missing, and
+    // fields with a default need to know if they were missing. Which fields
+    // have been seen is shared with any structs we are flattened into.
+    let is_required = !is_optional(field) && !has_tag(field, "repeated") &&
+        args.default.is_none();
+    let check_presence = if is_required {
+        Some(quote_spanned!{field.span() =>
+            if !seen.insert(class_name, #key) {
+                parser.validation_error(paradox::DiagnosticKind::DuplicateField,
+                    class_name, #key, "multiple definitions found",
+                    false, None)?;
+            }
+        })
     } else {
-        Some(format_ident!("seen_{}", name))
-    };
-    let field_match = quote_spanned!{field.span() =>
-        eu4_atom!(#stringy_name)
+        // Other fields are only marked as seen, for merging the pieces of
+        // partial input.
+        Some(quote_spanned!{field.span() =>
+            seen.insert(class_name, #key);
+        })
     };
-    let check_presence = if let Some(ref check_name) = check_name {
+    handler.finish = if let Some(default) = &args.default {
         Some(quote_spanned!{field.span() =>
-            if #check_name {
-                parser.validation_error(class_name, #stringy_name,
-                    "multiple definitions found", true, None)?;
+            if !seen.contains(class_name, #key) && parser.check_missing_fields() {
+                self.#name = #default;
+            }
+        })
+    } else if is_required {
+        Some(quote_spanned!{field.span() =>
+            if !seen.contains(class_name, #key) && parser.check_missing_fields() {
+                parser.validation_error(
+                    paradox::DiagnosticKind::MissingField, class_name,
+                    #key, "not found in definition", false, None)?;
             }
-            #check_name = true;
         })
     } else {
         None
     };
 
     // Build the body of the match.
-    let body = {
-        // Regular types. Build up a check here.
-        let parsee = quote_spanned!{field.span() =>
-            let parsee : &mut dyn paradox::ParadoxParse<crate::Eu4Trait>
-        };
-        let get_parsee = if has_tag(field, "repeated") {
-            quote_spanned!{field.span() =>
-                self.#name.push(Default::default());
-                #parsee = self.#name.last_mut().unwrap();
-            }
-        } else {
-            quote_spanned!{field.span() =>
-                #parsee = &mut self.#name;
-            }
-        };
+    let parsee = quote_spanned!{field.span() =>
+        let parsee : &mut dyn paradox::ParadoxParse<#game>
+    };
+    let get_parsee = if has_tag(field, "repeated") {
         quote_spanned!{field.span() =>
-            #field_match => {
-                #check_presence
-                #get_parsee
-                parsee.read(parser)?;
-            }
+            self.#name.push(Default::default());
+            #parsee = self.#name.last_mut().unwrap();
+        }
+    } else {
+        quote_spanned!{field.span() =>
+            #parsee = &mut self.#name;
         }
     };
-
-    FieldHandler { body, check_name, name, is_default: false }
+    let aliases = &args.aliases;
+    handler.arm = Some(quote_spanned!{field.span() =>
+        #key #( | #aliases )* => {
+            #check_presence
+            if op != paradox::Operator::Eq {
+                parser.validation_error(
+                    paradox::DiagnosticKind::InvalidValue, class_name,
+                    #key, &format!("unexpected operator {}", op),
+                    false, None)?;
+            }
+            #get_parsee
+            parsee.read(parser)?;
+        }
+    });
+    Ok(handler)
 }
 
 fn implement_parse_method(input: &DeriveInput) -> Result<TokenStream, Error> {
-    let trait_name = quote! { crate::Eu4Trait };
+    let game = game_trait(input)?;
     let name = &input.ident;
-    let body : Vec<_> = match &input.data {
-        Data::Struct(data) => data.fields.iter().map(handle_field).collect(),
-        _ => return Err(Error::new(input.span(),
-                                   "Can only derive ParadoxParse for structs"))
+    let (impl_generics, ty_generics, where_clause) =
+        input.generics.split_for_impl();
+    let fields = match &input.data {
+        Data::Struct(data) => data.fields.iter()
+            .map(|field| handle_field(field, &game))
+            .collect::<Result<Vec<_>, _>>()?,
+        Data::Enum(data) => return enums::implement_parse_method(input, data),
+        _ => return Err(Error::new(
+                input.span(), "Can only derive ParadoxParse for structs and enums"))
     };
 
-    let mut default_body = quote! {
-        key => {
-            let token = parser.get_token()?;
-            parser.validation_error(class_name, &key, "unknown in struct",
-                                    false, token)?;
-        },
-    };
-    let mut match_statements = Vec::new();
-    let mut prologue = Vec::new();
-    let mut epilogue = Vec::new();
-    for field in body {
-        if field.is_default {
-            default_body = field.body;
-            continue;
-        }
-        let field_name = field.name;
-        let stringy_name = stringify(&field_name);
-        match_statements.push(field.body);
-        if let Some(check_name) = &field.check_name {
-            prologue.push(quote! { let mut #check_name = false; });
-            epilogue.push(quote! {
-                if !#check_name {
-                    parser.validation_error(class_name, #stringy_name,
-                        "not found in definition", false, None)?;
-                }
-            });
-        }
+    let arms = fields.iter().filter_map(|field| field.arm.as_ref());
+    let flattened = fields.iter().filter_map(|field| field.flattened.as_ref());
+    let finish = fields.iter().filter_map(|field| field.finish.as_ref());
+    let mut catch_alls = fields.iter()
+        .filter_map(|field| field.catch_all.as_ref());
+    let catch_all = catch_alls.next().cloned()
+        .unwrap_or_else(|| quote! { return Ok(false); });
+    if catch_alls.next().is_some() {
+        return Err(Error::new(input.span(),
+            "Only one of #[collect], #[modifiers] and #[extra] can be used"));
     }
 
     let expanded = quote! {
         #[automatically_derived]
-        impl paradox::ParadoxParse<#trait_name> for #name {
-            fn read(&mut self, parser: &mut paradox::Parser<#trait_name>)
+        impl #impl_generics paradox::ParadoxParseFields<#game> for #name #ty_generics
+                #where_clause {
+            #[allow(unused_variables, unreachable_code)]
+            fn read_field(&mut self, key: &paradox::ParserAtom<#game>,
+                          op: paradox::Operator,
+                          parser: &mut paradox::Parser<#game>,
+                          seen: &mut paradox::SeenFields)
+                    -> Result<bool, paradox::ParseError> {
+                let class_name = std::any::type_name::<Self>();
+                match &**key {
+                    #( #arms, )*
+                    _ => {
+                        #( #flattened )*
+                        #catch_all
+                    }
+                }
+                Ok(true)
+            }
+
+            #[allow(unused_variables)]
+            fn finish_fields(&mut self, parser: &mut paradox::Parser<#game>,
+                             seen: &paradox::SeenFields)
                     -> Result<(), paradox::ParseError> {
                 let class_name = std::any::type_name::<Self>();
-                #( #prologue )*
-                parser.parse_key_scope(|key, parser| {
-                    match key {
-                        #( #match_statements, )*
-                        #default_body
+                #( #finish )*
+                Ok(())
+            }
+        }
+
+        #[automatically_derived]
+        impl #impl_generics paradox::ParadoxParse<#game> for #name #ty_generics
+                #where_clause {
+            fn read(&mut self, parser: &mut paradox::Parser<#game>)
+                    -> Result<(), paradox::ParseError> {
+                let class_name = std::any::type_name::<Self>();
+                let mut seen = paradox::SeenFields::default();
+                parser.parse_key_scope(|key, op, parser| {
+                    if !paradox::ParadoxParseFields::<#game>::read_field(
+                            self, &key, op, parser, &mut seen)? {
+                        let token = parser.get_token()?;
+                        parser.validation_error(
+                            paradox::DiagnosticKind::UnknownField, class_name,
+                            &key, "unknown in struct", false, token)?;
                     }
                     Ok(())
                 })?;
-                #( #epilogue )*
-                Ok(())
+                parser.finish_fields(self, seen)
             }
         }
     };
 
-    Ok(TokenStream::from(expanded))
+    Ok(expanded)
 }
 
-#[proc_macro_derive(ParadoxParse, attributes(collect, modifiers, optional, repeated))]
+#[proc_macro_derive(ParadoxParse, attributes(collect, extra, modifiers, optional, paradox, repeated))]
 pub fn derive_paradox_parse(input: proc_macro::TokenStream)
         -> proc_macro::TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
@@ -214,7 +412,34 @@ pub fn derive_paradox_parse(input: proc_macro::TokenStream)
         .into()
 }
 
-#[proc_macro_derive(GameData, attributes(parse))]
+#[proc_macro_derive(ParadoxWrite, attributes(collect, extra, modifiers, optional, paradox, repeated))]
+pub fn derive_paradox_write(input: proc_macro::TokenStream)
+        -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    write::implement_write_method(&input)
+        .unwrap_or_else(|err| err.0)
+        .into()
+}
+
+#[proc_macro_derive(ParadoxMerge, attributes(collect, extra, modifiers, optional, paradox, repeated))]
+pub fn derive_paradox_merge(input: proc_macro::TokenStream)
+        -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    merge::implement_merge_method(&input)
+        .unwrap_or_else(|err| err.0)
+        .into()
+}
+
+#[proc_macro_derive(ParadoxSchema, attributes(collect, extra, modifiers, optional, paradox, repeated))]
+pub fn derive_paradox_schema(input: proc_macro::TokenStream)
+        -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    schema::implement_schema(&input)
+        .unwrap_or_else(|err| err.0)
+        .into()
+}
+
+#[proc_macro_derive(GameData, attributes(paradox, parse))]
 pub fn derive_game_data(input: proc_macro::TokenStream)
         -> proc_macro::TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
@@ -251,8 +476,8 @@ impl Parse for Name {
 impl Name {
     fn name(&self) -> &Ident {
         match self {
-            Self::Fixed(n) => &n,
-            Self::Dynamic(n, _) => &n
+            Self::Fixed(n) => n,
+            Self::Dynamic(n, _) => n
         }
     }
 }
@@ -0,0 +1,93 @@
+use crate::{field_args, field_key, game_trait, has_tag, Error};
+use proc_macro2::TokenStream;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+use syn::{Data, DeriveInput, Field};
+
+/// Build the code that merges a single field, for `merge` and then for
+/// `merge_seen`. Whether a plain field was set is decided by
+/// `ParadoxWrite::is_default` in `merge`, so the type must derive that too,
+/// and by whether the field was in the input in `merge_seen`.
+fn merge_field(field: &Field, game: &TokenStream)
+        -> Result<(TokenStream, TokenStream), Error> {
+    let name = field.ident.as_ref().expect("unnamed field?");
+    let args = field_args(field)?;
+
+    if has_tag(field, "collect") || has_tag(field, "repeated") {
+        let merge = quote_spanned!{field.span() =>
+            self.#name.extend(other.#name);
+        };
+        return Ok((merge.clone(), merge));
+    }
+
+    if has_tag(field, "modifiers") || has_tag(field, "extra") {
+        let merge = quote_spanned!{field.span() =>
+            paradox::ParadoxMerge::merge(&mut self.#name, other.#name);
+        };
+        return Ok((merge.clone(), merge));
+    }
+
+    // The fields of a flattened struct are in the same block, so they share
+    // the seen fields.
+    if args.flatten {
+        return Ok((quote_spanned!{field.span() =>
+            paradox::ParadoxMerge::merge(&mut self.#name, other.#name);
+        }, quote_spanned!{field.span() =>
+            paradox::ParadoxMerge::merge_seen(&mut self.#name, other.#name,
+                                              seen);
+        }));
+    }
+
+    let key = field_key(field, &args);
+    Ok((quote_spanned!{field.span() =>
+        if !paradox::ParadoxWrite::<#game>::is_default(&other.#name) {
+            self.#name = other.#name;
+        }
+    }, quote_spanned!{field.span() =>
+        if seen.contains(class_name, #key) {
+            self.#name = other.#name;
+        }
+    }))
+}
+
+pub fn implement_merge_method(input: &DeriveInput) -> Result<TokenStream, Error> {
+    let game = game_trait(input)?;
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) =
+        input.generics.split_for_impl();
+    let fields : Vec<_> = match &input.data {
+        Data::Struct(data) => data.fields.iter()
+            .map(|field| merge_field(field, &game))
+            .collect::<Result<_, _>>()?,
+        Data::Enum(_) => return Ok(quote! {
+            #[automatically_derived]
+            impl #impl_generics paradox::ParadoxMerge for #name #ty_generics
+                    #where_clause {
+                fn merge(&mut self, other: Self) {
+                    if !paradox::ParadoxWrite::<#game>::is_default(&other) {
+                        *self = other;
+                    }
+                }
+            }
+        }),
+        _ => return Err(Error::new(
+                input.span(), "Can only derive ParadoxMerge for structs and enums"))
+    };
+    let (merge, merge_seen): (Vec<_>, Vec<_>) = fields.into_iter().unzip();
+
+    Ok(quote! {
+        #[automatically_derived]
+        impl #impl_generics paradox::ParadoxMerge for #name #ty_generics
+                #where_clause {
+            fn merge(&mut self, other: Self) {
+                #( #merge )*
+            }
+
+            #[allow(unused_variables)]
+            fn merge_seen(&mut self, other: Self, seen: &paradox::SeenFields) {
+                let class_name = std::any::type_name::<Self>();
+                #( #merge_seen )*
+            }
+        }
+    })
+}
@@ -0,0 +1,112 @@
+use crate::enums::{handle_variants, VariantKind};
+use crate::{field_args, field_key, has_tag, is_optional, stringify, Error};
+use proc_macro2::TokenStream;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+use syn::{Data, DeriveInput, Field};
+
+/// Build the description of a single field. This mirrors the handling of each
+/// kind of field in `handle_field`.
+fn field_schema(field: &Field) -> Result<TokenStream, Error> {
+    let name = stringify(field.ident.as_ref().expect("unnamed field?"));
+    let args = field_args(field)?;
+    let ty = &field.ty;
+
+    let is_catch_all = has_tag(field, "collect") || has_tag(field, "modifiers") ||
+        has_tag(field, "extra");
+    let cardinality = if is_catch_all {
+        quote! { CatchAll }
+    } else if args.flatten {
+        quote! { Flattened }
+    } else if has_tag(field, "repeated") {
+        quote! { Repeated }
+    } else if is_optional(field) || args.default.is_some() {
+        quote! { Optional }
+    } else {
+        quote! { Required }
+    };
+    let key = if is_catch_all || args.flatten {
+        String::new()
+    } else {
+        field_key(field, &args)
+    };
+    let aliases = &args.aliases;
+    let has_default = args.default.is_some();
+
+    Ok(quote_spanned!{field.span() =>
+        paradox::FieldSchema {
+            name: #name,
+            key: #key,
+            aliases: vec![#( #aliases ),*],
+            ty: stringify!(#ty),
+            cardinality: paradox::Cardinality::#cardinality,
+            has_default: #has_default,
+            schema: <#ty as paradox::ParadoxSchema>::schema,
+        }
+    })
+}
+
+pub fn implement_schema(input: &DeriveInput) -> Result<TokenStream, Error> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) =
+        input.generics.split_for_impl();
+    let schema = match &input.data {
+        Data::Struct(data) => {
+            let fields = data.fields.iter()
+                .map(field_schema)
+                .collect::<Result<Vec<_>, _>>()?;
+            quote! {
+                paradox::Schema::Struct(paradox::StructSchema {
+                    name: stringify!(#name),
+                    fields: vec![#( #fields ),*],
+                })
+            }
+        },
+        Data::Enum(data) => {
+            let variants = handle_variants(data)?;
+            let values = variants.iter().filter_map(|variant| match &variant.kind {
+                VariantKind::Unit(value) => Some(value),
+                _ => None
+            });
+            let keyed = variants.iter().filter_map(|variant| match &variant.kind {
+                VariantKind::Newtype(key, ty) => {
+                    let name = variant.ident.to_string();
+                    Some(quote! {
+                        paradox::FieldSchema {
+                            name: #name,
+                            key: #key,
+                            aliases: Vec::new(),
+                            ty: stringify!(#ty),
+                            cardinality: paradox::Cardinality::Optional,
+                            has_default: false,
+                            schema: <#ty as paradox::ParadoxSchema>::schema,
+                        }
+                    })
+                },
+                _ => None
+            });
+            let has_other = variants.iter()
+                .any(|variant| matches!(variant.kind, VariantKind::Other));
+            quote! {
+                paradox::Schema::Enum(paradox::EnumSchema {
+                    name: stringify!(#name),
+                    values: vec![#( #values ),*],
+                    variants: vec![#( #keyed ),*],
+                    has_other: #has_other,
+                })
+            }
+        },
+        _ => return Err(Error::new(
+                input.span(), "Can only derive ParadoxSchema for structs and enums"))
+    };
+
+    Ok(quote! {
+        #[automatically_derived]
+        impl #impl_generics paradox::ParadoxSchema for #name #ty_generics
+                #where_clause {
+            fn schema() -> paradox::Schema {
+                #schema
+            }
+        }
+    })
+}
@@ -12,6 +12,8 @@ mod kw {
     syn::custom_punctuation!(All, *);
 }
 
+// The parsed tokens are kept, like the fields of Scope, even if unused.
+#[allow(dead_code)]
 enum ScopeType {
     All(kw::All),
     Specific(Ident)
@@ -27,6 +29,7 @@ impl Parse for ScopeType {
     }
 }
 
+#[allow(dead_code)]
 enum ScopeKw {
     Scope(kw::scope),
     ScopeMany(kw::scope_many)
@@ -73,7 +76,7 @@ impl Scope {
         let params = match (&self.scope_kind, &self.name) {
             (ScopeKw::Scope(_), Name::Fixed(_)) => TokenStream::new(),
             (ScopeKw::Scope(_), Name::Dynamic(_, ty)) =>
-                quote! { (paradox::IdRef<crate::#ty>) },
+                quote! { (paradox::IdRef<#ty>) },
             (ScopeKw::ScopeMany(_), _) => quote! { (bool) }
         };
         quote_spanned!{name.span() => #name #params }
@@ -89,7 +92,7 @@ impl Scope {
             },
             (ScopeKw::Scope(_), Name::Dynamic(_, ty)) => {
                 quote_spanned!{name.span() =>
-                    if let Some(val) = paradox::IdRef::<crate::#ty>::from_str(key, data) {
+                    if let Some(val) = paradox::IdRef::<#ty>::from_str(key, data) {
                         Some(Self::#name(val))
                     } else
                 }
@@ -139,8 +142,9 @@ impl ScopeList {
     fn generate_parse(&self) -> TokenStream {
         let match_stmt = self.scopes.iter().map(Scope::parse_stmt);
         quote! {
-            pub fn get_scope(parser: &mut paradox::Parser,
-                             key: &str) -> Option<Self> {
+            pub fn get_scope<G: paradox::GameTrait>(
+                    parser: &mut paradox::Parser<G>, key: &str)
+                    -> Option<Self> {
                 let data = parser.get_game_data();
                 match key {
                     "FROM" => Some(Self::From),
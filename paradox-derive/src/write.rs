@@ -0,0 +1,112 @@
+use crate::{field_args, field_key, game_trait, has_tag, is_optional, Error};
+use proc_macro2::TokenStream;
+use quote::{quote, quote_spanned, ToTokens};
+use syn::spanned::Spanned;
+use syn::{Data, DeriveInput, Field, Type};
+
+/// Build the code that writes out a single field. This mirrors the handling of
+/// each kind of field in `handle_field`.
+fn write_field(field: &Field, game: &TokenStream)
+        -> Result<TokenStream, Error> {
+    let name = field.ident.as_ref().expect("unnamed field?");
+    let args = field_args(field)?;
+    let key = field_key(field, &args);
+    let ty = match &field.ty {
+        Type::Path(p) => p.path.clone().into_token_stream().to_string(),
+        _ => "".into()
+    };
+
+    if args.flatten || has_tag(field, "extra") {
+        return Ok(quote_spanned!{field.span() =>
+            paradox::ParadoxWriteFields::<#game>::write_fields(&self.#name,
+                                                               writer)?;
+        });
+    }
+
+    if has_tag(field, "collect") {
+        // Write maps in a stable order, but keep lists in their given order.
+        if !ty.contains("HashMap") {
+            return Ok(quote_spanned!{field.span() =>
+                for (key, value) in &self.#name {
+                    writer.write_field(&format!("{}", key), value)?;
+                }
+            });
+        }
+        return Ok(quote_spanned!{field.span() =>
+            let mut entries: Vec<_> = self.#name.iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            for (key, value) in entries {
+                writer.write_field(&key, value)?;
+            }
+        });
+    }
+
+    if has_tag(field, "modifiers") {
+        return Ok(quote_spanned!{field.span() =>
+            self.#name.write_fields(writer)?;
+        });
+    }
+
+    if has_tag(field, "repeated") {
+        return Ok(quote_spanned!{field.span() =>
+            for value in &self.#name {
+                writer.write_field(#key, value)?;
+            }
+        });
+    }
+
+    let write = quote_spanned!{field.span() =>
+        writer.write_field(#key, &self.#name)?;
+    };
+    Ok(if is_optional(field) {
+        quote_spanned!{field.span() =>
+            if !paradox::ParadoxWrite::<#game>::is_default(&self.#name) {
+                #write
+            }
+        }
+    } else {
+        write
+    })
+}
+
+pub fn implement_write_method(input: &DeriveInput) -> Result<TokenStream, Error> {
+    let game = game_trait(input)?;
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) =
+        input.generics.split_for_impl();
+    let fields : Vec<_> = match &input.data {
+        Data::Struct(data) => data.fields.iter()
+            .map(|field| write_field(field, &game))
+            .collect::<Result<_, _>>()?,
+        Data::Enum(data) =>
+            return crate::enums::implement_write_method(input, data),
+        _ => return Err(Error::new(
+                input.span(), "Can only derive ParadoxWrite for structs and enums"))
+    };
+
+    Ok(quote! {
+        #[automatically_derived]
+        impl #impl_generics paradox::ParadoxWriteFields<#game> for #name #ty_generics
+                #where_clause {
+            fn write_fields(&self, writer: &mut paradox::Writer<#game>)
+                    -> Result<(), paradox::ParseError> {
+                #( #fields )*
+                Ok(())
+            }
+        }
+
+        #[automatically_derived]
+        impl #impl_generics paradox::ParadoxWrite<#game> for #name #ty_generics
+                #where_clause {
+            fn write(&self, writer: &mut paradox::Writer<#game>)
+                    -> Result<(), paradox::ParseError> {
+                writer.write_key_scope(|writer| {
+                    paradox::ParadoxWriteFields::<#game>::write_fields(self,
+                                                                       writer)
+                })
+            }
+        }
+    })
+}
@@ -0,0 +1,112 @@
+//! Compare the throughput of the stream-based lexers against the lexers that
+//! work on an in-memory buffer.
+//!
+//! Run with `cargo bench -p paradox --bench lexer`.
+
+use paradox::*;
+use std::time::{Duration, Instant};
+use string_cache::{Atom, EmptyStaticAtomSet};
+
+struct BenchGame;
+impl GameTrait for BenchGame {
+    type Static = EmptyStaticAtomSet;
+    fn get_binary_token(_: u16) -> Option<Atom<Self::Static>> { None }
+}
+
+const ENTRIES: usize = 200_000;
+
+/// Build a text file that looks vaguely like a gamestate file.
+fn make_text() -> Vec<u8> {
+    let mut data = Vec::new();
+    for i in 0..ENTRIES {
+        data.extend_from_slice(format!(
+            "province_{} = {{\n\tname=\"Province {}\"\n\towner=FRA\n\t\
+             base_tax=3.000\n\thistory={{ 1444.11.11={{ owner=FRA }} }}\n\t\
+             buildings={{ {} {} {} }}\n}}\n", i, i, i, i + 1, i + 2)
+            .as_bytes());
+    }
+    data
+}
+
+/// Build a binary file with a similar shape to the text file.
+fn make_binary() -> Vec<u8> {
+    fn string(data: &mut Vec<u8>, s: &str) {
+        data.extend_from_slice(&0x000fu16.to_le_bytes());
+        data.extend_from_slice(&(s.len() as u16).to_le_bytes());
+        data.extend_from_slice(s.as_bytes());
+    }
+    fn code(data: &mut Vec<u8>, code: u16) {
+        data.extend_from_slice(&code.to_le_bytes());
+    }
+    fn int(data: &mut Vec<u8>, val: i32) {
+        code(data, 0x000c);
+        data.extend_from_slice(&val.to_le_bytes());
+    }
+    let mut data = Vec::new();
+    for i in 0..ENTRIES {
+        string(&mut data, &format!("province_{}", i));
+        code(&mut data, 0x0001);
+        code(&mut data, 0x0003);
+        string(&mut data, "name");
+        code(&mut data, 0x0001);
+        string(&mut data, &format!("Province {}", i));
+        string(&mut data, "owner");
+        code(&mut data, 0x0001);
+        string(&mut data, "FRA");
+        string(&mut data, "base_tax");
+        code(&mut data, 0x0001);
+        code(&mut data, 0x000d);
+        data.extend_from_slice(&3000i32.to_le_bytes());
+        string(&mut data, "buildings");
+        code(&mut data, 0x0001);
+        code(&mut data, 0x0003);
+        for j in 0..3 {
+            int(&mut data, (i + j) as i32);
+        }
+        code(&mut data, 0x0004);
+        code(&mut data, 0x0004);
+    }
+    data
+}
+
+fn count_tokens<'a>(lexer: &mut dyn Lexer<'a, BenchGame>) -> usize {
+    let mut count = 0;
+    while lexer.get_token().expect("lexing failed").is_some() {
+        count += 1;
+    }
+    count
+}
+
+fn run<F: FnMut() -> usize>(name: &str, bytes: usize, mut func: F) {
+    const ITERATIONS: u32 = 5;
+    let mut best = Duration::MAX;
+    let mut tokens = 0;
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        tokens = func();
+        best = best.min(start.elapsed());
+    }
+    let mb_per_sec = bytes as f64 / best.as_secs_f64() / 1_000_000.0;
+    println!("{:<24} {:>10} tokens {:>10.2?} {:>8.1} MB/s",
+             name, tokens, best, mb_per_sec);
+}
+
+fn main() {
+    let text = make_text();
+    run("TextLexer", text.len(), || {
+        count_tokens(&mut TextLexer::new(&text[..], "bench".into()))
+    });
+    run("TextSliceLexer", text.len(), || {
+        count_tokens(&mut TextSliceLexer::new(&text, "bench".into()))
+    });
+
+    let binary = make_binary();
+    run("BinaryLexer", binary.len(), || {
+        count_tokens(&mut BinaryLexer::<BenchGame, _>::new(&binary[..],
+                                                           "bench".into()))
+    });
+    run("BinarySliceLexer", binary.len(), || {
+        count_tokens(&mut BinarySliceLexer::<BenchGame>::new(&binary,
+                                                             "bench".into()))
+    });
+}
@@ -1,3 +1,5 @@
+use std::convert::TryFrom;
+
 /// A date, in Paradox terms, consists of a 4-digit year. The calendar doesn't
 /// follow any strict modern scheme, as leap years do not exist. The date after
 /// Feburary 28th is always March 1, unlike in the Julian or Gregorian
@@ -68,10 +70,10 @@ impl std::str::FromStr for Date {
         if pieces.next().is_some() {
             return Err(Self::Err { msg: "Too many date components" });
         }
-        if year < 1 || year > 9999 {
+        if !(1..=9999).contains(&year) {
             return Err(Self::Err { msg: "Year out of range" });
         }
-        if month < 1 || month > 12 {
+        if !(1..=12).contains(&month) {
             return Err(Self::Err { msg: "Month out of range" });
         }
         if day < 1 || day > MONTH_DAYS[(month - 1) as usize] {
@@ -81,6 +83,30 @@ impl std::str::FromStr for Date {
     }
 }
 
+impl Date {
+    /// Convert a date from its binary representation, which is also used by
+    /// typed literals in text. Returns `None` if the number is not a date.
+    pub fn from_binary(value: i32) -> Option<Date> {
+        let value = u32::try_from(value).ok()?;
+        let last = Date { year: 9999, month: 12, day: 31 };
+        if value % 24 != 0 || value < encode_date(Date::default()) ||
+                value > encode_date(last) {
+            return None;
+        }
+        Some(convert_date(value))
+    }
+
+    /// Convert a date to its binary representation.
+    pub fn to_binary(self) -> i32 {
+        encode_date(self) as i32
+    }
+
+    /// Format the date like `1444-11-11`.
+    pub fn to_iso_string(self) -> String {
+        format!("{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
 // XXX: Date parsing as an integer:
 // dates are stored as a 0x000c tag. the integer representation is hours since
 // -5000, 1, 1. So conversion
@@ -95,8 +121,8 @@ pub(crate) fn convert_date(mut val: u32) -> Date {
     let mut day_in_year = extract_mod(&mut val, 365);
     let year = val as i32 - 5000;
     let (mut month, mut day) = (0, 0);
-    for i in 0..12 {
-        let days_in_month = MONTH_DAYS[i] as u32;
+    for (i, &days_in_month) in MONTH_DAYS.iter().enumerate() {
+        let days_in_month = days_in_month as u32;
         if day_in_year < days_in_month {
             month = i + 1;
             day = day_in_year + 1;
@@ -108,6 +134,14 @@ pub(crate) fn convert_date(mut val: u32) -> Date {
     Date { year: year as i16, month: month as u8, day: day as u8 }
 }
 
+/// The inverse of `convert_date`.
+pub(crate) fn encode_date(date: Date) -> u32 {
+    let day_in_year : u32 = MONTH_DAYS[..(date.month - 1) as usize].iter()
+        .map(|&days| days as u32)
+        .sum::<u32>() + date.day as u32 - 1;
+    ((date.year as u32 + 5000) * 365 + day_in_year) * 24
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -127,5 +161,14 @@ mod tests {
     fn check_int_convert() {
         assert_eq!(convert_date(0x29c77f8), Date { year: 1, month: 1, day: 1 });
         assert_eq!(convert_date(0x35d7710), Date { year: 1444, month: 11, day: 11 });
+        assert_eq!(encode_date(Date { year: 1, month: 1, day: 1 }), 0x29c77f8);
+        assert_eq!(encode_date(Date { year: 1444, month: 11, day: 11 }), 0x35d7710);
+        assert_eq!(Date::from_binary(0x35d7710),
+                   Some(Date { year: 1444, month: 11, day: 11 }));
+        assert_eq!(Date::from_binary(0x35d7711), None);
+        assert_eq!(Date::from_binary(24), None);
+        assert_eq!(Date::from_binary(-24), None);
+        assert_eq!(Date { year: 1444, month: 11, day: 1 }.to_iso_string(),
+                   "1444-11-01");
     }
 }
@@ -0,0 +1,126 @@
+use crate::ErrorLocation;
+use std::fmt;
+
+/// How the parser handles input that doesn't match the types being parsed,
+/// like unknown or missing fields.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ValidationMode {
+    /// Invalid input is an error.
+    Strict,
+    /// Invalid input is skipped and recorded as a diagnostic, which the caller
+    /// can report as it likes.
+    #[default]
+    Lenient,
+    /// Invalid input is skipped without being recorded, for callers that only
+    /// want what could be read.
+    Silent,
+}
+
+/// Options that control the parser.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ParseOptions {
+    pub mode: ValidationMode,
+}
+
+impl ParseOptions {
+    pub fn strict() -> Self {
+        Self { mode: ValidationMode::Strict }
+    }
+
+    pub fn lenient() -> Self {
+        Self { mode: ValidationMode::Lenient }
+    }
+
+    pub fn silent() -> Self {
+        Self { mode: ValidationMode::Silent }
+    }
+}
+
+/// The kind of problem found in the input.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum DiagnosticKind {
+    /// A key that the type being parsed doesn't have a field for.
+    UnknownField,
+    /// A required field that wasn't in the input.
+    MissingField,
+    /// A field that was defined more than once.
+    DuplicateField,
+    /// A value that the type being parsed can't hold, like a list that is too
+    /// long or an unexpected operator.
+    InvalidValue,
+    /// A file in a directory of game files that was skipped, because it isn't
+    /// a `.txt` file.
+    UnexpectedFile,
+}
+
+/// A problem found in the input that the parser recovered from.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    /// The name of the type being parsed.
+    pub class: &'static str,
+    pub field: String,
+    pub message: String,
+    pub location: ErrorLocation,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}: {}{}", self.class, self.field, self.message,
+               self.location)
+    }
+}
+
+/// The diagnostics collected while parsing, in the order they were found.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Diagnostics {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// Add all the diagnostics from `other`, such as those from parsing
+    /// another file.
+    pub fn extend(&mut self, other: Diagnostics) {
+        self.diagnostics.extend(other.diagnostics);
+    }
+
+    pub fn len(&self) -> usize {
+        self.diagnostics.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Diagnostic> {
+        self.diagnostics.iter()
+    }
+
+    /// Get the diagnostics of a single kind.
+    pub fn of_kind(&self, kind: DiagnosticKind)
+            -> impl Iterator<Item = &Diagnostic> {
+        self.iter().filter(move |diagnostic| diagnostic.kind == kind)
+    }
+}
+
+impl <'a> IntoIterator for &'a Diagnostics {
+    type Item = &'a Diagnostic;
+    type IntoIter = std::slice::Iter<'a, Diagnostic>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl IntoIterator for Diagnostics {
+    type Item = Diagnostic;
+    type IntoIter = std::vec::IntoIter<Diagnostic>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.diagnostics.into_iter()
+    }
+}
@@ -0,0 +1,159 @@
+use std::borrow::Cow;
+
+/// The character encodings used in the files of Paradox games.
+///
+/// Older games (such as EU4) write their saves and game data in Windows-1252,
+/// while localisation files and the files of newer games use UTF-8.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum Encoding {
+    #[default]
+    Windows1252,
+    Utf8
+}
+
+/// The characters for bytes 0x80-0x9f in Windows-1252. The five bytes that are
+/// undefined in Windows-1252 map to the C1 control character of the same value,
+/// so that every byte sequence survives a decode/encode cycle.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20ac}', '\u{0081}', '\u{201a}', '\u{0192}',
+    '\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02c6}', '\u{2030}', '\u{0160}', '\u{2039}',
+    '\u{0152}', '\u{008d}', '\u{017d}', '\u{008f}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201c}',
+    '\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02dc}', '\u{2122}', '\u{0161}', '\u{203a}',
+    '\u{0153}', '\u{009d}', '\u{017e}', '\u{0178}',
+];
+
+fn decode_1252(byte: u8) -> char {
+    match byte {
+        0x80..=0x9f => WINDOWS_1252_HIGH[(byte - 0x80) as usize],
+        _ => byte as char
+    }
+}
+
+fn encode_1252(ch: char) -> Option<u8> {
+    match ch as u32 {
+        0x00..=0x7f | 0xa0..=0xff => Some(ch as u8),
+        _ => WINDOWS_1252_HIGH.iter()
+            .position(|&c| c == ch)
+            .map(|pos| 0x80 + pos as u8)
+    }
+}
+
+impl Encoding {
+    /// Convert the raw bytes of a string into a string. If the bytes are
+    /// already valid in the output, the result borrows from the input.
+    ///
+    /// Invalid UTF-8 sequences are replaced with U+FFFD.
+    pub fn decode(self, bytes: Cow<'_, [u8]>) -> Cow<'_, str> {
+        match (self, bytes) {
+            (_, Cow::Borrowed(data)) if data.is_ascii() =>
+                Cow::Borrowed(std::str::from_utf8(data).unwrap_or_default()),
+            (_, Cow::Owned(data)) if data.is_ascii() =>
+                Cow::Owned(String::from_utf8(data).unwrap_or_default()),
+            (Encoding::Windows1252, data) =>
+                Cow::Owned(data.iter().copied().map(decode_1252).collect()),
+            (Encoding::Utf8, Cow::Borrowed(data)) =>
+                String::from_utf8_lossy(data),
+            (Encoding::Utf8, Cow::Owned(data)) => Cow::Owned(
+                String::from_utf8(data)
+                    .unwrap_or_else(|err| String::from_utf8_lossy(
+                            err.as_bytes()).into_owned())),
+        }
+    }
+
+    /// Convert a string into the raw bytes used in the file. This is the
+    /// inverse of `decode`, so decoding and then encoding a string will produce
+    /// the same bytes.
+    ///
+    /// Characters that cannot be represented in Windows-1252 are written as
+    /// `?`.
+    pub fn encode(self, string: &str) -> Cow<'_, [u8]> {
+        if string.is_ascii() || self == Encoding::Utf8 {
+            return Cow::Borrowed(string.as_bytes());
+        }
+        Cow::Owned(string.chars()
+            .map(|ch| encode_1252(ch).unwrap_or(b'?'))
+            .collect())
+    }
+}
+
+/// Remove the escapes from the contents of a quoted string.
+///
+/// Only `\"` and `\\` are escape sequences; a backslash before any other
+/// character is kept as-is.
+pub fn unescape(bytes: Cow<'_, [u8]>) -> Cow<'_, [u8]> {
+    if !bytes.contains(&b'\\') {
+        return bytes;
+    }
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut iter = bytes.iter().copied().peekable();
+    while let Some(ch) = iter.next() {
+        match (ch, iter.peek()) {
+            (b'\\', Some(&next)) if next == b'"' || next == b'\\' => {
+                result.push(next);
+                iter.next();
+            },
+            _ => result.push(ch)
+        }
+    }
+    Cow::Owned(result)
+}
+
+/// Add escapes to a string so that it can be written within quotes. This is the
+/// inverse of `unescape`.
+pub fn escape(string: &str) -> Cow<'_, str> {
+    if !string.contains(['"', '\\']) {
+        return Cow::Borrowed(string);
+    }
+    let mut result = String::with_capacity(string.len() + 2);
+    let mut iter = string.chars().peekable();
+    while let Some(ch) = iter.next() {
+        match ch {
+            '"' => result.push_str("\\\""),
+            '\\' if matches!(iter.peek(), None | Some('"') | Some('\\')) =>
+                result.push_str("\\\\"),
+            _ => result.push(ch)
+        }
+    }
+    Cow::Owned(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_1252_round_trip() {
+        let bytes : Vec<u8> = (0..=255).collect();
+        let decoded = Encoding::Windows1252.decode(Cow::Borrowed(&bytes));
+        assert_eq!(decoded.chars().count(), 256);
+        assert!(decoded.contains('\u{20ac}'));
+        assert!(decoded.contains('ç'));
+        assert_eq!(Encoding::Windows1252.encode(&decoded), &bytes[..]);
+        assert_eq!(Encoding::Windows1252.encode("日本"), &b"??"[..]);
+    }
+
+    #[test]
+    fn utf8_decode() {
+        let bytes = "Fran\u{e7}ois".as_bytes();
+        let decoded = Encoding::Utf8.decode(Cow::Borrowed(bytes));
+        assert!(matches!(decoded, Cow::Borrowed("Fran\u{e7}ois")));
+        assert_eq!(Encoding::Utf8.encode(&decoded), bytes);
+    }
+
+    #[test]
+    fn escapes() {
+        fn check(raw: &str, value: &str) {
+            let unescaped = unescape(Cow::Borrowed(raw.as_bytes()));
+            assert_eq!(&*unescaped, value.as_bytes());
+            assert_eq!(escape(value), raw);
+        }
+        check("plain", "plain");
+        check(r#"say \"hi\""#, r#"say "hi""#);
+        check(r"C:\dir\\", r"C:\dir\");
+        check(r"a\\\b", r"a\\b");
+        check(r"\n", r"\n");
+    }
+}
@@ -147,7 +147,7 @@ impl std::str::FromStr for FixedPoint {
             3 => fract_str.parse::<i32>()?,
             _ => fract_str[0..3].parse::<i32>()?
         };
-        if fract > 1000 || fract < 0 {
+        if !(0..=1000).contains(&fract) {
             return Err(Self::Err::Format);
         }
         Ok(Self(negation * (integer * 1000 + fract)))
@@ -2,7 +2,7 @@ use derivative::Derivative;
 use std::collections::HashMap;
 use std::io::{Error, ErrorKind};
 use std::path::{Path, PathBuf};
-use crate::{GameTrait, ParadoxParse, ParseError, Parser, ParserAtom};
+use crate::{Diagnostics, GameTrait, ParadoxParse, ParseError, Parser, ParserAtom};
 
 type Result<T> = std::result::Result<T, ParseError>;
 
@@ -10,8 +10,10 @@ type Result<T> = std::result::Result<T, ParseError>;
 ///
 /// This struct shouldn't be used by most people, as the core elements will be
 /// exposed by crates deriving GameData (which uses this struct internally).
+#[derive(Clone)]
 pub struct GameData {
     game_directory: PathBuf,
+    diagnostics: Diagnostics,
 }
 
 impl GameData {
@@ -24,7 +26,8 @@ impl GameData {
         }
 
         Ok(GameData {
-            game_directory: game_dir.to_path_buf()
+            game_directory: game_dir.to_path_buf(),
+            diagnostics: Diagnostics::default()
         })
     }
 
@@ -33,9 +36,31 @@ impl GameData {
         &mut self, path: &str,
         target: &mut dyn ParadoxParse<G>) -> Result<&mut Self>
     {
-        crate::load_directory(&self.game_directory.join(path), target, self)?;
+        let diagnostics = crate::load_directory(
+            &self.game_directory.join(path), target, self)?;
+        self.diagnostics.extend(diagnostics);
         Ok(self)
     }
+
+    /// The diagnostics for the input skipped over by `parse_directory`, for
+    /// all of the directories parsed so far.
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
+    }
+
+    /// A copy of this struct for parsing on another thread, without the
+    /// diagnostics collected so far. Pass it back to `join` when done.
+    pub(crate) fn fork(&self) -> Self {
+        GameData {
+            game_directory: self.game_directory.clone(),
+            diagnostics: Diagnostics::default()
+        }
+    }
+
+    /// Add the diagnostics collected by a copy from `fork`.
+    pub(crate) fn join(&mut self, other: GameData) {
+        self.diagnostics.extend(other.diagnostics);
+    }
 }
 
 pub trait BoxedValue: Default {
@@ -73,10 +98,10 @@ impl <'a, T> std::ops::Index<&'a ParserAtom<T::Trait>> for TypeDefinition<T>
 
 impl <T: BoxedValue + ParadoxParse<T::Trait>> ParadoxParse<T::Trait> for TypeDefinition<T> {
     fn read(&mut self, parser: &mut Parser<T::Trait>) -> Result<()> {
-        parser.parse_key_scope(|key, parser| {
+        parser.parse_key_scope(|key, _, parser| {
             let index = self.values.len();
             if self.map.insert(key.clone(), index).is_some() {
-                return Err(ParseError::Constraint(
+                return Err(ParseError::constraint(
                         format!("Duplicate key {} in map", key)));
             }
             let mut val = T::default();
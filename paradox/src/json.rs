@@ -0,0 +1,229 @@
+use crate::{Date, Entry, GameTrait, Item, Operator, Token, Value};
+use std::convert::TryFrom;
+use std::io::{self, Write};
+use string_cache::StaticAtomSet;
+
+/// Write a value as JSON.
+///
+/// * Objects become JSON objects. Keys that appear more than once have all of
+///   their values collected into an array, at the position of the first one.
+/// * Entries that use a comparison instead of `=` become an object with the
+///   operator as its key, like `{"adm_tech": {">=": 10}}`.
+/// * Arrays become arrays. Mixed blocks become arrays too, with each entry
+///   written as an object with a single key.
+/// * Colors become an object with the color space as its key, like
+///   `{"rgb": [255, 0, 0]}`.
+/// * Numbers, including `FixedPoint` decimals, are written as numbers, and
+///   `yes` and `no` as booleans. Dates are written like `1444-11-11`. The
+///   binary format stores dates as integers, so integers are written as dates
+///   too when they are the value of a date key, or in a list that is (see
+///   [`GameTrait::is_date_key`]), and are valid dates in their binary form.
+///
+/// Typed literals should be enabled when reading text files, so that numbers
+/// and dates are recognized. Otherwise they are all written as strings.
+pub fn write_json<G: GameTrait>(value: &Value<G::Static>,
+                                writer: &mut dyn Write) -> io::Result<()> {
+    write_value::<G>(value, None, writer)
+}
+
+/// Write a value as JSON, where `key` is the key of the entry it belongs to.
+fn write_value<G: GameTrait>(value: &Value<G::Static>, key: Option<&str>,
+                             writer: &mut dyn Write) -> io::Result<()> {
+    match value {
+        Value::Scalar(token) => {
+            let is_date = key.is_some_and(|key| G::is_date_key(key));
+            write_scalar(token, is_date, writer)
+        },
+        Value::Color(space, values) => {
+            writer.write_all(b"{")?;
+            write_scalar(space, false, writer)?;
+            writer.write_all(b":")?;
+            write_array::<G>(values.iter(), None, writer)?;
+            writer.write_all(b"}")
+        },
+        Value::Object(entries) => write_object::<G>(entries.iter(), writer),
+        Value::Array(values) => write_array::<G>(values.iter(), key, writer),
+        Value::Mixed(items) => {
+            writer.write_all(b"[")?;
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    writer.write_all(b",")?;
+                }
+                match item {
+                    Item::Entry(entry) =>
+                        write_object::<G>(std::iter::once(entry), writer)?,
+                    Item::Value(value) => write_value::<G>(value, key, writer)?,
+                }
+            }
+            writer.write_all(b"]")
+        },
+    }
+}
+
+impl <Static: StaticAtomSet> Value<Static> {
+    /// Convert the value to a JSON string. See [`write_json`] for details.
+    pub fn to_json<G: GameTrait<Static = Static>>(&self) -> String {
+        let mut output = Vec::new();
+        write_json::<G>(self, &mut output)
+            .expect("writing to a Vec can't fail");
+        String::from_utf8(output).expect("JSON output is always UTF-8")
+    }
+}
+
+fn write_array<'v, G: GameTrait + 'v>(
+    values: impl Iterator<Item = &'v Value<G::Static>>, key: Option<&str>,
+    writer: &mut dyn Write) -> io::Result<()> {
+    writer.write_all(b"[")?;
+    for (i, value) in values.enumerate() {
+        if i > 0 {
+            writer.write_all(b",")?;
+        }
+        write_value::<G>(value, key, writer)?;
+    }
+    writer.write_all(b"]")
+}
+
+fn write_object<'v, G: GameTrait + 'v>(
+    entries: impl Iterator<Item = &'v Entry<G::Static>>, writer: &mut dyn Write)
+        -> io::Result<()> {
+    // Group duplicate keys, keeping the order of their first appearance.
+    let mut groups: Vec<(&str, Vec<&Entry<G::Static>>)> = Vec::new();
+    let mut indices = std::collections::HashMap::new();
+    for entry in entries {
+        let index = *indices.entry(&*entry.key).or_insert_with(|| {
+            groups.push((&*entry.key, Vec::new()));
+            groups.len() - 1
+        });
+        groups[index].1.push(entry);
+    }
+
+    writer.write_all(b"{")?;
+    for (i, (key, group)) in groups.iter().enumerate() {
+        if i > 0 {
+            writer.write_all(b",")?;
+        }
+        write_string(key, writer)?;
+        writer.write_all(b":")?;
+        if let [entry] = group[..] {
+            write_entry_value::<G>(entry, writer)?;
+            continue;
+        }
+        writer.write_all(b"[")?;
+        for (j, entry) in group.iter().enumerate() {
+            if j > 0 {
+                writer.write_all(b",")?;
+            }
+            write_entry_value::<G>(entry, writer)?;
+        }
+        writer.write_all(b"]")?;
+    }
+    writer.write_all(b"}")
+}
+
+fn write_entry_value<G: GameTrait>(entry: &Entry<G::Static>,
+                                   writer: &mut dyn Write) -> io::Result<()> {
+    if entry.op == Operator::Eq {
+        return write_value::<G>(&entry.value, Some(&entry.key), writer);
+    }
+    writer.write_all(b"{")?;
+    write_string(&entry.op.to_string(), writer)?;
+    writer.write_all(b":")?;
+    write_value::<G>(&entry.value, Some(&entry.key), writer)?;
+    writer.write_all(b"}")
+}
+
+/// Write a single value. Dates are always written as dates, and integers are
+/// too if `is_date` is set and they are valid dates, since that is how the
+/// binary format stores dates.
+fn write_scalar<Static: StaticAtomSet>(token: &Token<'_, Static>,
+                                       is_date: bool, writer: &mut dyn Write)
+        -> io::Result<()> {
+    match token {
+        Token::String(s) => write_string(s, writer),
+        Token::Word(s) => write_string(s, writer),
+        Token::Atom(s) => write_string(s, writer),
+        Token::Bool(b) => write!(writer, "{}", b),
+        Token::Fixed(f) => write!(writer, "{}", f),
+        Token::Float(f) if f.is_finite() => write!(writer, "{}", f),
+        Token::Float(_) => writer.write_all(b"null"),
+        Token::Integer(i) => match Date::from_binary(*i).filter(|_| is_date) {
+            Some(date) => write_string(&date.to_iso_string(), writer),
+            None => write!(writer, "{}", i),
+        },
+        Token::Unsigned(i) => write!(writer, "{}", i),
+        Token::Long(i) => write!(writer, "{}", i),
+        Token::UnsignedLong(i) => write!(writer, "{}", i),
+        Token::Date(date) => write_string(&date.to_iso_string(), writer),
+        Token::Lookup(_, i) => write!(writer, "{}", i),
+        token => match string_cache::Atom::<Static>::try_from(token.clone()) {
+            Ok(text) => write_string(&text, writer),
+            Err(_) => writer.write_all(b"null"),
+        },
+    }
+}
+
+fn write_string(text: &str, writer: &mut dyn Write) -> io::Result<()> {
+    writer.write_all(b"\"")?;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        let escape = match c {
+            '"' => "\\\"".into(),
+            '\\' => "\\\\".into(),
+            '\n' => "\\n".into(),
+            '\r' => "\\r".into(),
+            '\t' => "\\t".into(),
+            c if (c as u32) < 0x20 => format!("\\u{:04x}", c as u32),
+            _ => continue
+        };
+        writer.write_all(&text.as_bytes()[start..i])?;
+        writer.write_all(escape.as_bytes())?;
+        start = i + c.len_utf8();
+    }
+    writer.write_all(&text.as_bytes()[start..])?;
+    writer.write_all(b"\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{GameTrait, TextSliceLexer, Value};
+    use string_cache::{Atom, EmptyStaticAtomSet};
+
+    struct TestGame;
+    impl GameTrait for TestGame {
+        type Static = EmptyStaticAtomSet;
+        fn get_binary_token(_: u16) -> Option<Atom<Self::Static>> { None }
+    }
+
+    fn to_json(input: &str) -> String {
+        let mut lexer = TextSliceLexer::new(input.as_bytes(), "input".into())
+            .with_typed_literals(true);
+        Value::from_lexer::<TestGame>(&mut lexer).unwrap().to_json::<TestGame>()
+    }
+
+    #[test]
+    fn json_output() {
+        assert_eq!(to_json("date = 1444.11.11 tax = 1.500 big = 0.12345 \
+                            n = -3 ok = yes name = \"a \\\"b\\\"\""),
+                   "{\"date\":\"1444-11-11\",\"tax\":1.500,\"big\":0.12345,\
+                    \"n\":-3,\"ok\":true,\"name\":\"a \\\"b\\\"\"}");
+        assert_eq!(to_json("a = 1 b = { x y } a = 2 a = { c = d }"),
+                   "{\"a\":[1,2,{\"c\":\"d\"}],\"b\":[\"x\",\"y\"]}");
+        assert_eq!(to_json("c = rgb { 1 2 3 } t = { adm_tech >= 10 } \
+                            m = { 1 k = v }"),
+                   "{\"c\":{\"rgb\":[1,2,3]},\"t\":{\"adm_tech\":{\">=\":10}},\
+                    \"m\":[1,{\"k\":\"v\"}]}");
+    }
+
+    #[test]
+    fn json_dates() {
+        // 56456976 is 1444.11.11 in binary form, but is only a date under a
+        // date key. Dates written as dates are dates under any key.
+        assert_eq!(to_json("id = 56456976 start_date = 56456976 \
+                            date = { 56456976 1444.11.12 } amount = 1444.11.11 \
+                            founding = { 1444.11.11 1500.1.1 }"),
+                   "{\"id\":56456976,\"start_date\":\"1444-11-11\",\
+                    \"date\":[\"1444-11-11\",\"1444-11-12\"],\
+                    \"amount\":\"1444-11-11\",\
+                    \"founding\":[\"1444-11-11\",\"1500-01-01\"]}");
+    }
+}
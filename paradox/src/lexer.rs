@@ -1,91 +1,448 @@
-use byteorder::{ReadBytesExt, LittleEndian};
-use crate::{FixedPoint, GameTrait, ParseError};
+use crate::{Date, Encoding, ErrorLocation, FixedPoint, GameTrait, ParseError};
+use crate::TokenTable;
+use crate::encoding::unescape;
 use derivative::Derivative;
-use std::io::{BufReader, Bytes, Read};
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::io::{BufRead, BufReader, ErrorKind, Read};
 use std::marker::PhantomData;
+use std::sync::Arc;
 use string_cache::{Atom, StaticAtomSet};
 
 type Result<T> = std::result::Result<T, ParseError>;
 
 /// An individual toker from the lexer.
+///
+/// Strings may borrow from the input buffer of the lexer, which is what the
+/// `'a` lifetime refers to. Lexers that read from a stream always produce
+/// owned strings.
 #[derive(Derivative)]
 #[derivative(Debug(bound=""), Clone(bound=""), PartialEq(bound=""))]
-pub enum Token<Static: StaticAtomSet> {
+pub enum Token<'a, Static: StaticAtomSet> {
     /// The { token
     LBrace,
     /// The } token
     RBrace,
     /// The = token
     Eq,
-    /// A quoted or unquoted string.
-    String(String),
-    /// A fixed atom (useful for faster parsing).
+    /// The < token
+    Lt,
+    /// The <= token
+    Le,
+    /// The > token
+    Gt,
+    /// The >= token
+    Ge,
+    /// The != token
+    Ne,
+    /// The ?= token, which tests a value only if it exists.
+    QEq,
+    /// A quoted string.
+    String(Cow<'a, str>),
+    /// An unquoted word, borrowed from the input where possible. Words aren't
+    /// interned by the lexer; whatever needs an [`Atom`] of one interns it.
+    Word(Cow<'a, str>),
+    /// A fixed atom, such as a token from a binary token table.
     Atom(Atom<Static>),
-    // Special binary token types. We don't parse these in the text lexer.
+    /// The `rgb` prefix of a color, as in `color = rgb { 255 0 0 }`.
+    Rgb,
+    /// The `hsv` prefix of a color, as in `color = hsv { 0.5 1 1 }`.
+    Hsv,
+    // Special binary token types. The text lexer only produces these if typed
+    // literals are enabled.
     Bool(bool),
     Fixed(FixedPoint),
     Float(f64),
     Integer(i32),
-    Unsigned(u32)
+    Unsigned(u32),
+    Long(i64),
+    UnsignedLong(u64),
+    /// A date, from a typed literal like `1444.11.11`. The binary format has
+    /// no token for dates and stores them as integers, so this only becomes
+    /// an integer when it is written in binary.
+    Date(Date),
+    /// An index into the string lookup table of a save, along with the code it
+    /// was written with, which sets the size of the index. In the text format,
+    /// this is written as `__lookup_0x0d40_5`.
+    Lookup(u16, u32),
+    /// A binary token that isn't known to the game or the lexer. In the text
+    /// format, this is written as `__unknown_0x2001`.
+    Unknown(u16)
+}
+
+/// How a game stores 32-bit decimals (code 0x000d) in binary files.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BinaryF32 {
+    /// A signed count of thousandths, which is read as a [`FixedPoint`]. This is
+    /// what EU4 uses.
+    Fixed,
+    /// An IEEE 754 single-precision float.
+    Float
+}
+
+/// The operator that separates a key from its value.
+///
+/// Almost everything uses `=`, but trigger blocks in game scripts can compare
+/// values with the other operators.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Operator {
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Ne,
+    QEq
+}
+
+impl std::fmt::Display for Operator {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            Operator::Eq => "=",
+            Operator::Lt => "<",
+            Operator::Le => "<=",
+            Operator::Gt => ">",
+            Operator::Ge => ">=",
+            Operator::Ne => "!=",
+            Operator::QEq => "?=",
+        })
+    }
+}
+
+impl <Static: StaticAtomSet> Token<'_, Static> {
+    /// If this token is an operator, return which one it is.
+    pub fn as_operator(&self) -> Option<Operator> {
+        match self {
+            Token::Eq => Some(Operator::Eq),
+            Token::Lt => Some(Operator::Lt),
+            Token::Le => Some(Operator::Le),
+            Token::Gt => Some(Operator::Gt),
+            Token::Ge => Some(Operator::Ge),
+            Token::Ne => Some(Operator::Ne),
+            Token::QEq => Some(Operator::QEq),
+            _ => None
+        }
+    }
+
+    /// Copy any borrowed string, so that the token can outlive its lexer.
+    pub fn into_owned(self) -> Token<'static, Static> {
+        match self {
+            Token::LBrace => Token::LBrace,
+            Token::RBrace => Token::RBrace,
+            Token::Eq => Token::Eq,
+            Token::Lt => Token::Lt,
+            Token::Le => Token::Le,
+            Token::Gt => Token::Gt,
+            Token::Ge => Token::Ge,
+            Token::Ne => Token::Ne,
+            Token::QEq => Token::QEq,
+            Token::String(s) => Token::String(Cow::Owned(s.into_owned())),
+            Token::Word(s) => Token::Word(Cow::Owned(s.into_owned())),
+            Token::Atom(a) => Token::Atom(a),
+            Token::Rgb => Token::Rgb,
+            Token::Hsv => Token::Hsv,
+            Token::Bool(b) => Token::Bool(b),
+            Token::Fixed(f) => Token::Fixed(f),
+            Token::Float(f) => Token::Float(f),
+            Token::Integer(i) => Token::Integer(i),
+            Token::Date(date) => Token::Date(date),
+            Token::Unsigned(i) => Token::Unsigned(i),
+            Token::Long(i) => Token::Long(i),
+            Token::UnsignedLong(i) => Token::UnsignedLong(i),
+            Token::Lookup(code, i) => Token::Lookup(code, i),
+            Token::Unknown(code) => Token::Unknown(code),
+        }
+    }
+}
+
+/// Where a token starts within a file.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Position {
+    /// A position in a text file. Lines and columns count from 1.
+    Text { line: u32, column: u32 },
+    /// A byte offset in a binary file.
+    Binary { offset: usize }
+}
+
+/// The location of a token: the file it is in and its position there.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Span {
+    pub filename: String,
+    pub position: Position
+}
+
+impl std::fmt::Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.position {
+            Position::Text { line, column } =>
+                write!(f, "{}:{}:{}", self.filename, line, column),
+            Position::Binary { offset } =>
+                write!(f, "{}:{:08x}", self.filename, offset),
+        }
+    }
 }
 
 /// A trait for lexing the input files of Paradox games that use the Jomini or
 /// Clausewitz engines.
-pub trait Lexer<G: GameTrait> {
+///
+/// The tokens returned may borrow from data that lives for `'a`.
+pub trait Lexer<'a, G: GameTrait> {
     /// Get the next token. If EOF has been reached, return None instead.
-    fn get_token(&mut self) -> Result<Option<Token<G::Static>>>;
+    fn get_token(&mut self) -> Result<Option<Token<'a, G::Static>>>;
+
+    /// Get the span of the last token returned by `get_token`.
+    fn get_span(&self) -> Span;
 
     /// Get a displayable name for the current location.
-    fn get_location_info(&self) -> String;
+    fn get_location_info(&self) -> String {
+        self.get_span().to_string()
+    }
 }
 
-pub struct TextLexer<R: Read> {
-    reader: Bytes<BufReader<R>>,
-    saved_char: Option<u8>,
-    filename: String,
-    line: u32,
-    column: u32
+/// The raw bytes underneath a lexer.
+///
+/// Lexers over a reader have to copy each token's bytes out of the stream,
+/// while lexers over an in-memory buffer can hand out slices of it instead.
+trait ByteSource<'a> {
+    /// Look at the next byte without consuming it.
+    fn peek(&mut self) -> std::io::Result<Option<u8>>;
+
+    /// Consume the next byte.
+    fn next(&mut self) -> std::io::Result<Option<u8>>;
+
+    /// Consume exactly `N` bytes.
+    fn take_array<const N: usize>(&mut self) -> std::io::Result<[u8; N]>;
+
+    /// Consume exactly `len` bytes.
+    fn take(&mut self, len: usize) -> std::io::Result<Cow<'a, [u8]>>;
+
+    /// Check if the upcoming bytes match `prefix`, without consuming them.
+    fn has_prefix(&mut self, prefix: &[u8]) -> std::io::Result<bool>;
+
+    /// Consume `prefix` if the upcoming bytes match it.
+    fn skip_prefix(&mut self, prefix: &[u8]) -> std::io::Result<bool> {
+        if !self.has_prefix(prefix)? {
+            return Ok(false);
+        }
+        for _ in prefix {
+            self.next()?;
+        }
+        Ok(true)
+    }
+
+    /// Start remembering the bytes consumed by `next`.
+    fn mark(&mut self);
+
+    /// Return all of the bytes consumed since the last call to `mark`.
+    fn since_mark(&mut self) -> Cow<'a, [u8]>;
+
+    /// The number of bytes consumed so far.
+    fn offset(&self) -> usize;
 }
 
-impl <R: Read> TextLexer<R> {
-    /// Create a lexer from the given input file. Pass a filename in as well, to
-    /// give better error messages.
-    pub fn new(reader: R, filename: String) -> Self {
-        TextLexer {
-            reader: BufReader::new(reader).bytes(),
-            filename, line: 1, column: 1,
-            saved_char: None
+fn unexpected_eof() -> std::io::Error {
+    std::io::Error::new(ErrorKind::UnexpectedEof, "unexpected end of input")
+}
+
+struct ReaderSource<R: Read> {
+    reader: BufReader<R>,
+    /// Bytes already taken out of the reader, so that a prefix can be matched
+    /// across the end of its buffer.
+    lookahead: Vec<u8>,
+    offset: usize,
+    recording: Option<Vec<u8>>
+}
+
+impl <R: Read> ReaderSource<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader: BufReader::new(reader), lookahead: Vec::new(), offset: 0,
+            recording: None
         }
     }
 
-    fn get_char(&mut self) -> std::io::Result<Option<u8>> {
-        if let Some(ch) = self.saved_char {
-            self.saved_char = None;
-            Ok(Some(ch))
+    /// Fill `data`, starting with the bytes in the lookahead.
+    fn read_exact(&mut self, data: &mut [u8]) -> std::io::Result<()> {
+        let len = self.lookahead.len().min(data.len());
+        data[..len].copy_from_slice(&self.lookahead[..len]);
+        self.lookahead.drain(..len);
+        self.reader.read_exact(&mut data[len..])?;
+        self.offset += data.len();
+        Ok(())
+    }
+}
+
+impl <'a, R: Read> ByteSource<'a> for ReaderSource<R> {
+    fn peek(&mut self) -> std::io::Result<Option<u8>> {
+        if let Some(&ch) = self.lookahead.first() {
+            return Ok(Some(ch));
+        }
+        Ok(self.reader.fill_buf()?.first().copied())
+    }
+
+    fn next(&mut self) -> std::io::Result<Option<u8>> {
+        let ch = if self.lookahead.is_empty() {
+            let ch = self.peek()?;
+            if ch.is_some() {
+                self.reader.consume(1);
+            }
+            ch
         } else {
-            let ch = self.reader.next();
-            match ch {
-                Some(Ok(b'\n')) => {
-                    self.line += 1; self.column = 1;
-                    Ok(Some(b'\n'))
-                },
-                Some(Ok(ch)) => {
-                    self.column += 1;
-                    Ok(Some(ch))
-                },
-                Some(Err(err)) => {
-                    Err(err)
-                },
-                None => {
-                    Ok(None)
+            Some(self.lookahead.remove(0))
+        };
+        if let Some(ch) = ch {
+            self.offset += 1;
+            if let Some(recording) = &mut self.recording {
+                recording.push(ch);
+            }
+        }
+        Ok(ch)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> std::io::Result<[u8; N]> {
+        let mut data = [0u8; N];
+        self.read_exact(&mut data)?;
+        Ok(data)
+    }
+
+    fn take(&mut self, len: usize) -> std::io::Result<Cow<'a, [u8]>> {
+        let mut data = vec![0; len];
+        self.read_exact(&mut data)?;
+        Ok(Cow::Owned(data))
+    }
+
+    fn has_prefix(&mut self, prefix: &[u8]) -> std::io::Result<bool> {
+        if self.lookahead.is_empty() {
+            let buffer = self.reader.fill_buf()?;
+            if buffer.len() >= prefix.len() || buffer.is_empty() {
+                return Ok(buffer.starts_with(prefix));
+            }
+        }
+        // The prefix runs past the end of the buffer, so move bytes into the
+        // lookahead until it can be checked.
+        while self.lookahead.len() < prefix.len() {
+            match self.reader.fill_buf()?.first().copied() {
+                Some(ch) => {
+                    self.lookahead.push(ch);
+                    self.reader.consume(1);
                 },
+                None => break
             }
         }
+        Ok(self.lookahead.starts_with(prefix))
+    }
+
+    fn mark(&mut self) {
+        self.recording = Some(Vec::new());
+    }
+
+    fn since_mark(&mut self) -> Cow<'a, [u8]> {
+        Cow::Owned(self.recording.take().unwrap_or_default())
+    }
+
+    fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+struct SliceSource<'a> {
+    data: &'a [u8],
+    pos: usize,
+    mark: usize
+}
+
+impl <'a> SliceSource<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0, mark: 0 }
+    }
+}
+
+impl <'a> ByteSource<'a> for SliceSource<'a> {
+    fn peek(&mut self) -> std::io::Result<Option<u8>> {
+        Ok(self.data.get(self.pos).copied())
+    }
+
+    fn next(&mut self) -> std::io::Result<Option<u8>> {
+        let ch = self.data.get(self.pos).copied();
+        if ch.is_some() {
+            self.pos += 1;
+        }
+        Ok(ch)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> std::io::Result<[u8; N]> {
+        let mut data = [0u8; N];
+        data.copy_from_slice(&self.take(N)?);
+        Ok(data)
+    }
+
+    fn take(&mut self, len: usize) -> std::io::Result<Cow<'a, [u8]>> {
+        let end = self.pos.checked_add(len)
+            .filter(|&end| end <= self.data.len())
+            .ok_or_else(unexpected_eof)?;
+        let data = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(Cow::Borrowed(data))
+    }
+
+    fn has_prefix(&mut self, prefix: &[u8]) -> std::io::Result<bool> {
+        Ok(self.data[self.pos..].starts_with(prefix))
+    }
+
+    fn mark(&mut self) {
+        self.mark = self.pos;
+    }
+
+    fn since_mark(&mut self) -> Cow<'a, [u8]> {
+        Cow::Borrowed(&self.data[self.mark..self.pos])
+    }
+
+    fn offset(&self) -> usize {
+        self.pos
+    }
+}
+
+/// The guts of the text lexers, shared between the reader and slice variants.
+struct TextState<S> {
+    source: S,
+    encoding: Encoding,
+    typed: bool,
+    filename: String,
+    line: u32,
+    column: u32,
+    /// The line and column of the start of the last token.
+    start: (u32, u32)
+}
+
+impl <'a, S: ByteSource<'a>> TextState<S> {
+    fn new(source: S, filename: String) -> Self {
+        TextState {
+            source, filename, line: 1, column: 1, start: (1, 1),
+            encoding: Encoding::default(),
+            typed: false
+        }
     }
 
-    fn unget(&mut self, ch: u8) {
-        assert!(self.saved_char.is_none(), "Only one char can be ungotten");
-        self.saved_char = Some(ch);
+    /// Skip a UTF-8 byte order mark at the start of the file, if present. Files
+    /// with a BOM are always UTF-8, whatever the default encoding is.
+    fn skip_bom(&mut self) -> std::io::Result<()> {
+        if self.source.offset() == 0 &&
+                self.source.skip_prefix(b"\xef\xbb\xbf")? {
+            self.encoding = Encoding::Utf8;
+        }
+        Ok(())
+    }
+
+    fn get_char(&mut self) -> std::io::Result<Option<u8>> {
+        let ch = self.source.next()?;
+        match ch {
+            Some(b'\n') => {
+                self.line += 1; self.column = 1;
+            },
+            Some(_) => self.column += 1,
+            None => {}
+        }
+        Ok(ch)
     }
 
     /// Read until the end of line of a comment.
@@ -99,122 +456,329 @@ impl <R: Read> TextLexer<R> {
     }
 
     /// Read the tail of a quoted string.
-    fn read_qstring(&mut self) -> Result<String> {
-        let mut s = String::new();
+    fn read_qstring(&mut self) -> Result<Cow<'a, str>> {
+        self.source.mark();
+        let mut escaped = false;
         loop {
-            match self.get_char()? {
-                Some(b'"') => return Ok(s),
-                Some(ch) => s.push(ch as char),
+            match self.source.peek()? {
+                Some(b'"') if !escaped => {
+                    let data = unescape(self.source.since_mark());
+                    self.get_char()?;
+                    return Ok(self.encoding.decode(data));
+                },
+                Some(ch) => {
+                    escaped = !escaped && ch == b'\\';
+                    self.get_char()?;
+                },
                 None => return Err(
-                    ParseError::Lexer("could not find end of string".into()))
+                    self.error("could not find end of string".into()))
             }
         }
     }
 
+    /// Read an operator that starts with the given character, if there is one.
+    fn read_operator<Static: StaticAtomSet>(&mut self, ch: u8)
+            -> std::io::Result<Option<Token<'a, Static>>> {
+        let has_eq = self.source.has_prefix(&[ch, b'='])?;
+        let token = match (ch, has_eq) {
+            (b'=', _) => Token::Eq,
+            (b'<', false) => Token::Lt,
+            (b'<', true) => Token::Le,
+            (b'>', false) => Token::Gt,
+            (b'>', true) => Token::Ge,
+            (b'!', true) => Token::Ne,
+            (b'?', true) => Token::QEq,
+            _ => return Ok(None)
+        };
+        self.get_char()?;
+        if has_eq && ch != b'=' {
+            self.get_char()?;
+        }
+        Ok(Some(token))
+    }
+
     /// Read an unparsed full token.
-    fn read_unknown(&mut self, init_char: u8) -> Result<String> {
-        let mut s = String::new();
-        s.push(init_char as char);
+    fn read_unknown(&mut self) -> Result<Cow<'a, str>> {
+        self.source.mark();
         loop {
-            match self.get_char()? {
-                Some(ch) if b"#{=}\"".contains(&ch) => {
-                    self.unget(ch);
-                    return Ok(s);
-                },
-                None => return Ok(s),
-                Some(ch) if Self::is_whitespace(ch) => return Ok(s),
-                Some(ch) => s.push(ch as char),
+            match self.source.peek()? {
+                Some(ch) if b"#{=}\"<>".contains(&ch) => break,
+                Some(ch @ b'!') | Some(ch @ b'?')
+                    if self.source.has_prefix(&[ch, b'='])? => break,
+                Some(ch) if Self::is_whitespace(ch) => break,
+                None => break,
+                Some(_) => { self.get_char()?; },
+            }
+        }
+        Ok(self.encoding.decode(self.source.since_mark()))
+    }
+
+    /// Read a bare word, converting it into a typed token if typed literals are
+    /// enabled.
+    fn read_word<Static: StaticAtomSet>(&mut self)
+            -> Result<Token<'a, Static>> {
+        let word = self.read_unknown()?;
+        if self.typed {
+            if let Some(token) = typed_literal(&word) {
+                return Ok(token);
             }
         }
+        Ok(Token::Word(word))
     }
 
     /// Check if the given character is whitespace, according to Paradox.
     fn is_whitespace(ch: u8) -> bool {
         ch == b' ' || ch == b'\t' || ch == b'\r' || ch == b'\n'
     }
-}
 
-impl <G: GameTrait, R: Read> Lexer<G> for TextLexer<R> {
-    fn get_token(&mut self) -> Result<Option<Token<G::Static>>> {
+    fn get_token<Static: StaticAtomSet>(&mut self)
+            -> Result<Option<Token<'a, Static>>> {
+        self.skip_bom()?;
         loop {
-            match self.get_char()? {
+            let ch = match self.source.peek()? {
                 None => return Ok(None),
-                Some(ch) if Self::is_whitespace(ch) => continue,
-                Some(b'#') => self.skip_comment()?,
-                Some(b'{') => return Ok(Some(Token::LBrace)),
-                Some(b'}') => return Ok(Some(Token::RBrace)),
-                Some(b'=') => return Ok(Some(Token::Eq)),
-                Some(b'"') =>
-                    return Ok(Some(Token::String(self.read_qstring()?))),
-                Some(ch) =>
-                    return Ok(Some(Token::Atom(self.read_unknown(ch)?.into())))
+                Some(ch) => ch
+            };
+            self.start = (self.line, self.column);
+            let token = match ch {
+                ch if Self::is_whitespace(ch) => None,
+                b'#' => { self.skip_comment()?; continue; },
+                b'{' => Some(Token::LBrace),
+                b'}' => Some(Token::RBrace),
+                b'=' | b'<' | b'>' | b'!' | b'?' => {
+                    if let Some(token) = self.read_operator(ch)? {
+                        return Ok(Some(token));
+                    }
+                    return Ok(Some(self.read_word()?));
+                },
+                b'"' => {
+                    self.get_char()?;
+                    return Ok(Some(Token::String(self.read_qstring()?)));
+                },
+                _ => return Ok(Some(self.read_word()?))
+            };
+            self.get_char()?;
+            if token.is_some() {
+                return Ok(token);
             }
         }
     }
 
-    fn get_location_info(&self) -> String {
-        format!("{}:{}:{}", self.filename, self.line, self.column)
+    fn get_span(&self) -> Span {
+        let (line, column) = self.start;
+        Span {
+            filename: self.filename.clone(),
+            position: Position::Text { line, column }
+        }
+    }
+
+    /// Create an error for the last token.
+    fn error(&self, msg: String) -> ParseError {
+        ParseError::Lexer(msg, ErrorLocation::at(self.get_span()))
     }
 }
 
-pub struct BinaryLexer<G: GameTrait, R: Read> {
-    filename: String,
-    offset: u32,
-    reader: BufReader<R>,
-    _trait: PhantomData<G>
+/// Split a number into its sign and the digits of each `.`-separated piece,
+/// returning `None` if it isn't made up of digits.
+fn number_pieces(word: &str) -> Option<(bool, Vec<&str>)> {
+    let (negative, digits) = match word.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, word)
+    };
+    let pieces : Vec<_> = digits.split('.').collect();
+    let is_digits = |p: &&str| !p.is_empty() &&
+        p.bytes().all(|b| b.is_ascii_digit());
+    if !pieces.iter().all(is_digits) {
+        return None;
+    }
+    Some((negative, pieces))
 }
 
-impl <G: GameTrait, R: Read> BinaryLexer<G, R> {
+/// Convert a bare word into the token that the binary format would use for
+/// it, if it looks like a literal.
+///
+/// Decimals with at most three places become [`FixedPoint`]s, and longer ones
+/// floats. Dates are kept as dates, so that they aren't mistaken for integers.
+fn typed_literal<'a, Static: StaticAtomSet>(word: &str)
+        -> Option<Token<'a, Static>> {
+    match word {
+        "yes" => return Some(Token::Bool(true)),
+        "no" => return Some(Token::Bool(false)),
+        "rgb" => return Some(Token::Rgb),
+        "hsv" => return Some(Token::Hsv),
+        _ => {}
+    }
+    // Binary tokens that have no text form of their own, as written by
+    // `TextWriter`.
+    if let Some(code) = word.strip_prefix("__unknown_0x") {
+        return u16::from_str_radix(code, 16).ok().map(Token::Unknown);
+    }
+    if let Some(lookup) = word.strip_prefix("__lookup_0x") {
+        let (code, index) = lookup.split_once('_')?;
+        let code = u16::from_str_radix(code, 16).ok()?;
+        return index.parse().ok().map(|index| Token::Lookup(code, index));
+    }
+    let (negative, pieces) = number_pieces(word)?;
+    match pieces[..] {
+        [_] => word.parse().map(Token::Integer).ok()
+            .or_else(|| word.parse().map(Token::Unsigned).ok())
+            .or_else(|| word.parse().map(Token::Long).ok())
+            .or_else(|| word.parse().map(Token::UnsignedLong).ok()),
+        [integer, fraction] if fraction.len() <= 3 => {
+            let integer : i64 = integer.parse().ok()?;
+            let fraction : i64 = fraction.parse().ok()?;
+            let scale = 10i64.pow(3 - pieces[1].len() as u32);
+            let value = integer * 1000 + fraction * scale;
+            let value = if negative { -value } else { value };
+            let value = i32::try_from(value).ok()?;
+            Some(Token::Fixed(FixedPoint(value)))
+        },
+        [_, _] => word.parse().map(Token::Float).ok(),
+        [_, _, _] if !negative => word.parse().ok().map(Token::Date),
+        _ => None
+    }
+}
+
+pub struct TextLexer<R: Read> {
+    state: TextState<ReaderSource<R>>
+}
+
+impl <R: Read> TextLexer<R> {
+    /// Create a lexer from the given input file. Pass a filename in as well, to
+    /// give better error messages.
     pub fn new(reader: R, filename: String) -> Self {
-        BinaryLexer {
-            reader: BufReader::new(reader),
-            offset: 0,
-            filename,
-            _trait: PhantomData
+        TextLexer {
+            state: TextState::new(ReaderSource::new(reader), filename)
         }
     }
 
-    fn read_token(&mut self) -> Result<Token<G::Static>> {
-        let code = self.reader.read_u16::<LittleEndian>()?;
-        self.offset += 2;
+    /// Set the encoding of strings in the file. Files that start with a UTF-8
+    /// byte order mark are always read as UTF-8.
+    pub fn with_encoding(mut self, encoding: Encoding) -> Self {
+        self.state.encoding = encoding;
+        self
+    }
+
+    /// Convert bare words that look like literals (numbers, dates, `yes` and
+    /// `no`, and the `rgb` and `hsv` color prefixes) into the same typed tokens
+    /// that the binary lexer produces, instead of returning them as words.
+    /// Dates are the exception, as [`Token::Date`] rather than integers.
+    pub fn with_typed_literals(mut self, typed: bool) -> Self {
+        self.state.typed = typed;
+        self
+    }
+}
+
+impl <'a, G: GameTrait, R: Read> Lexer<'a, G> for TextLexer<R> {
+    fn get_token(&mut self) -> Result<Option<Token<'a, G::Static>>> {
+        self.state.get_token()
+    }
+
+    fn get_span(&self) -> Span {
+        self.state.get_span()
+    }
+}
+
+/// A text lexer over an in-memory buffer.
+///
+/// Unlike [`TextLexer`], the strings returned by this lexer borrow from the
+/// buffer rather than being copied, which makes it much faster for large files.
+pub struct TextSliceLexer<'a> {
+    state: TextState<SliceSource<'a>>
+}
+
+impl <'a> TextSliceLexer<'a> {
+    /// Create a lexer for the given data. Pass a filename in as well, to give
+    /// better error messages.
+    pub fn new(data: &'a [u8], filename: String) -> Self {
+        TextSliceLexer {
+            state: TextState::new(SliceSource::new(data), filename)
+        }
+    }
+
+    /// Set the encoding of strings in the file. Files that start with a UTF-8
+    /// byte order mark are always read as UTF-8.
+    pub fn with_encoding(mut self, encoding: Encoding) -> Self {
+        self.state.encoding = encoding;
+        self
+    }
+
+    /// Convert bare words that look like literals (numbers, dates, `yes` and
+    /// `no`, and the `rgb` and `hsv` color prefixes) into the same typed tokens
+    /// that the binary lexer produces, instead of returning them as words.
+    /// Dates are the exception, as [`Token::Date`] rather than integers.
+    pub fn with_typed_literals(mut self, typed: bool) -> Self {
+        self.state.typed = typed;
+        self
+    }
+}
+
+impl <'a, 'b: 'a, G: GameTrait> Lexer<'a, G> for TextSliceLexer<'b> {
+    fn get_token(&mut self) -> Result<Option<Token<'a, G::Static>>> {
+        self.state.get_token()
+    }
+
+    fn get_span(&self) -> Span {
+        self.state.get_span()
+    }
+}
+
+/// The guts of the binary lexers, shared between the reader and slice variants.
+struct BinaryState<S> {
+    source: S,
+    encoding: Encoding,
+    filename: String,
+    /// The offset of the start of the last token.
+    start: usize
+}
+
+impl <'a, S: ByteSource<'a>> BinaryState<S> {
+    fn read_token<G: GameTrait>(&mut self,
+                                table: Option<&TokenTable<G::Static>>)
+            -> Result<Token<'a, G::Static>> {
+        self.start = self.source.offset();
+        let code = u16::from_le_bytes(self.source.take_array()?);
         Ok(match code {
             0x0001 => Token::Eq,
             0x0003 => Token::LBrace,
             0x0004 => Token::RBrace,
             0x000b => Token::Atom("id".into()),
             0x000c => {
-                let val = self.reader.read_i32::<LittleEndian>()?;
-                self.offset += 4;
+                let val = i32::from_le_bytes(self.source.take_array()?);
                 Token::Integer(val)
             },
             0x000d => {
-                // Fixed point notation.
-                let val = self.reader.read_i32::<LittleEndian>()?;
-                self.offset += 4;
-                Token::Fixed(FixedPoint(val))
+                let data = self.source.take_array()?;
+                match G::BINARY_F32 {
+                    BinaryF32::Fixed =>
+                        Token::Fixed(FixedPoint(i32::from_le_bytes(data))),
+                    BinaryF32::Float =>
+                        Token::Float(f32::from_le_bytes(data) as f64),
+                }
             },
             0x000e => {
-                let val = self.reader.read_u8()?;
-                self.offset += 1;
+                let [val] = self.source.take_array()?;
                 match val {
                     0 => Token::Bool(false),
                     1 => Token::Bool(true),
-                    _ => return Err(ParseError::Lexer(val.to_string()))
+                    _ => return Err(self.error(
+                            format!("invalid boolean value {}", val)))
                 }
             },
-            0x000f | 0x0017 => {
-                let len = self.reader.read_u16::<LittleEndian>()? as usize;
-                self.offset += 2;
-                let mut data = Vec::with_capacity(len);
-                data.resize(len, 0);
-                self.reader.read_exact(&mut data)?;
-                self.offset += len as u32;
-                let string = data.iter().map(|&ch| ch as char).collect();
-                Token::String(string)
+            0x000f => {
+                let len = u16::from_le_bytes(self.source.take_array()?);
+                let data = self.source.take(len as usize)?;
+                Token::String(self.encoding.decode(data))
+            },
+            0x0017 => {
+                // An unquoted string, which is the same as a bare word in the
+                // text format.
+                let len = u16::from_le_bytes(self.source.take_array()?);
+                let data = self.source.take(len as usize)?;
+                Token::Word(self.encoding.decode(data))
             },
             0x0014 => {
-                let val = self.reader.read_u32::<LittleEndian>()?;
-                self.offset += 4;
+                let val = u32::from_le_bytes(self.source.take_array()?);
                 Token::Unsigned(val)
             },
             0x001b => {
@@ -222,14 +786,14 @@ impl <G: GameTrait, R: Read> BinaryLexer<G, R> {
             },
             0x0167 => {
                 // A fixed point number, with a base of 1 << 16.
-                let val = self.reader.read_i64::<LittleEndian>()?;
-                self.offset += 8;
+                let val = i64::from_le_bytes(self.source.take_array()?);
                 // As long as the mantissa is small enough, we can represent
                 // this number exactly in a double-precision floating-point
                 // number.
                 let mantissa_size = 64 - val.abs().leading_zeros();
-                if mantissa_size > std::f64::MANTISSA_DIGITS + 1 {
-                    return Err(ParseError::Lexer(format!("{:016x}", val)));
+                if mantissa_size > f64::MANTISSA_DIGITS + 1 {
+                    return Err(self.error(
+                            format!("float out of range: {:016x}", val)));
                 }
                 // Converting to double-precision and then doing a fdiv is the
                 // easiest way to do the conversion. Both steps are exact, if we
@@ -239,22 +803,41 @@ impl <G: GameTrait, R: Read> BinaryLexer<G, R> {
                 let val = (val as f64) / 65536.0;
                 Token::Float(val)
             },
+            0x0243 => Token::Rgb,
+            0x029c => {
+                let val = u64::from_le_bytes(self.source.take_array()?);
+                Token::UnsignedLong(val)
+            },
+            0x0317 => {
+                let val = i64::from_le_bytes(self.source.take_array()?);
+                Token::Long(val)
+            },
+            0x0d3e | 0x0d40 => {
+                let [index] = self.source.take_array()?;
+                Token::Lookup(code, index as u32)
+            },
+            0x0d3d | 0x0d43 => {
+                let val = u16::from_le_bytes(self.source.take_array()?);
+                Token::Lookup(code, val as u32)
+            },
+            0x0d44 => {
+                let [a, b, c] = self.source.take_array()?;
+                Token::Lookup(code, u32::from_le_bytes([a, b, c, 0]))
+            },
             0x0020..=0xffff => {
-                G::get_binary_token(code)
-                    .map(|s| Token::Atom(s.as_ref().into()))
-                    .ok_or_else(|| ParseError::Lexer(
-                            format!("Unknown code: {:04x}", code)))?
+                table.and_then(|table| table.get(code))
+                    .or_else(|| G::get_binary_token(code))
+                    .map_or(Token::Unknown(code), Token::Atom)
             },
-            _ => panic!("Unknown code: {:4x}", code)
+            _ => Token::Unknown(code)
         })
     }
-}
 
-impl <G: GameTrait, R: Read> Lexer<G> for BinaryLexer<G, R> {
-    fn get_token(&mut self) -> Result<Option<Token<G::Static>>> {
-        match self.read_token() {
-            Err(ParseError::Io(e))
-                    if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+    fn get_token<G: GameTrait>(&mut self,
+                               table: Option<&TokenTable<G::Static>>)
+            -> Result<Option<Token<'a, G::Static>>> {
+        match self.read_token::<G>(table) {
+            Err(ParseError::Io(e, _)) if e.kind() == ErrorKind::UnexpectedEof => {
                 Ok(None)
             },
             Err(e) => Err(e),
@@ -262,8 +845,310 @@ impl <G: GameTrait, R: Read> Lexer<G> for BinaryLexer<G, R> {
         }
     }
 
-    fn get_location_info(&self) -> String {
-        format!("{}:{:08x}", self.filename, self.offset)
+    fn get_span(&self) -> Span {
+        Span {
+            filename: self.filename.clone(),
+            position: Position::Binary { offset: self.start }
+        }
+    }
+
+    fn error(&self, msg: String) -> ParseError {
+        ParseError::Lexer(msg, ErrorLocation::at(self.get_span()))
     }
 }
 
+pub struct BinaryLexer<G: GameTrait, R: Read> {
+    state: BinaryState<ReaderSource<R>>,
+    table: Option<Arc<TokenTable<G::Static>>>,
+    _trait: PhantomData<G>
+}
+
+impl <G: GameTrait, R: Read> BinaryLexer<G, R> {
+    pub fn new(reader: R, filename: String) -> Self {
+        BinaryLexer {
+            state: BinaryState {
+                source: ReaderSource::new(reader),
+                encoding: Encoding::default(),
+                filename,
+                start: 0
+            },
+            table: None,
+            _trait: PhantomData
+        }
+    }
+
+    /// Set the encoding of strings in the file.
+    pub fn with_encoding(mut self, encoding: Encoding) -> Self {
+        self.state.encoding = encoding;
+        self
+    }
+
+    /// Look up token names in the given table. Tokens missing from it fall
+    /// back to [`GameTrait::get_binary_token`].
+    pub fn with_token_table(mut self, table: Arc<TokenTable<G::Static>>)
+            -> Self {
+        self.table = Some(table);
+        self
+    }
+}
+
+impl <'a, G: GameTrait, R: Read> Lexer<'a, G> for BinaryLexer<G, R> {
+    fn get_token(&mut self) -> Result<Option<Token<'a, G::Static>>> {
+        self.state.get_token::<G>(self.table.as_deref())
+    }
+
+    fn get_span(&self) -> Span {
+        self.state.get_span()
+    }
+}
+
+/// A binary lexer over an in-memory buffer.
+///
+/// Unlike [`BinaryLexer`], the strings returned by this lexer borrow from the
+/// buffer rather than being copied.
+pub struct BinarySliceLexer<'a, G: GameTrait> {
+    state: BinaryState<SliceSource<'a>>,
+    table: Option<Arc<TokenTable<G::Static>>>,
+    _trait: PhantomData<G>
+}
+
+impl <'a, G: GameTrait> BinarySliceLexer<'a, G> {
+    pub fn new(data: &'a [u8], filename: String) -> Self {
+        BinarySliceLexer {
+            state: BinaryState {
+                source: SliceSource::new(data),
+                encoding: Encoding::default(),
+                filename,
+                start: 0
+            },
+            table: None,
+            _trait: PhantomData
+        }
+    }
+
+    /// Set the encoding of strings in the file.
+    pub fn with_encoding(mut self, encoding: Encoding) -> Self {
+        self.state.encoding = encoding;
+        self
+    }
+
+    /// Look up token names in the given table. Tokens missing from it fall
+    /// back to [`GameTrait::get_binary_token`].
+    pub fn with_token_table(mut self, table: Arc<TokenTable<G::Static>>)
+            -> Self {
+        self.table = Some(table);
+        self
+    }
+}
+
+impl <'a, 'b: 'a, G: GameTrait> Lexer<'a, G> for BinarySliceLexer<'b, G> {
+    fn get_token(&mut self) -> Result<Option<Token<'a, G::Static>>> {
+        self.state.get_token::<G>(self.table.as_deref())
+    }
+
+    fn get_span(&self) -> Span {
+        self.state.get_span()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use string_cache::EmptyStaticAtomSet;
+
+    struct TestGame;
+    impl GameTrait for TestGame {
+        type Static = EmptyStaticAtomSet;
+        fn get_binary_token(id: u16) -> Option<Atom<Self::Static>> {
+            match id {
+                0x2000 => Some("key".into()),
+                _ => None
+            }
+        }
+    }
+
+    fn all_tokens<'a>(lexer: &mut dyn Lexer<'a, TestGame>)
+            -> Vec<Token<'a, EmptyStaticAtomSet>> {
+        let mut tokens = Vec::new();
+        while let Some(token) = lexer.get_token().unwrap() {
+            tokens.push(token);
+        }
+        tokens
+    }
+
+    #[test]
+    fn test_slice_lexer_matches() {
+        let input = b"a = { 1 \"two words\" } # comment\nb=\"x\"";
+        let mut reader = TextLexer::new(&input[..], "input".into());
+        let mut slice = TextSliceLexer::new(input, "input".into());
+        let tokens = all_tokens(&mut slice);
+        assert_eq!(all_tokens(&mut reader), tokens);
+        assert_eq!(tokens[4], Token::String("two words".into()));
+        assert!(matches!(&tokens[4], Token::String(Cow::Borrowed(_))));
+        assert_eq!(tokens[0], Token::Word("a".into()));
+        assert!(matches!(&tokens[0], Token::Word(Cow::Borrowed(_))));
+    }
+
+    #[test]
+    fn test_operator_across_buffer() {
+        // Put the `<=` across the end of the reader's 8 KiB buffer.
+        let mut input = vec![b' '; 8188];
+        input.extend_from_slice(b"dev<= 20");
+        assert_eq!(&input[8191..8193], b"<=");
+        let mut reader = TextLexer::new(&input[..], "input".into());
+        let mut slice = TextSliceLexer::new(&input, "input".into());
+        let tokens = all_tokens(&mut slice);
+        assert_eq!(all_tokens(&mut reader), tokens);
+        assert_eq!(tokens[1].as_operator(), Some(Operator::Le));
+    }
+
+    #[test]
+    fn test_operators() {
+        let input = b"a<1 b <= 2 c>3 d>=4 e!=5 f ?= 6 g=7 !h=8 i?";
+        let mut lexer = TextSliceLexer::new(input, "input".into());
+        let operators : Vec<_> = all_tokens(&mut lexer).iter()
+            .filter_map(|t| t.as_operator())
+            .collect();
+        assert_eq!(operators, vec![
+            Operator::Lt, Operator::Le, Operator::Gt, Operator::Ge,
+            Operator::Ne, Operator::QEq, Operator::Eq, Operator::Eq
+        ]);
+
+        let mut lexer = TextSliceLexer::new(b"!h=8 i?", "input".into());
+        assert_eq!(all_tokens(&mut lexer), vec![
+            Token::Word("!h".into()),
+            Token::Eq,
+            Token::Word("8".into()),
+            Token::Word("i?".into()),
+        ]);
+    }
+
+    #[test]
+    fn test_string_encodings() {
+        let input = b"name=\"Fran\xe7ois \\\"le \x80\\\"\" Bl\xe9";
+        let mut lexer = TextSliceLexer::new(input, "input".into());
+        let tokens = all_tokens(&mut lexer);
+        assert_eq!(tokens[2],
+                   Token::String("Fran\u{e7}ois \"le \u{20ac}\"".into()));
+        assert_eq!(tokens[3], Token::Word("Bl\u{e9}".into()));
+
+        let input = "\u{feff}name=\"Fran\u{e7}ois\"".as_bytes();
+        let mut lexer = TextLexer::new(input, "input".into());
+        assert_eq!(all_tokens(&mut lexer)[2],
+                   Token::String("Fran\u{e7}ois".into()));
+
+        let input = "name=\"Fran\u{e7}ois\"".as_bytes();
+        let mut lexer = TextSliceLexer::new(input, "input".into())
+            .with_encoding(Encoding::Utf8);
+        assert_eq!(all_tokens(&mut lexer)[2],
+                   Token::String("Fran\u{e7}ois".into()));
+    }
+
+    #[test]
+    fn test_binary_slice_lexer_matches() {
+        let input = [
+            0x00, 0x20, 0x01, 0x00, 0x03, 0x00, // key = {
+            0x0c, 0x00, 0x05, 0x00, 0x00, 0x00, // 5
+            0x0f, 0x00, 0x02, 0x00, b'h', b'i', // "hi"
+            0x04, 0x00, // }
+        ];
+        let mut reader = BinaryLexer::<TestGame, _>::new(&input[..],
+                                                         "input".into());
+        let mut slice = BinarySliceLexer::<TestGame>::new(&input,
+                                                         "input".into());
+        let tokens = all_tokens(&mut slice);
+        assert_eq!(all_tokens(&mut reader), tokens);
+        assert_eq!(tokens, vec![
+            Token::Atom("key".into()),
+            Token::Eq,
+            Token::LBrace,
+            Token::Integer(5),
+            Token::String("hi".into()),
+            Token::RBrace,
+        ]);
+        assert!(matches!(&tokens[4], Token::String(Cow::Borrowed(_))));
+    }
+
+    #[test]
+    fn test_typed_literals() {
+        let text = b"key = { 5 -1.5 yes 0.25000 1444.11.11 \"7\" 3000000000 }";
+        let binary = [
+            0x17, 0x00, 0x03, 0x00, b'k', b'e', b'y', // key
+            0x01, 0x00, 0x03, 0x00, // = {
+            0x0c, 0x00, 0x05, 0x00, 0x00, 0x00, // 5
+            0x0d, 0x00, 0x24, 0xfa, 0xff, 0xff, // -1.5
+            0x0e, 0x00, 0x01, // yes
+            0x67, 0x01, 0x00, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // 0.25
+            0x0c, 0x00, 0x10, 0x77, 0x5d, 0x03, // 1444.11.11
+            0x0f, 0x00, 0x01, 0x00, b'7', // "7"
+            0x14, 0x00, 0x00, 0x5e, 0xd0, 0xb2, // 3000000000
+            0x04, 0x00, // }
+        ];
+        let mut lexer = TextLexer::new(&text[..], "input".into())
+            .with_typed_literals(true);
+        let mut binary = BinarySliceLexer::<TestGame>::new(&binary,
+                                                          "input".into());
+        // Dates are only integers in the binary format.
+        let date = "1444.11.11".parse::<Date>().unwrap();
+        let mut tokens = all_tokens(&mut lexer);
+        assert_eq!(tokens[7], Token::Date(date));
+        tokens[7] = Token::Integer(date.to_binary());
+        assert_eq!(tokens, all_tokens(&mut binary));
+
+        let text = b"color = rgb { 1 2 3 } a=1.2.3.4 b=no- c=-1444.1.1 d=1.";
+        let mut lexer = TextSliceLexer::new(text, "input".into())
+            .with_typed_literals(true);
+        let tokens = all_tokens(&mut lexer);
+        assert_eq!(tokens[2], Token::Rgb);
+        assert_eq!(tokens[3..7], [Token::LBrace, Token::Integer(1),
+                   Token::Integer(2), Token::Integer(3)]);
+        assert_eq!(tokens[10], Token::Word("1.2.3.4".into()));
+        assert_eq!(tokens[13], Token::Word("no-".into()));
+        assert_eq!(tokens[16], Token::Word("-1444.1.1".into()));
+        assert_eq!(tokens[19], Token::Word("1.".into()));
+    }
+
+    #[test]
+    fn test_binary_codes() {
+        struct FloatGame;
+        impl GameTrait for FloatGame {
+            type Static = EmptyStaticAtomSet;
+            const BINARY_F32: BinaryF32 = BinaryF32::Float;
+            fn get_binary_token(_: u16) -> Option<Atom<Self::Static>> {
+                None
+            }
+        }
+
+        let input = [
+            0x00, 0x20, 0x01, 0x00, // key =
+            0x43, 0x02, 0x03, 0x00, 0x04, 0x00, // rgb { }
+            0x9c, 0x02, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+            0x17, 0x03, 0xfe, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+            0x40, 0x0d, 0x05, // lookup 5
+            0x44, 0x0d, 0x01, 0x02, 0x03, // lookup 0x030201
+            0x01, 0x20, 0x06, 0x00, // unknown tokens
+            0x0d, 0x00, 0x00, 0x00, 0xc0, 0x3f, // 1.5
+        ];
+        let mut lexer = BinarySliceLexer::<TestGame>::new(&input,
+                                                         "input".into());
+        assert_eq!(all_tokens(&mut lexer), vec![
+            Token::Atom("key".into()),
+            Token::Eq,
+            Token::Rgb,
+            Token::LBrace,
+            Token::RBrace,
+            Token::UnsignedLong(u64::MAX),
+            Token::Long(-2),
+            Token::Lookup(0x0d40, 5),
+            Token::Lookup(0x0d44, 0x030201),
+            Token::Unknown(0x2001),
+            Token::Unknown(0x0006),
+            Token::Fixed(FixedPoint(0x3fc00000)),
+        ]);
+
+        let mut lexer = BinarySliceLexer::<FloatGame>::new(&input[input.len() - 6..],
+                                                          "input".into());
+        let token = Lexer::<FloatGame>::get_token(&mut lexer).unwrap();
+        assert_eq!(token, Some(Token::Float(1.5)));
+    }
+}
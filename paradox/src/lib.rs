@@ -5,28 +5,59 @@
 //! The utilities provided are:
 //! * A representation of the date system used in the games.
 //! * An implementation of the fixed-point arithmetic they use.
-//! * A parser for their internal format.
+//! * A parser for their internal format, which can collect diagnostics for
+//!   input that doesn't match the types being parsed, and a writer to produce
+//!   it again.
+//! * A generic document tree for files without a typed representation, and a
+//!   query language to pull values out of it.
+//! * Conversion of saves and game files into JSON.
+//! * A description of the types that are derived, for tools that need to know
+//!   the format of the files.
 
-// Set up #[derive(ParadoxParse)] support.
+// Set up #[derive(ParadoxParse)], #[derive(ParadoxWrite)],
+// #[derive(ParadoxMerge)] and #[derive(ParadoxSchema)] support.
 #[allow(unused_imports)]
 #[macro_use]
 extern crate paradox_derive;
 pub use paradox_derive::*;
 
+// The derives refer to `paradox::`, so let them be used in this crate's tests.
+#[cfg(test)]
+extern crate self as paradox;
+
 mod date;
+mod diagnostics;
+mod encoding;
 mod fixed;
 mod game;
+mod json;
 mod lexer;
+mod multimap;
 mod parser;
 mod parser_impl;
+mod query;
 mod save;
+mod schema;
+mod tokens;
+mod value;
+mod writer;
+mod writer_impl;
 
 pub use date::*;
+pub use diagnostics::*;
+pub use encoding::*;
 pub use fixed::*;
 pub use game::*;
+pub use json::*;
 pub use lexer::*;
+pub use multimap::*;
 pub use parser::*;
+pub use query::*;
 pub use save::*;
+pub use schema::*;
+pub use tokens::*;
+pub use value::*;
+pub use writer::*;
 
 use std::path::PathBuf;
 use string_cache::{Atom, StaticAtomSet};
@@ -66,7 +97,26 @@ pub fn get_default_save_dir() -> PathBuf {
 }
 
 pub trait GameTrait {
-    type Static: StaticAtomSet;
+    type Static: StaticAtomSet + Send + Sync;
+
+    /// The encoding used for strings in the game's saves and data files.
+    const ENCODING: Encoding = Encoding::Windows1252;
+
+    /// How 32-bit decimals are stored in the game's binary files.
+    const BINARY_F32: BinaryF32 = BinaryF32::Fixed;
 
     fn get_binary_token(id: u16) -> Option<Atom<Self::Static>>;
+
+    /// The inverse of `get_binary_token`: find the ID of a binary token from
+    /// its name.
+    fn get_binary_id(_name: &str) -> Option<u16> {
+        None
+    }
+
+    /// Whether the values of a key are dates. The binary format stores dates
+    /// as plain integers, so this is needed to turn them back into dates when
+    /// the type of the file doesn't say.
+    fn is_date_key(key: &str) -> bool {
+        key == "date" || key.ends_with("_date")
+    }
 }
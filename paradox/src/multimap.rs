@@ -0,0 +1,133 @@
+use crate::{DiagnosticKind, GameTrait, Operator, ParadoxKey, ParadoxParse};
+use crate::{ParadoxWrite, ParseError, Parser, Writer};
+use std::fmt::Display;
+use std::iter::FromIterator;
+
+/// A map that keeps every entry of a `key = value` block in order, including
+/// keys that are defined more than once.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MultiMap<K, V> {
+    entries: Vec<(K, V)>
+}
+
+impl <K, V> Default for MultiMap<K, V> {
+    fn default() -> Self {
+        Self { entries: Vec::new() }
+    }
+}
+
+impl <K: PartialEq, V> MultiMap<K, V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, key: K, value: V) {
+        self.entries.push((key, value));
+    }
+
+    /// Get the first value for a key.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.get_all(key).next()
+    }
+
+    /// Get the last value for a key, which is the one the games use when a
+    /// key is defined more than once.
+    pub fn get_last(&self, key: &K) -> Option<&V> {
+        self.get_all(key).last()
+    }
+
+    /// Get all the values for a key, in order.
+    pub fn get_all<'s, 'k>(&'s self, key: &'k K)
+            -> impl Iterator<Item = &'s V> + 'k
+        where 's: 'k
+    {
+        self.entries.iter()
+            .filter(move |(k, _)| k == key)
+            .map(|(_, value)| value)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.entries.iter().any(|(k, _)| k == key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, (K, V)> {
+        self.entries.iter()
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.entries.iter().map(|(key, _)| key)
+    }
+}
+
+impl <K, V> Extend<(K, V)> for MultiMap<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        self.entries.extend(iter);
+    }
+}
+
+impl <K, V> FromIterator<(K, V)> for MultiMap<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Self { entries: iter.into_iter().collect() }
+    }
+}
+
+impl <K, V> IntoIterator for MultiMap<K, V> {
+    type Item = (K, V);
+    type IntoIter = std::vec::IntoIter<(K, V)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl <'a, K, V> IntoIterator for &'a MultiMap<K, V> {
+    type Item = &'a (K, V);
+    type IntoIter = std::slice::Iter<'a, (K, V)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter()
+    }
+}
+
+impl <G, K, V> ParadoxParse<G> for MultiMap<K, V>
+    where G: GameTrait, K: ParadoxKey<G>, V: ParadoxParse<G> + Default
+{
+    fn read(&mut self, parser: &mut Parser<G>) -> Result<(), ParseError> {
+        let class_name = std::any::type_name::<Self>();
+        parser.parse_key_scope(|key, op, parser| {
+            if op != Operator::Eq {
+                parser.validation_error(DiagnosticKind::InvalidValue,
+                    class_name, &key, &format!("unexpected operator {}", op),
+                    false, None)?;
+            }
+            let mut value = V::default();
+            value.read(parser)?;
+            self.entries.push((K::from_key(key)?, value));
+            Ok(())
+        })
+    }
+}
+
+impl <G, K, V> ParadoxWrite<G> for MultiMap<K, V>
+    where G: GameTrait, K: Display, V: ParadoxWrite<G>
+{
+    fn write(&self, writer: &mut Writer<G>) -> Result<(), ParseError> {
+        writer.write_key_scope(|writer| {
+            self.entries.iter().try_for_each(|(key, value)| {
+                writer.write_field(&key.to_string(), value)
+            })
+        })
+    }
+
+    fn is_default(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
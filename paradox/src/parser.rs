@@ -1,11 +1,12 @@
-use crate::{GameTrait, Lexer, TextLexer, Token};
-use std::fs::File;
+use crate::{Diagnostic, DiagnosticKind, Diagnostics, GameTrait, Lexer};
+use crate::{Operator, ParseOptions, Span, TextSliceLexer, Token, ValidationMode};
+use crate::value::color_space;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
 use std::path::Path;
 use thiserror::Error;
 use string_cache::{Atom, StaticAtomSet};
 
-const ERR_ON_INVALID_INPUT : bool = false;
-
 type Result<T> = std::result::Result<T, ParseError>;
 
 // There's no good documentation on Paradox's file format here. Most of this
@@ -14,30 +15,45 @@ type Result<T> = std::result::Result<T, ParseError>;
 // between games (and since I don't own all of them, I can't test all of the
 // issues here).
 
-impl <Static: StaticAtomSet> Token<Static> {
+impl <Static: StaticAtomSet> Token<'_, Static> {
     /// Convert the token into a string if it can be done.
     pub fn try_to_string(&self) -> Result<&str> {
         match self {
-            Self::String(s) => Ok(&s),
-            Self::Atom(s) => Ok(&s),
+            Self::String(s) | Self::Word(s) => Ok(s),
+            Self::Atom(s) => Ok(s),
+            Self::Rgb => Ok("rgb"),
+            Self::Hsv => Ok("hsv"),
             t => Err(t.clone().into())
         }
     }
 }
 
-impl <S: StaticAtomSet, Static: StaticAtomSet> From<Token<S>> for Atom<Static> {
-    fn from(t: Token<S>) -> Self {
-        match t {
-            Token::LBrace | Token::RBrace | Token::Eq =>
-                panic!("Shouldn't call this method if it's not a simple value"),
-            Token::String(s) => Self::from(s),
+/// Convert a value token into an atom, such as a key. Braces and operators
+/// aren't values, so they are an error.
+impl <S: StaticAtomSet, Static: StaticAtomSet> TryFrom<Token<'_, S>> for Atom<Static> {
+    type Error = ParseError;
+
+    fn try_from(t: Token<'_, S>) -> Result<Self> {
+        Ok(match t {
+            Token::LBrace | Token::RBrace | Token::Eq | Token::Lt |
+                Token::Le | Token::Gt | Token::Ge | Token::Ne | Token::QEq =>
+                return Err(t.into()),
+            Token::String(s) | Token::Word(s) => Self::from(s),
             Token::Atom(s) => Self::from(s.as_ref()),
+            Token::Rgb => Self::from("rgb"),
+            Token::Hsv => Self::from("hsv"),
             Token::Bool(b) => Self::from(if b { "yes" } else { "no" }),
             Token::Fixed(f) => f.to_string().into(),
             Token::Float(f) => f.to_string().into(),
             Token::Integer(i) => i.to_string().into(),
-            Token::Unsigned(i) => i.to_string().into()
-        }
+            Token::Unsigned(i) => i.to_string().into(),
+            Token::Long(i) => i.to_string().into(),
+            Token::UnsignedLong(i) => i.to_string().into(),
+            Token::Date(date) => date.to_string().into(),
+            Token::Lookup(code, i) =>
+                format!("__lookup_0x{:04x}_{}", code, i).into(),
+            Token::Unknown(code) => format!("__unknown_0x{:04x}", code).into()
+        })
     }
 }
 
@@ -45,189 +61,592 @@ pub trait ParadoxParse<G: GameTrait> {
     fn read(&mut self, parser: &mut Parser<G>) -> Result<()>;
 }
 
+/// Types that can be parsed in pieces and then combined, such as a save game
+/// whose files are parsed in parallel.
+///
+/// Merging is field by field: lists and maps are extended, and other fields
+/// are replaced if they were set in `other`.
+pub trait ParadoxMerge {
+    fn merge(&mut self, other: Self);
+
+    /// Merge a piece that was parsed from input with the given fields at its
+    /// top level (see [`Parser::parse_partial`]). Fields are replaced if they
+    /// were in the input, even if their value is the default, which is what
+    /// parsing all of the pieces in order would have done.
+    fn merge_seen(&mut self, other: Self, seen: &SeenFields) where Self: Sized {
+        let _ = seen;
+        self.merge(other);
+    }
+}
+
+/// Types that can be used as the keys of maps, which are read from the keys
+/// of `key = value` blocks.
+pub trait ParadoxKey<G: GameTrait>: Sized {
+    fn from_key(key: Atom<G::Static>) -> Result<Self>;
+}
+
+/// The fields of a struct, which can be read as part of the block of another
+/// struct that flattens it with `#[paradox(flatten)]`. This is derived along
+/// with `ParadoxParse`.
+pub trait ParadoxParseFields<G: GameTrait> {
+    /// Read the value of `key` if it is one of the fields, and return whether
+    /// it was.
+    fn read_field(&mut self, key: &Atom<G::Static>, op: Operator,
+                  parser: &mut Parser<G>, seen: &mut SeenFields)
+        -> Result<bool>;
+
+    /// Called at the end of the block, to fill in or report the fields that
+    /// weren't in it.
+    fn finish_fields(&mut self, parser: &mut Parser<G>, seen: &SeenFields)
+        -> Result<()>;
+}
+
+/// The fields that have been read in a block, so that fields which are
+/// duplicated or missing can be found across flattened structs.
+#[derive(Debug, Default)]
+pub struct SeenFields {
+    fields: Vec<(&'static str, &'static str)>
+}
+
+impl SeenFields {
+    /// Mark a field of a type as seen, returning false if it already was.
+    pub fn insert(&mut self, class_name: &'static str, field: &'static str)
+            -> bool {
+        if self.contains(class_name, field) {
+            return false;
+        }
+        self.fields.push((class_name, field));
+        true
+    }
+
+    pub fn contains(&self, class_name: &'static str, field: &'static str)
+            -> bool {
+        self.fields.contains(&(class_name, field))
+    }
+
+    /// Add the fields seen in another piece of the same block.
+    pub fn extend(&mut self, other: SeenFields) {
+        for (class_name, field) in other.fields {
+            self.insert(class_name, field);
+        }
+    }
+}
+
+/// Where an error happened: the span of the token being read, and the path of
+/// keys leading to it, like `countries/FRA/army[3]/regiment`.
+///
+/// Errors are created without a location, and the parser fills it in as the
+/// error is returned.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ErrorLocation {
+    pub span: Option<Span>,
+    pub path: String
+}
+
+impl ErrorLocation {
+    /// A location with a span but no path.
+    pub fn at(span: Span) -> Self {
+        Self { span: Some(span), path: String::new() }
+    }
+}
+
+impl std::fmt::Display for ErrorLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if !self.path.is_empty() {
+            write!(f, " at {}", self.path)?;
+        }
+        if let Some(span) = &self.span {
+            write!(f, " ({})", span)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum ParseError {
-    #[error("error reading file")]
-    Io(#[from] std::io::Error),
-    #[error("lexing error: {0}")]
-    Lexer(String),
-    #[error("unexpected token: {0}")]
-    Parse(String),
-    #[error("unexpected eof")]
-    Eof,
-    #[error("error reading type")]
-    Conversion(#[from] Box<dyn std::error::Error + 'static>),
-    #[error("value error: {0}")]
-    Constraint(String)
+    #[error("error reading file{1}")]
+    Io(#[source] std::io::Error, ErrorLocation),
+    #[error("lexing error: {0}{1}")]
+    Lexer(String, ErrorLocation),
+    #[error("unexpected token: {0}{1}")]
+    Parse(String, ErrorLocation),
+    #[error("unexpected eof{0}")]
+    Eof(ErrorLocation),
+    #[error("error reading type{1}")]
+    Conversion(#[source] Box<dyn std::error::Error + Send + Sync + 'static>,
+               ErrorLocation),
+    #[error("value error: {0}{1}")]
+    Constraint(String, ErrorLocation),
+    /// More tokens were put back or peeked at than [`MAX_LOOKAHEAD`].
+    #[error("can only look ahead {} tokens{0}", MAX_LOOKAHEAD)]
+    Lookahead(ErrorLocation)
+}
+
+impl ParseError {
+    /// An unexpected end of file, with no location yet.
+    pub fn eof() -> Self {
+        Self::Eof(ErrorLocation::default())
+    }
+
+    /// A value that doesn't meet the requirements of the type being read, with
+    /// no location yet.
+    pub fn constraint(msg: String) -> Self {
+        Self::Constraint(msg, ErrorLocation::default())
+    }
+
+    pub fn location(&self) -> &ErrorLocation {
+        match self {
+            Self::Io(_, location) | Self::Lexer(_, location) |
+                Self::Parse(_, location) | Self::Eof(location) |
+                Self::Conversion(_, location) |
+                Self::Constraint(_, location) |
+                Self::Lookahead(location) => location
+        }
+    }
+
+    pub(crate) fn location_mut(&mut self) -> &mut ErrorLocation {
+        match self {
+            Self::Io(_, location) | Self::Lexer(_, location) |
+                Self::Parse(_, location) | Self::Eof(location) |
+                Self::Conversion(_, location) |
+                Self::Constraint(_, location) |
+                Self::Lookahead(location) => location
+        }
+    }
 }
 
-impl <S: StaticAtomSet> From<Token<S>> for ParseError {
-    fn from(t: Token<S>) -> Self {
-        Self::Parse(format!("{:?}", t))
+impl From<std::io::Error> for ParseError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err, ErrorLocation::default())
     }
 }
 
+impl From<Box<dyn std::error::Error + Send + Sync + 'static>> for ParseError {
+    fn from(err: Box<dyn std::error::Error + Send + Sync + 'static>) -> Self {
+        Self::Conversion(err, ErrorLocation::default())
+    }
+}
+
+impl <S: StaticAtomSet> From<Token<'_, S>> for ParseError {
+    fn from(t: Token<'_, S>) -> Self {
+        Self::Parse(format!("{:?}", t), ErrorLocation::default())
+    }
+}
+
+/// One step in the path of keys to the value being parsed.
+enum PathSegment<Static: StaticAtomSet> {
+    /// A key, and how many times in a row it has been repeated before.
+    Key(Atom<Static>, u32),
+    /// An index into a list.
+    Index(usize)
+}
+
+/// The number of tokens the parser can look ahead, with [`Parser::peek_n`] or
+/// by putting tokens back with [`Parser::unget`].
+pub const MAX_LOOKAHEAD: usize = 8;
+
 pub struct Parser<'a, G: GameTrait> {
-    lexer: &'a mut dyn Lexer<G>,
+    lexer: &'a mut dyn Lexer<'a, G>,
     depth: u32,
-    saved_token: Option<Token<G::Static>>,
+    /// Tokens that have been read from the lexer but not yet consumed.
+    lookahead: VecDeque<Token<'a, G::Static>>,
     game_data: &'a mut crate::GameData,
-    //scope: Vec<Atom<G::Static>>,
+    path: Vec<PathSegment<G::Static>>,
+    partial_input: bool,
+    /// The fields seen at the top level of partial input.
+    seen_fields: SeenFields,
+    options: ParseOptions,
+    diagnostics: Diagnostics,
 }
 
 impl <'a, G: GameTrait> Parser<'a, G> {
-    pub fn new(lexer: &'a mut dyn Lexer<G>,
+    pub fn new(lexer: &'a mut dyn Lexer<'a, G>,
                game_data: &'a mut crate::GameData) -> Self {
         Self {
-            lexer, depth: 0, saved_token: None, game_data,
-            //scope: Vec::new()
+            lexer, depth: 0, lookahead: VecDeque::new(), game_data,
+            path: Vec::new(), partial_input: false,
+            seen_fields: SeenFields::default(),
+            options: ParseOptions::default(),
+            diagnostics: Diagnostics::default()
+        }
+    }
+
+    /// Set the options that control how invalid input is handled.
+    pub fn with_options(mut self, options: ParseOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Set whether the input is only part of the value being parsed, as when
+    /// the files of a save are parsed separately. Fields missing from the top
+    /// level are not reported then, as they may be in another part.
+    pub fn with_partial_input(mut self, partial_input: bool) -> Self {
+        self.partial_input = partial_input;
+        self
+    }
+
+    /// Whether fields missing at the current level should be reported.
+    pub fn check_missing_fields(&self) -> bool {
+        !(self.partial_input && self.depth == 0)
+    }
+
+    /// Get the path of keys to the value currently being parsed, like
+    /// `countries/FRA/army[3]/regiment`. Keys that are repeated in a row are
+    /// numbered from their second occurrence.
+    pub fn get_path(&self) -> String {
+        let mut path = String::new();
+        for segment in &self.path {
+            match segment {
+                PathSegment::Key(key, 0) => {
+                    if !path.is_empty() {
+                        path.push('/');
+                    }
+                    path.push_str(key);
+                },
+                PathSegment::Key(key, count) => {
+                    if !path.is_empty() {
+                        path.push('/');
+                    }
+                    path.push_str(&format!("{}[{}]", key, count));
+                },
+                PathSegment::Index(index) =>
+                    path.push_str(&format!("[{}]", index)),
+            }
+        }
+        path
+    }
+
+    /// Add the current location to an error, unless it already has one.
+    pub fn locate(&self, mut err: ParseError) -> ParseError {
+        let path = self.get_path();
+        let location = err.location_mut();
+        if location.span.is_none() {
+            location.span = Some(self.lexer.get_span());
+        }
+        if location.path.is_empty() {
+            location.path = path;
         }
+        err
     }
 
+    /// Return an error at the current location.
+    fn error<T>(&self, err: ParseError) -> Result<T> {
+        Err(self.locate(err))
+    }
+
+    /// Parse a block of `key = value` entries, calling `func` for each key.
+    /// The callback is responsible for reading the value.
+    ///
+    /// Keys may also be separated from their values by comparison operators
+    /// like `<` or `!=`, which are passed to the callback. If there is no
+    /// operator (as in `key { ... }`), it is treated as `=`.
     pub fn parse_key_scope<F>(&mut self, mut func: F) -> Result<()>
-        where F: FnMut(Atom<G::Static>, &mut Self) -> Result<()>
+        where F: FnMut(Atom<G::Static>, Operator, &mut Self) -> Result<()>
     {
         let is_top = self.depth == 0;
         if !is_top {
             match self.get_token()? {
                 Some(Token::LBrace) => {},
-                None => return Err(ParseError::Eof),
-                Some(t) => return Err(t.into()),
+                None => return self.error(ParseError::eof()),
+                Some(t) => return self.error(t.into()),
             }
         }
         self.depth += 1;
+        let mut last_key = None;
         let hit_eof = loop {
             let key = match self.get_token()? {
                 Some(Token::RBrace) => break false,
                 None => break true,
-                Some(t) => Atom::from(t),
+                Some(t) => match Atom::try_from(t) {
+                    Ok(key) => key,
+                    Err(err) => return self.error(err),
+                },
             };
-            match self.get_token()? {
-                Some(Token::Eq) => {},
+            let op = match self.get_token()? {
                 Some(Token::LBrace) => {
-                    self.unget(Token::LBrace);
+                    self.unget(Token::LBrace)?;
+                    Operator::Eq
+                },
+                None => return self.error(ParseError::eof()),
+                Some(t) => match t.as_operator() {
+                    Some(op) => op,
+                    None => return self.error(t.into()),
                 },
-                None => return Err(ParseError::Eof),
-                Some(t) => return Err(t.into()),
+            };
+            let repeats = match last_key {
+                Some((ref last, count)) if *last == key => count + 1,
+                _ => 0
+            };
+            self.path.push(PathSegment::Key(key.clone(), repeats));
+            let result = func(key, op, self).map_err(|e| self.locate(e));
+            if let Some(PathSegment::Key(key, count)) = self.path.pop() {
+                last_key = Some((key, count));
             }
-            //self.scope.push(key.clone());
-            //println!("Parsing {}", self.scope.join("/"));
-            func(key, self)?;
-            //self.scope.pop();
+            result?;
         };
         self.depth -= 1;
         match (hit_eof, is_top) {
             (true, true) | (false, false) => Ok(()),
-            (true, false) => Err(ParseError::Eof),
-            (false, true) => Err(Token::<G::Static>::RBrace.into()),
+            (true, false) => self.error(ParseError::eof()),
+            (false, true) => self.error(Token::<G::Static>::RBrace.into()),
         }
     }
 
     pub fn with_scope<F>(&mut self, mut func: F) -> Result<()>
         where F: FnMut(&mut Self) -> Result<()>
     {
-        match self.get_token()? {
+        let mut token = self.get_token()?;
+        // Colors may have an rgb or hsv prefix before the list of components.
+        if token.as_ref().is_some_and(|token| color_space(token).is_some()) &&
+                matches!(self.peek()?, Some(Token::LBrace)) {
+            token = self.get_token()?;
+        }
+        match token {
             Some(Token::LBrace) => {},
-            None => return Err(ParseError::Eof),
-            Some(t) => return Err(t.into()),
+            None => return self.error(ParseError::eof()),
+            Some(t) => return self.error(t.into()),
         }
         self.depth += 1;
-        //self.scope.push("(with_scope)".into());
+        let mut index = 0;
         loop {
-            match self.get_token()? {
-                Some(Token::RBrace) => break,
-                None => break,
-                Some(t) => self.unget(t),
+            match self.peek()? {
+                Some(Token::RBrace) => {
+                    self.get_token()?;
+                    break;
+                },
+                None => return self.error(ParseError::eof()),
+                Some(_) => {},
             };
-            func(self)?;
+            self.path.push(PathSegment::Index(index));
+            let result = func(self).map_err(|e| self.locate(e));
+            self.path.pop();
+            result?;
+            index += 1;
         }
-        //self.scope.pop();
         self.depth -= 1;
         Ok(())
     }
 
+    /// Whether the parser is at the top level of the file, outside of any
+    /// braces.
+    pub fn is_top_level(&self) -> bool {
+        self.depth == 0
+    }
+
     pub fn get_game_data(&mut self) -> &mut crate::GameData {
         self.game_data
     }
 
     pub fn parse(mut self, result: &mut dyn ParadoxParse<G>) -> Result<()> {
-        result.read(&mut self)
-            .or_else(|err| {
-                eprintln!("Error at {}", self.lexer.get_location_info());
-                Err(err)
-            })
-    }
-
-    pub fn get_token(&mut self) -> Result<Option<Token<G::Static>>> {
-        if self.saved_token.is_some() {
-            Ok(self.saved_token.take())
-        } else {
-            self.lexer.get_token()
+        result.read(&mut self).map_err(|e| self.locate(e))
+    }
+
+    /// Parse the input, and return the diagnostics for any invalid input that
+    /// was skipped over.
+    pub fn parse_with_diagnostics(mut self, result: &mut dyn ParadoxParse<G>)
+            -> Result<Diagnostics> {
+        result.read(&mut self).map_err(|e| self.locate(e))?;
+        Ok(self.diagnostics)
+    }
+
+    /// Parse partial input (see [`Parser::with_partial_input`]), and return
+    /// the fields that were seen at the top level along with the diagnostics.
+    /// Once all of the pieces have been merged with
+    /// [`ParadoxMerge::merge_seen`], the missing fields can be checked with
+    /// [`ParadoxParseFields::finish_fields`] and the combined fields.
+    pub fn parse_partial(mut self, result: &mut dyn ParadoxParse<G>)
+            -> Result<(Diagnostics, SeenFields)> {
+        result.read(&mut self).map_err(|e| self.locate(e))?;
+        Ok((self.diagnostics, self.seen_fields))
+    }
+
+    /// Finish reading a block of fields, checking for the ones that were
+    /// missing. At the top level of partial input, the fields are kept for
+    /// [`Parser::parse_partial`] instead. This is called by the derived
+    /// `read`.
+    pub fn finish_fields(&mut self, fields: &mut dyn ParadoxParseFields<G>,
+                         seen: SeenFields) -> Result<()> {
+        if self.partial_input && self.depth == 0 {
+            self.seen_fields.extend(seen);
+            return Ok(());
+        }
+        fields.finish_fields(self, &seen)
+    }
+
+    pub fn get_token(&mut self) -> Result<Option<Token<'a, G::Static>>> {
+        match self.lookahead.pop_front() {
+            Some(token) => Ok(Some(token)),
+            None => self.lexer.get_token().map_err(|e| self.locate(e))
+        }
+    }
+
+    /// Put a token back, so that it is the next one returned. Up to
+    /// [`MAX_LOOKAHEAD`] tokens can be put back or peeked at once; beyond that
+    /// this returns [`ParseError::Lookahead`].
+    pub fn unget(&mut self, token: Token<'a, G::Static>) -> Result<()> {
+        if self.lookahead.len() >= MAX_LOOKAHEAD {
+            return self.error(ParseError::Lookahead(Default::default()));
+        }
+        self.lookahead.push_front(token);
+        Ok(())
+    }
+
+    /// Look at the next token without consuming it.
+    pub fn peek(&mut self) -> Result<Option<&Token<'a, G::Static>>> {
+        self.peek_n(0)
+    }
+
+    /// Look at the token `n` places ahead without consuming it, so that
+    /// `peek_n(0)` is the next token. `n` must be less than [`MAX_LOOKAHEAD`],
+    /// or this returns [`ParseError::Lookahead`].
+    pub fn peek_n(&mut self, n: usize)
+            -> Result<Option<&Token<'a, G::Static>>> {
+        if n >= MAX_LOOKAHEAD {
+            return self.error(ParseError::Lookahead(Default::default()));
+        }
+        while self.lookahead.len() <= n {
+            match self.lexer.get_token().map_err(|e| self.locate(e))? {
+                Some(token) => self.lookahead.push_back(token),
+                None => return Ok(None)
+            }
+        }
+        Ok(self.lookahead.get(n))
+    }
+
+    /// Whether the next value is a block of `key = value` entries, like
+    /// `{ a = 1 }`, as opposed to a list or a single value.
+    pub fn is_object_next(&mut self) -> Result<bool> {
+        if !matches!(self.peek()?, Some(Token::LBrace)) {
+            return Ok(false);
         }
+        let is_key = match self.peek_n(1)? {
+            Some(Token::LBrace) | Some(Token::RBrace) | None => false,
+            Some(token) => color_space(token).is_none() &&
+                token.as_operator().is_none(),
+        };
+        Ok(is_key && match self.peek_n(2)? {
+            Some(Token::LBrace) => true,
+            Some(token) => token.as_operator().is_some(),
+            None => false
+        })
     }
 
-    pub fn unget(&mut self, token: Token<G::Static>) {
-        assert!(self.saved_token.is_none(), "Can only save one token");
-        self.saved_token = Some(token);
+    /// Whether the next value is a block that isn't an object, like `{ 1 2 }`
+    /// or `{ { a = 1 } }`. Empty blocks count as lists.
+    pub fn is_array_next(&mut self) -> Result<bool> {
+        Ok(matches!(self.peek()?, Some(Token::LBrace)) &&
+           !self.is_object_next()?)
     }
 
-    pub fn validation_error(&mut self, class_name: &'static str, field: &str,
+    /// Skip over the next value, whether it is a single token, a block or a
+    /// color like `rgb { 1 2 3 }`.
+    pub fn skip_value(&mut self) -> Result<()> {
+        let token = self.get_token()?.ok_or_else(ParseError::eof)?;
+        let mut depth = match token {
+            Token::LBrace => 1,
+            Token::RBrace => return self.error(token.into()),
+            token if color_space(&token).is_some() => {
+                if !matches!(self.peek()?, Some(Token::LBrace)) {
+                    return Ok(());
+                }
+                0
+            },
+            _ => return Ok(()),
+        };
+        loop {
+            match self.get_token()? {
+                Some(Token::LBrace) => depth += 1,
+                Some(Token::RBrace) if depth <= 1 => return Ok(()),
+                Some(Token::RBrace) => depth -= 1,
+                Some(_) => {},
+                None => return self.error(ParseError::eof()),
+            }
+        }
+    }
+
+    /// Report input that doesn't match the type being parsed. This is an
+    /// error if `fatal` is set or the parser is in strict mode. Otherwise,
+    /// `value` (the start of the value of an invalid field, if any) is skipped
+    /// over, and in lenient mode a diagnostic is recorded.
+    pub fn validation_error(&mut self, kind: DiagnosticKind,
+                            class_name: &'static str, field: &str,
                             message: &str, fatal: bool,
-                            value: Option<Token<G::Static>>) -> Result<()> {
+                            value: Option<Token<'a, G::Static>>) -> Result<()> {
         let type_hint = match value {
             Some(Token::LBrace) => " (scope)",
             Some(Token::Integer(_)) => " (i32)",
             Some(Token::Unsigned(_)) => " (u32)",
+            Some(Token::Long(_)) => " (i64)",
+            Some(Token::UnsignedLong(_)) => " (u64)",
+            Some(Token::Date(_)) => " (Date)",
             Some(Token::Float(_)) => " (f64)",
             Some(Token::Fixed(_)) => " (FixedPoint)",
             Some(Token::Bool(_)) => " (bool)",
-            Some(Token::String(_)) => " (String)",
-            Some(Token::Atom(_)) => " (String)",
+            Some(Token::String(_)) | Some(Token::Word(_)) |
+                Some(Token::Atom(_)) => " (String)",
             _ => "",
         };
-        let msg = format!("{}/{}{}: {}", class_name, field, type_hint, message);
-        if fatal || ERR_ON_INVALID_INPUT {
-            Err(ParseError::Constraint(msg))
-        } else {
-            println!("warning: {}", msg);
-            if let Some(value) = value {
-                self.unget(value);
-                let mut discard = ();
-                discard.read(self)?;
-            }
-            Ok(())
+        if fatal || self.options.mode == ValidationMode::Strict {
+            let msg = format!("{}/{}{}: {}", class_name, field, type_hint,
+                              message);
+            return self.error(ParseError::constraint(msg));
+        }
+
+        if self.options.mode == ValidationMode::Lenient {
+            self.diagnostics.push(Diagnostic {
+                kind,
+                class: class_name,
+                field: field.to_owned(),
+                message: format!("{}{}", message, type_hint),
+                location: ErrorLocation {
+                    span: Some(self.lexer.get_span()),
+                    path: self.get_path()
+                }
+            });
+        }
+        if let Some(value) = value {
+            self.unget(value)?;
+            self.skip_value()?;
         }
+        Ok(())
     }
 }
 
 pub type ParserAtom<T> = Atom<<T as GameTrait>::Static>;
 
-/// Load an entire directory of parseable files.
+/// Load an entire directory of parseable files, and return the diagnostics
+/// for the input that was skipped over, including any entries of the
+/// directory that aren't `.txt` files.
 ///
 /// All of the entries will be loaded in alphabetical order.
 pub fn load_directory<G: GameTrait>(
     path: &Path, data: &mut dyn ParadoxParse<G>,
-    gamedata: &mut crate::GameData) -> Result<()>
+    gamedata: &mut crate::GameData) -> Result<Diagnostics>
 {
+    let mut diagnostics = Diagnostics::default();
     let mut files : Vec<_> = Default::default();
     if path.is_dir() {
         for entry in path.read_dir()? {
             let entry = entry?;
             let path = entry.path();
-            if !entry.metadata()?.is_file() {
-                eprintln!("Unexpected non-file in directory: {}",
-                          path.display());
-                continue;
-            } else if path.extension().is_none() {
-                eprintln!("Unexpected non-txt file in directory: {}",
-                          path.display());
-                continue;
-            } else if path.extension().unwrap() != "txt" {
-                eprintln!("Unexpected non-txt file in directory: {}",
-                          path.display());
+            let message = if !entry.metadata()?.is_file() {
+                "unexpected non-file in directory"
+            } else if path.extension() != Some("txt".as_ref()) {
+                "unexpected non-txt file in directory"
+            } else {
+                files.push(path);
                 continue;
-            }
-            files.push(path);
+            };
+            diagnostics.push(Diagnostic {
+                kind: DiagnosticKind::UnexpectedFile,
+                class: "directory",
+                field: entry.file_name().to_string_lossy().into_owned(),
+                message: message.to_owned(),
+                location: ErrorLocation {
+                    span: None,
+                    path: path.display().to_string()
+                }
+            });
         }
         files.sort();
     } else {
@@ -235,71 +654,281 @@ pub fn load_directory<G: GameTrait>(
     }
     for path in files {
         let filename = path.to_string_lossy().into();
-        let file = File::open(path)?;
-        let mut lexer = TextLexer::new(file, filename);
-        Parser::new(&mut lexer, gamedata).parse(data)?;
+        let contents = std::fs::read(path)?;
+        let mut lexer = TextSliceLexer::new(&contents, filename)
+            .with_encoding(G::ENCODING);
+        diagnostics.extend(Parser::new(&mut lexer, gamedata)
+                           .parse_with_diagnostics(data)?);
     }
-    Ok(())
+    Ok(diagnostics)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{GameData, TextLexer};
     use std::collections::HashMap;
+    use string_cache::EmptyStaticAtomSet;
+
+    struct TestGame;
+    impl GameTrait for TestGame {
+        type Static = EmptyStaticAtomSet;
+        fn get_binary_token(_: u16) -> Option<Atom<Self::Static>> { None }
+    }
+
+    type TestToken = Token<'static, EmptyStaticAtomSet>;
 
     fn make_reader(input: &'static [u8]) -> TextLexer<&'static [u8]> {
         TextLexer::new(input, "input".into())
     }
 
-    fn check_tokens(mut lexer: impl Lexer, vec: Vec<Token>) {
+    fn check_tokens(mut lexer: impl Lexer<'static, TestGame>,
+                    vec: Vec<TestToken>) {
         for token in vec {
             assert_eq!(lexer.get_token().unwrap(), Some(token));
         }
         assert_eq!(lexer.get_token().unwrap(), None);
     }
 
+    fn parse_text(input: &'static [u8],
+                  result: &mut dyn ParadoxParse<TestGame>) -> Result<()> {
+        let mut game_data = GameData::load(Path::new("."))?;
+        let mut lexer = make_reader(input);
+        Parser::new(&mut lexer, &mut game_data).parse(result)
+    }
+
     #[test]
     fn test_lexer() {
         let lexer = make_reader(b"1.0#");
-        check_tokens(lexer, vec![Token::String("1.0".into())]);
+        check_tokens(lexer, vec![Token::Word("1.0".into())]);
 
         let lexer = make_reader(b"# This is a comment\n1.0");
-        check_tokens(lexer, vec![Token::String("1.0".into())]);
+        check_tokens(lexer, vec![Token::Word("1.0".into())]);
 
         let lexer = make_reader(b"-5={ 1} \"inner\"");
         check_tokens(lexer, vec![
-            Token::String("-5".into()),
+            Token::Word("-5".into()),
             Token::Eq,
             Token::LBrace,
-            Token::String("1".into()),
+            Token::Word("1".into()),
             Token::RBrace,
             Token::String("inner".into())
         ]);
     }
 
+    #[test]
+    fn test_error_locations() {
+        let mut res : HashMap<String, HashMap<String, i32>> = Default::default();
+        let err = parse_text(b"a = {\n  b = 1\n  c = x\n}", &mut res)
+            .unwrap_err();
+        assert!(matches!(err, ParseError::Conversion(..)));
+        assert_eq!(err.location().path, "a/c");
+        assert_eq!(err.location().span, Some(Span {
+            filename: "input".into(),
+            position: crate::Position::Text { line: 3, column: 7 }
+        }));
+        assert_eq!(err.to_string(), "error reading type at a/c (input:3:7)");
+
+        let mut res : HashMap<ParserAtom<TestGame>, Vec<i32>> =
+            Default::default();
+        let err = parse_text(b"a = { 1 } a = { 2 } a = { 3 x }", &mut res)
+            .unwrap_err();
+        assert_eq!(err.location().path, "a[2][1]");
+
+        let err = parse_text(b"a = { 1 ", &mut res).unwrap_err();
+        assert!(matches!(err, ParseError::Eof(_)));
+    }
+
+    #[test]
+    fn test_malformed_keys() {
+        let mut res : HashMap<String, Vec<(ParserAtom<TestGame>, i32)>> =
+            Default::default();
+        let err = parse_text(b"a = { { } }", &mut res).unwrap_err();
+        assert!(matches!(err, ParseError::Parse(..)));
+        assert_eq!(err.location().path, "a[0]");
+        assert!(err.location().span.is_some());
+
+        let mut res : HashMap<ParserAtom<TestGame>, i32> = Default::default();
+        let err = parse_text(b"a = 1\n= 2", &mut res).unwrap_err();
+        assert!(matches!(err, ParseError::Parse(..)));
+        assert_eq!(err.location().span, Some(Span {
+            filename: "input".into(),
+            position: crate::Position::Text { line: 2, column: 1 }
+        }));
+
+        let mut res : HashMap<String, ParserAtom<TestGame>> = Default::default();
+        let err = parse_text(b"a = <", &mut res).unwrap_err();
+        assert!(matches!(err, ParseError::Parse(..)));
+        assert_eq!(err.location().path, "a");
+    }
+
     #[test]
     fn test_parser() -> Result<()> {
         let mut res : HashMap<String, i32> = Default::default();
-        let lexer = make_reader(b"a=1 b=2");
-        Parser::new(Box::new(lexer))
-            .parse(&mut res)?;
+        parse_text(b"a=1 b=2", &mut res)?;
         assert_eq!(*res.get("a").unwrap(), 1);
         assert_eq!(*res.get("b").unwrap(), 2);
         assert_eq!(res.iter().len(), 2);
 
         let mut res : HashMap<String, Vec<i32>> = Default::default();
-        let lexer = make_reader(b"a={1 2 3}");
-        Parser::new(Box::new(lexer))
-            .parse(&mut res)?;
+        parse_text(b"a={1 2 3}", &mut res)?;
         assert_eq!(*res.get("a").unwrap(), vec![1, 2, 3]);
         assert_eq!(res.iter().len(), 1);
 
-        let mut res : Vec<Vec<Vec<i32>>> = Default::default();
-        let lexer = make_reader(b"{{1 2} {3 4} {5 6}}");
-        Parser::new(Box::new(lexer))
-            .parse(&mut res)?;
-        assert_eq!(res, vec![vec![vec![1, 2], vec![3, 4], vec![5, 6]]]);
+        let mut res : Vec<(String, Operator, i32)> = Default::default();
+        struct Comparisons<'a>(&'a mut Vec<(String, Operator, i32)>);
+        impl ParadoxParse<TestGame> for Comparisons<'_> {
+            fn read(&mut self, parser: &mut Parser<TestGame>) -> Result<()> {
+                parser.parse_key_scope(|key, op, parser| {
+                    let mut value = 0;
+                    value.read(parser)?;
+                    self.0.push((key.to_string(), op, value));
+                    Ok(())
+                })
+            }
+        }
+        parse_text(b"a > 1 b<=2 c=3", &mut Comparisons(&mut res))?;
+        assert_eq!(res, vec![
+            ("a".into(), Operator::Gt, 1),
+            ("b".into(), Operator::Le, 2),
+            ("c".into(), Operator::Eq, 3),
+        ]);
+
+        let mut res : Vec<Vec<i32>> = Default::default();
+        parse_text(b"{{1 2} {3 4} {5 6}}", &mut res)?;
+        assert_eq!(res, vec![vec![1, 2], vec![3, 4], vec![5, 6]]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_diagnostics() -> Result<()> {
+        #[derive(Default)]
+        struct Point { x: i32 }
+        impl ParadoxParse<TestGame> for Point {
+            fn read(&mut self, parser: &mut Parser<TestGame>) -> Result<()> {
+                let mut seen_x = false;
+                parser.parse_key_scope(|key, _, parser| {
+                    if &*key == "x" {
+                        seen_x = true;
+                        return self.x.read(parser);
+                    }
+                    let token = parser.get_token()?;
+                    parser.validation_error(DiagnosticKind::UnknownField,
+                                            "Point", &key, "unknown in struct",
+                                            false, token)
+                })?;
+                if !seen_x && parser.check_missing_fields() {
+                    parser.validation_error(DiagnosticKind::MissingField,
+                                            "Point", "x",
+                                            "not found in definition", false,
+                                            None)?;
+                }
+                Ok(())
+            }
+        }
+
+        let parse = |input: &'static [u8], options|
+                -> Result<(HashMap<String, Point>, Diagnostics)> {
+            let mut game_data = GameData::load(Path::new("."))?;
+            let mut lexer = make_reader(input);
+            let mut points = HashMap::new();
+            let diagnostics = Parser::new(&mut lexer, &mut game_data)
+                .with_options(options)
+                .parse_with_diagnostics(&mut points)?;
+            Ok((points, diagnostics))
+        };
+
+        let (points, diagnostics) = parse(b"a = { x = 1 y = { 2 } } b = { }",
+                                          ParseOptions::lenient())?;
+        assert_eq!(points["a"].x, 1);
+        let found: Vec<_> = diagnostics.iter()
+            .map(|d| (d.kind, d.field.as_str(), d.location.path.as_str()))
+            .collect();
+        assert_eq!(found, vec![
+            (DiagnosticKind::UnknownField, "y", "a/y"),
+            (DiagnosticKind::MissingField, "x", "b"),
+        ]);
+        assert_eq!(diagnostics.iter().next().unwrap().to_string(),
+                   "Point/y: unknown in struct (scope) at a/y (input:1:17)");
+
+        let (points, diagnostics) = parse(b"a = { x = 1 y = { 2 } } b = { }",
+                                          ParseOptions::silent())?;
+        assert_eq!(points["a"].x, 1);
+        assert!(diagnostics.is_empty());
+
+        let err = parse(b"a = { x = 1 y = 2 }", ParseOptions::strict())
+            .err().unwrap();
+        assert_eq!(err.to_string(),
+                   "value error: Point/y (String): unknown in struct at a/y \
+                    (input:1:17)");
+
+        let (points, diagnostics) = parse(b"a = { x = 1 } a = { x = 2 }",
+                                          ParseOptions::lenient())?;
+        assert_eq!(points["a"].x, 2);
+        assert_eq!(diagnostics.of_kind(DiagnosticKind::DuplicateField)
+                       .map(|d| d.field.as_str()).collect::<Vec<_>>(), ["a"]);
+
+        // Maps only take `=`.
+        let (points, diagnostics) = parse(b"a > { x = 1 }",
+                                          ParseOptions::lenient())?;
+        assert_eq!(points["a"].x, 1);
+        assert_eq!(diagnostics.of_kind(DiagnosticKind::InvalidValue)
+                       .map(|d| d.field.as_str()).collect::<Vec<_>>(), ["a"]);
+        assert!(parse(b"a > { x = 1 }", ParseOptions::strict()).is_err());
+
+        let mut game_data = GameData::load(Path::new("."))?;
+        let mut lexer = make_reader(b"a = 1 b < 2 a = 3");
+        let mut entries = crate::MultiMap::<String, i32>::default();
+        let diagnostics = Parser::<TestGame>::new(&mut lexer, &mut game_data)
+            .with_options(ParseOptions::lenient())
+            .parse_with_diagnostics(&mut entries)?;
+        assert_eq!(entries.len(), 3);
+        assert_eq!(diagnostics.of_kind(DiagnosticKind::InvalidValue)
+                       .map(|d| d.field.as_str()).collect::<Vec<_>>(), ["b"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lookahead() -> Result<()> {
+        let mut game_data = GameData::load(Path::new("."))?;
+        let mut lexer = make_reader(b"a = { b = 1 } c = { 1 2 } d = { } \
+                                      e = { { x = 1 } } f = rgb { 1 2 3 } g = h");
+        let mut parser = Parser::<TestGame>::new(&mut lexer, &mut game_data);
+        assert_eq!(parser.peek_n(2)?, Some(&Token::LBrace));
+        assert_eq!(parser.peek()?, Some(&Token::Word("a".into())));
+        assert_eq!(parser.get_token()?, Some(Token::Word("a".into())));
+        parser.unget(Token::Word("z".into()))?;
+        parser.unget(Token::Word("y".into()))?;
+        assert_eq!(parser.get_token()?, Some(Token::Word("y".into())));
+        assert_eq!(parser.get_token()?, Some(Token::Word("z".into())));
+        parser.unget(Token::Word("a".into()))?;
+
+        let mut shapes = Vec::new();
+        while parser.get_token()?.is_some() {
+            parser.get_token()?;
+            shapes.push((parser.is_object_next()?, parser.is_array_next()?));
+            parser.skip_value()?;
+        }
+        assert_eq!(shapes, vec![(true, false), (false, true), (false, true),
+                                (false, true), (false, false), (false, false)]);
+        assert_eq!(parser.peek()?, None);
+
+        let mut color: HashMap<String, Vec<u32>> = HashMap::new();
+        parse_text(b"color = rgb { 1 2 3 }", &mut color)?;
+        assert_eq!(color["color"], vec![1, 2, 3]);
 
+        let mut lexer = make_reader(b"a b c d e f g h i j");
+        let mut parser = Parser::<TestGame>::new(&mut lexer, &mut game_data);
+        assert_eq!(parser.peek_n(MAX_LOOKAHEAD - 1)?,
+                   Some(&Token::Word("h".into())));
+        assert!(matches!(parser.peek_n(MAX_LOOKAHEAD),
+                         Err(ParseError::Lookahead(_))));
+        assert!(matches!(parser.unget(Token::Word("z".into())),
+                         Err(ParseError::Lookahead(_))));
+        assert_eq!(parser.get_token()?, Some(Token::Word("a".into())));
+        parser.unget(Token::Word("z".into()))?;
+        assert_eq!(parser.get_token()?, Some(Token::Word("z".into())));
         Ok(())
     }
 }
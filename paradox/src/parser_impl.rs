@@ -1,14 +1,17 @@
-use crate::{Date, FixedPoint, GameTrait, ParserAtom, Token};
+use crate::{Date, DiagnosticKind, FixedPoint, GameTrait, Operator, ParserAtom};
+use crate::Token;
 use crate::parser::*;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::hash::Hash;
 use std::error::Error as StdError;
 use std::str::FromStr;
 use string_cache::{Atom, StaticAtomSet};
 
-type DynError = Box<dyn StdError>;
+type DynError = Box<dyn StdError + Send + Sync>;
 type ParseResult = Result<(), ParseError>;
 
-fn convert_err<T, E: StdError + 'static>(val: Result<T, E>)
+fn convert_err<T, E: StdError + Send + Sync + 'static>(val: Result<T, E>)
         -> Result<T, DynError> {
     val.map_err(|err| err.into())
 }
@@ -17,7 +20,7 @@ macro_rules! from_string {
     {$T:ty} => {
         impl <G: GameTrait> ParadoxParse<G> for $T {
             fn read(&mut self, parser: &mut Parser<G>) -> ParseResult {
-                let val = parser.get_token()?.ok_or(ParseError::Eof)?;
+                let val = parser.get_token()?.ok_or_else(ParseError::eof)?;
                 *self = convert_err(<$T>::from_str(val.try_to_string()?))?;
                 Ok(())
             }
@@ -26,7 +29,7 @@ macro_rules! from_string {
     {$T:ty, $arm:ident} => {
         impl <G: GameTrait> ParadoxParse<G> for $T {
             fn read(&mut self, parser: &mut Parser<G>) -> ParseResult {
-                let val = parser.get_token()?.ok_or(ParseError::Eof)?;
+                let val = parser.get_token()?.ok_or_else(ParseError::eof)?;
                 if let Token::$arm(val) = val {
                     *self = val;
                     return Ok(());
@@ -40,6 +43,8 @@ macro_rules! from_string {
 
 from_string!{i32, Integer}
 from_string!{u32, Unsigned}
+from_string!{i64, Long}
+from_string!{u64, UnsignedLong}
 from_string!{f32}
 from_string!{f64, Float}
 from_string!{String}
@@ -47,10 +52,19 @@ from_string!{FixedPoint, Fixed}
 
 impl <G: GameTrait> ParadoxParse<G> for Date {
     fn read(&mut self, parser: &mut Parser<G>) -> ParseResult {
-        let val = parser.get_token()?.ok_or(ParseError::Eof)?;
-        if let Token::Integer(val) = val {
-            *self = crate::date::convert_date(val as u32);
-            return Ok(());
+        let val = parser.get_token()?.ok_or_else(ParseError::eof)?;
+        match val {
+            Token::Date(date) => {
+                *self = date;
+                return Ok(());
+            },
+            Token::Integer(val) => {
+                *self = Date::from_binary(val).ok_or_else(||
+                    ParseError::constraint(
+                        format!("{} is not a valid date", val)))?;
+                return Ok(());
+            },
+            _ => {}
         }
         *self = convert_err(val.try_to_string()?.parse())?;
         Ok(())
@@ -59,8 +73,8 @@ impl <G: GameTrait> ParadoxParse<G> for Date {
 
 impl <G: GameTrait, Static: StaticAtomSet> ParadoxParse<G> for Atom<Static> {
     fn read(&mut self, parser: &mut Parser<G>) -> ParseResult {
-        let val = parser.get_token()?.ok_or(ParseError::Eof)?;
-        *self = Self::from(val);
+        let val = parser.get_token()?.ok_or_else(ParseError::eof)?;
+        *self = Self::try_from(val)?;
         Ok(())
     }
 }
@@ -73,7 +87,8 @@ macro_rules! impl_array {
                 let class_name = std::any::type_name::<Self>();
                 parser.with_scope(|parser| {
                     if i == $len {
-                        return parser.validation_error(class_name,
+                        return parser.validation_error(
+                            DiagnosticKind::InvalidValue, class_name,
                             stringify!($len),
                             "too many entries in list", true, None);
                     }
@@ -82,8 +97,9 @@ macro_rules! impl_array {
                     Ok(())
                 })?;
                 if i != $len {
-                    return parser.validation_error(class_name, &i.to_string(),
-                        "list terminated early", true, None);
+                    return parser.validation_error(
+                        DiagnosticKind::InvalidValue, class_name,
+                        &i.to_string(), "list terminated early", true, None);
                 }
                 Ok(())
             }
@@ -126,7 +142,7 @@ impl_array!{32}
 
 impl <G: GameTrait> ParadoxParse<G> for bool {
     fn read(&mut self, parser: &mut Parser<G>) -> ParseResult {
-        let val = parser.get_token()?.ok_or(ParseError::Eof)?;
+        let val = parser.get_token()?.ok_or_else(ParseError::eof)?;
         if let Token::Bool(b) = val {
             *self = b;
             return Ok(());
@@ -137,7 +153,7 @@ impl <G: GameTrait> ParadoxParse<G> for bool {
         } else if string == "no" {
             *self = false;
         } else {
-            return Err(ParseError::Constraint(
+            return Err(ParseError::constraint(
                 format!("Expected bool string, found {}", string)));
         }
         Ok(())
@@ -155,47 +171,126 @@ impl <G: GameTrait, T: ParadoxParse<G> + Default> ParadoxParse<G> for Vec<T> {
     }
 }
 
-impl <G: GameTrait, T: ParadoxParse<G> + Default> ParadoxParse<G> for HashMap<String, T> {
-    fn read(&mut self, parser: &mut Parser<G>) -> ParseResult {
-        parser.parse_key_scope(|key, parser| {
-            let mut val = T::default();
-            val.read(parser)?;
-            if self.insert(format!("{}", key), val).is_some() {
-                return Err(ParseError::Constraint(
-                        format!("Duplicate key {} in map", key)));
+impl <G: GameTrait> ParadoxKey<G> for String {
+    fn from_key(key: ParserAtom<G>) -> Result<Self, ParseError> {
+        Ok(key.to_string())
+    }
+}
+
+impl <G: GameTrait> ParadoxKey<G> for ParserAtom<G> {
+    fn from_key(key: ParserAtom<G>) -> Result<Self, ParseError> {
+        Ok(key)
+    }
+}
+
+macro_rules! key_from_string {
+    {$T:ty} => {
+        impl <G: GameTrait> ParadoxKey<G> for $T {
+            fn from_key(key: ParserAtom<G>) -> Result<Self, ParseError> {
+                Ok(convert_err(key.parse())?)
             }
-            Ok(())
-        })
+        }
+    }
+}
+
+key_from_string!{i32}
+key_from_string!{u32}
+key_from_string!{Date}
+
+/// Read a `key = value` block into a map. Keys that are defined more than once
+/// are reported, and the last definition is kept. Comparisons such as
+/// `key > value` are reported too, and read as if they were `=`.
+fn read_map<G, K, T>(parser: &mut Parser<G>, class_name: &'static str,
+                     mut insert: impl FnMut(K, T) -> Option<T>) -> ParseResult
+    where G: GameTrait, K: ParadoxKey<G>, T: ParadoxParse<G> + Default
+{
+    parser.parse_key_scope(|key, op, parser| {
+        if op != Operator::Eq {
+            parser.validation_error(DiagnosticKind::InvalidValue, class_name,
+                                    &key, &format!("unexpected operator {}", op),
+                                    false, None)?;
+        }
+        let mut val = T::default();
+        val.read(parser)?;
+        if insert(K::from_key(key.clone())?, val).is_some() {
+            parser.validation_error(DiagnosticKind::DuplicateField, class_name,
+                                    &key, "multiple definitions found", false,
+                                    None)?;
+        }
+        Ok(())
+    })
+}
+
+impl <G, K, T> ParadoxParse<G> for HashMap<K, T>
+    where G: GameTrait, K: ParadoxKey<G> + Eq + Hash,
+          T: ParadoxParse<G> + Default
+{
+    fn read(&mut self, parser: &mut Parser<G>) -> ParseResult {
+        let class_name = std::any::type_name::<Self>();
+        read_map(parser, class_name, |key, val| self.insert(key, val))
     }
 }
 
-impl <G: GameTrait, T: ParadoxParse<G> + Default> ParadoxParse<G> for HashMap<ParserAtom<G>, T> {
+impl <G, K, T> ParadoxParse<G> for BTreeMap<K, T>
+    where G: GameTrait, K: ParadoxKey<G> + Ord, T: ParadoxParse<G> + Default
+{
     fn read(&mut self, parser: &mut Parser<G>) -> ParseResult {
-        parser.parse_key_scope(|key, parser| {
-            let mut val = T::default();
-            val.read(parser)?;
-            if self.insert(key.clone(), val).is_some() {
-                // Some maps have duplicate keys!
-                //return Err(ParseError::Constraint(
-                //        format!("Duplicate key {} in map", key)));
-            }
+        let class_name = std::any::type_name::<Self>();
+        read_map(parser, class_name, |key, val| self.insert(key, val))
+    }
+}
+
+/// A single `key = value` pair, such as an element of a `Vec<(K, V)>`, which
+/// keeps all of the entries of a block in order.
+impl <G, K, T> ParadoxParse<G> for (K, T)
+    where G: GameTrait, K: ParadoxKey<G>, T: ParadoxParse<G>
+{
+    fn read(&mut self, parser: &mut Parser<G>) -> ParseResult {
+        let key = parser.get_token()?.ok_or_else(ParseError::eof)?;
+        self.0 = K::from_key(ParserAtom::<G>::try_from(key)?)?;
+        match parser.peek()? {
+            Some(Token::LBrace) => {},
+            Some(token) if token.as_operator() == Some(Operator::Eq) => {
+                parser.get_token()?;
+            },
+            Some(_) => {
+                let token = parser.get_token()?.unwrap();
+                return Err(token.into());
+            },
+            None => return Err(ParseError::eof()),
+        }
+        self.1.read(parser)
+    }
+}
+
+impl <G, T> ParadoxParse<G> for HashSet<T>
+    where G: GameTrait, T: ParadoxParse<G> + Default + Eq + Hash
+{
+    fn read(&mut self, parser: &mut Parser<G>) -> ParseResult {
+        parser.with_scope(|parser| {
+            let mut value = T::default();
+            value.read(parser)?;
+            self.insert(value);
             Ok(())
         })
     }
 }
 
+/// An `Option` is `Some` if the value was present in the input.
+impl <G: GameTrait, T: ParadoxParse<G> + Default> ParadoxParse<G> for Option<T> {
+    fn read(&mut self, parser: &mut Parser<G>) -> ParseResult {
+        self.get_or_insert_with(T::default).read(parser)
+    }
+}
+
+impl <G: GameTrait, T: ParadoxParse<G> + ?Sized> ParadoxParse<G> for Box<T> {
+    fn read(&mut self, parser: &mut Parser<G>) -> ParseResult {
+        (**self).read(parser)
+    }
+}
+
 impl <G: GameTrait> ParadoxParse<G> for () {
     fn read(&mut self, parser: &mut Parser<G>) -> ParseResult {
-        match parser.get_token()? {
-            None => Err(ParseError::Eof),
-            Some(Token::LBrace) => {
-                parser.unget(Token::LBrace);
-                parser.with_scope(|parser| {
-                    ().read(parser)
-                })
-            },
-            Some(Token::RBrace) => Err(Token::<G::Static>::RBrace.into()),
-            Some(_) => Ok(()),
-        }
+        parser.skip_value()
     }
 }
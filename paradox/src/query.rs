@@ -0,0 +1,467 @@
+use crate::{ErrorLocation, Operator, ParseError, Token, Value};
+use std::convert::TryFrom;
+use std::fmt;
+use string_cache::StaticAtomSet;
+
+type Result<T> = std::result::Result<T, ParseError>;
+
+/// A query that selects values from a [`Value`] tree.
+///
+/// A query is a path of keys separated by dots, like `countries.FRA.treasury`.
+/// Each step can be:
+/// * a key, which matches every entry with that key (including duplicates),
+/// * `*`, which matches every entry of an object and every element of a list,
+/// * either of those followed by filters in brackets. `[owner=FRA]` keeps
+///   values whose `owner` is `FRA`, and also accepts `!=`, `<`, `<=`, `>` and
+///   `>=`, which compare numerically if both sides are numbers. The key of a
+///   filter can itself be a path. If a value doesn't have the key of the
+///   filter, the filter is applied to its children instead, so
+///   `provinces[owner=FRA]` is short for `provinces.*[owner=FRA]`. `[2]` keeps
+///   only the third match.
+///
+/// The last step may be a projection like `{name, base_tax}`, which selects
+/// several paths from each match. Every match becomes one [`Row`].
+///
+/// ```text
+/// countries.*.treasury
+/// provinces[owner=FRA].base_tax
+/// trade.node[definitions=english_channel].country_info.*.val
+/// provinces[base_tax>=10].{name, owner}
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Query {
+    path: Vec<Step>,
+    columns: Vec<Vec<Step>>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Step {
+    /// The key to match, or `None` for `*`.
+    key: Option<String>,
+    filters: Vec<Filter>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Filter {
+    Index(usize),
+    Compare(Vec<Step>, Operator, String)
+}
+
+/// One result of a query: the key matched at each step of the path, and the
+/// value of each column. Queries without a projection have a single column,
+/// holding the matched value itself.
+#[derive(Debug)]
+pub struct Row<'v, Static: StaticAtomSet> {
+    pub keys: Vec<String>,
+    pub values: Vec<Option<&'v Value<Static>>>
+}
+
+impl Query {
+    pub fn parse(query: &str) -> Result<Self> {
+        let mut parser = QueryParser { text: query, pos: 0 };
+        let (path, columns) = parser.parse_query()?;
+        Ok(Query { path, columns })
+    }
+
+    /// Run the query against a tree, returning the matches in order.
+    pub fn run<'v, Static: StaticAtomSet>(&self, root: &'v Value<Static>)
+            -> Vec<Row<'v, Static>> {
+        let mut matches = vec![(Vec::new(), root)];
+        for step in &self.path {
+            matches = matches.into_iter()
+                .flat_map(|(keys, value)| {
+                    step.apply(value).into_iter().map(move |(path, child)| {
+                        let mut keys = keys.clone();
+                        keys.extend(path);
+                        (keys, child)
+                    })
+                })
+                .collect();
+        }
+        matches.into_iter().map(|(keys, value)| {
+            let values = if self.columns.is_empty() {
+                vec![Some(value)]
+            } else {
+                self.columns.iter().map(|column| first_match(column, value))
+                    .collect()
+            };
+            Row { keys, values }
+        }).collect()
+    }
+}
+
+impl std::str::FromStr for Query {
+    type Err = ParseError;
+    fn from_str(query: &str) -> Result<Self> {
+        Query::parse(query)
+    }
+}
+
+impl Step {
+    /// Find the children of a value that this step matches, with the keys
+    /// that lead to them.
+    fn apply<'v, Static: StaticAtomSet>(&self, value: &'v Value<Static>)
+            -> Vec<(Vec<String>, &'v Value<Static>)> {
+        let mut matches: Vec<_> = match &self.key {
+            Some(key) => value.get_all(key).map(|v| (vec![key.clone()], v))
+                .collect(),
+            None => children(value).map(|(key, v)| (vec![key], v)).collect()
+        };
+        for filter in &self.filters {
+            matches = match filter {
+                Filter::Index(index) =>
+                    matches.into_iter().nth(*index).into_iter().collect(),
+                Filter::Compare(path, op, expected) => matches.into_iter()
+                    .flat_map(|(keys, child)| {
+                        // Filter the children instead if the value doesn't
+                        // have the key itself.
+                        let candidates: Vec<_> = if first_match(path, child)
+                                .is_some() {
+                            vec![(keys, child)]
+                        } else {
+                            children(child).map(|(key, v)| {
+                                let mut keys = keys.clone();
+                                keys.push(key);
+                                (keys, v)
+                            }).collect()
+                        };
+                        candidates.into_iter().filter(|(_, child)| {
+                            first_match(path, child)
+                                .is_some_and(|v| compare(v, *op, expected))
+                        })
+                    })
+                    .collect()
+            };
+        }
+        matches
+    }
+}
+
+/// Get every entry of an object and every element of a list, with their keys
+/// or indices.
+fn children<Static: StaticAtomSet>(value: &Value<Static>)
+        -> impl Iterator<Item = (String, &Value<Static>)> {
+    value.entries()
+        .map(|entry| (entry.key.to_string(), &entry.value))
+        .chain(value.as_array().unwrap_or(&[]).iter().enumerate()
+               .map(|(index, v)| (index.to_string(), v)))
+}
+
+/// Follow a path from a value, and return the first value it reaches.
+fn first_match<'v, Static: StaticAtomSet>(path: &[Step], value: &'v Value<Static>)
+        -> Option<&'v Value<Static>> {
+    match path.split_first() {
+        None => Some(value),
+        Some((step, rest)) => step.apply(value).into_iter()
+            .find_map(|(_, child)| first_match(rest, child))
+    }
+}
+
+fn compare<Static: StaticAtomSet>(value: &Value<Static>, op: Operator,
+                                  expected: &str) -> bool {
+    let text = match value {
+        Value::Scalar(token) => scalar_text(token),
+        _ => return false
+    };
+    let ordering = match (value.as_f64(), expected.parse::<f64>()) {
+        (Some(a), Ok(b)) => a.partial_cmp(&b),
+        _ => Some(text.as_str().cmp(expected))
+    };
+    let ordering = match ordering {
+        Some(ordering) => ordering,
+        None => return false
+    };
+    use std::cmp::Ordering::*;
+    match op {
+        Operator::Eq | Operator::QEq => ordering == Equal,
+        Operator::Ne => ordering != Equal,
+        Operator::Lt => ordering == Less,
+        Operator::Le => ordering != Greater,
+        Operator::Gt => ordering == Greater,
+        Operator::Ge => ordering != Less,
+    }
+}
+
+/// Get the text of a scalar, as it would be written in a text file but without
+/// quotes.
+fn scalar_text<Static: StaticAtomSet>(token: &Token<'_, Static>) -> String {
+    match token {
+        Token::String(s) | Token::Word(s) => s.to_string(),
+        token => string_cache::Atom::<Static>::try_from(token.clone())
+            .map(|atom| atom.to_string())
+            .unwrap_or_default()
+    }
+}
+
+impl <Static: StaticAtomSet> Value<Static> {
+    /// Get the value of a number. Strings and bare words are parsed.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self.as_scalar()? {
+            Token::Fixed(f) => Some(f64::from(*f)),
+            Token::Float(f) => Some(*f),
+            Token::Integer(i) => Some(*i as f64),
+            Token::Unsigned(i) => Some(*i as f64),
+            Token::Long(i) => Some(*i as f64),
+            Token::UnsignedLong(i) => Some(*i as f64),
+            token => token.try_to_string().ok()?.parse().ok()
+        }
+    }
+
+    /// Run a query against this tree. See [`Query`] for the syntax.
+    pub fn query(&self, query: &str) -> Result<Vec<Row<'_, Static>>> {
+        Ok(Query::parse(query)?.run(self))
+    }
+}
+
+/// Values are displayed like the text format, but on a single line and without
+/// quotes around strings.
+impl <Static: StaticAtomSet> fmt::Display for Value<Static> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn list<T: fmt::Display>(f: &mut fmt::Formatter, items: &[T])
+                -> fmt::Result {
+            f.write_str("{")?;
+            for item in items {
+                write!(f, " {}", item)?;
+            }
+            f.write_str(" }")
+        }
+        match self {
+            Value::Scalar(token) => f.write_str(&scalar_text(token)),
+            Value::Color(space, values) => {
+                write!(f, "{} ", scalar_text(space))?;
+                list(f, values)
+            },
+            Value::Object(entries) => list(f, entries),
+            Value::Array(values) => list(f, values),
+            Value::Mixed(items) => list(f, items),
+        }
+    }
+}
+
+impl <Static: StaticAtomSet> fmt::Display for crate::Entry<Static> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.key, self.op, self.value)
+    }
+}
+
+impl <Static: StaticAtomSet> fmt::Display for crate::Item<Static> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            crate::Item::Entry(entry) => entry.fmt(f),
+            crate::Item::Value(value) => value.fmt(f),
+        }
+    }
+}
+
+struct QueryParser<'q> {
+    text: &'q str,
+    pos: usize
+}
+
+impl <'q> QueryParser<'q> {
+    fn error<T>(&self, msg: &str) -> Result<T> {
+        Err(ParseError::Parse(
+            format!("invalid query {:?} at offset {}: {}", self.text, self.pos,
+                    msg),
+            ErrorLocation::default()))
+    }
+
+    fn rest(&self) -> &'q str {
+        &self.text[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Consume the given text if it comes next.
+    fn eat(&mut self, text: &str) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(text) {
+            self.pos += text.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Read text up to (but not including) any of the given characters.
+    fn take_until(&mut self, stop: &[char]) -> &'q str {
+        self.skip_whitespace();
+        let rest = self.rest();
+        let end = rest.find(|c: char| stop.contains(&c) || c.is_whitespace())
+            .unwrap_or(rest.len());
+        self.pos += end;
+        &rest[..end]
+    }
+
+    fn parse_query(&mut self) -> Result<(Vec<Step>, Vec<Vec<Step>>)> {
+        let mut path = Vec::new();
+        let mut columns = Vec::new();
+        loop {
+            if self.eat("{") {
+                loop {
+                    columns.push(self.parse_path()?);
+                    if self.eat("}") {
+                        break;
+                    } else if !self.eat(",") {
+                        return self.error("expected , or }");
+                    }
+                }
+                break;
+            }
+            path.push(self.parse_step()?);
+            if !self.eat(".") {
+                break;
+            }
+        }
+        self.skip_whitespace();
+        if !self.rest().is_empty() {
+            return self.error("unexpected text");
+        }
+        Ok((path, columns))
+    }
+
+    fn parse_path(&mut self) -> Result<Vec<Step>> {
+        let mut path = vec![self.parse_step()?];
+        while self.eat(".") {
+            path.push(self.parse_step()?);
+        }
+        Ok(path)
+    }
+
+    fn parse_step(&mut self) -> Result<Step> {
+        const STOP: &[char] = &['.', '[', ']', '{', '}', ',', '=', '!', '<',
+                                '>'];
+        let key = match self.take_until(STOP) {
+            "" => return self.error("expected a key"),
+            "*" => None,
+            key => Some(key.to_owned())
+        };
+        let mut filters = Vec::new();
+        while self.eat("[") {
+            let start = self.pos;
+            if let Ok(index) = self.take_until(STOP).parse() {
+                if self.eat("]") {
+                    filters.push(Filter::Index(index));
+                    continue;
+                }
+            }
+            // Not an index, so go back and read a comparison.
+            self.pos = start;
+            let path = self.parse_path()?;
+            let op = self.parse_operator()?;
+            let value = self.parse_literal()?;
+            if !self.eat("]") {
+                return self.error("expected ]");
+            }
+            filters.push(Filter::Compare(path, op, value));
+        }
+        Ok(Step { key, filters })
+    }
+
+    fn parse_operator(&mut self) -> Result<Operator> {
+        // Check the two-character operators first.
+        for (text, op) in &[("!=", Operator::Ne), ("<=", Operator::Le),
+                            (">=", Operator::Ge), ("=", Operator::Eq),
+                            ("<", Operator::Lt), (">", Operator::Gt)] {
+            if self.eat(text) {
+                return Ok(*op);
+            }
+        }
+        self.error("expected a comparison")
+    }
+
+    fn parse_literal(&mut self) -> Result<String> {
+        if self.eat("\"") {
+            let rest = self.rest();
+            let end = match rest.find('"') {
+                Some(end) => end,
+                None => return self.error("unterminated string")
+            };
+            self.pos += end + 1;
+            return Ok(rest[..end].to_owned());
+        }
+        match self.take_until(&[']']) {
+            "" => self.error("expected a value"),
+            value => Ok(value.to_owned())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GameTrait, TextSliceLexer};
+    use string_cache::{Atom, EmptyStaticAtomSet};
+
+    struct TestGame;
+    impl GameTrait for TestGame {
+        type Static = EmptyStaticAtomSet;
+        fn get_binary_token(_: u16) -> Option<Atom<Self::Static>> { None }
+    }
+
+    const SAVE: &str = "
+        countries = {
+            FRA = { treasury = 100.5 }
+            ENG = { treasury = 20.000 }
+            REB = { }
+        }
+        provinces = {
+            -1 = { name = \"Stockholm\" owner = SWE base_tax = 5 }
+            -2 = { name = \"Paris\" owner = FRA base_tax = 12 }
+            -3 = { name = \"Lyon\" owner = FRA base_tax = 4 }
+        }
+        trade = {
+            node = { definitions = baltic_sea country_info = { SWE = { val = 1 } } }
+            node = { definitions = english_channel
+                     country_info = { ENG = { val = 2 } FRA = { val = 3 } } }
+        }";
+
+    fn rows(query: &str) -> Vec<Vec<String>> {
+        let mut lexer = TextSliceLexer::new(SAVE.as_bytes(), "save".into());
+        let save = Value::from_lexer::<TestGame>(&mut lexer).unwrap();
+        save.query(query).unwrap().into_iter()
+            .map(|row| row.keys.into_iter()
+                 .chain(row.values.into_iter().map(|v| match v {
+                     Some(v) => v.to_string(),
+                     None => "-".into()
+                 }))
+                 .collect())
+            .collect()
+    }
+
+    #[test]
+    fn run_queries() {
+        assert_eq!(rows("countries.*.treasury"), vec![
+            vec!["countries", "FRA", "treasury", "100.5"],
+            vec!["countries", "ENG", "treasury", "20.000"],
+        ]);
+        assert_eq!(rows("provinces[owner=FRA].base_tax"), vec![
+            vec!["provinces", "-2", "base_tax", "12"],
+            vec!["provinces", "-3", "base_tax", "4"],
+        ]);
+        assert_eq!(rows("provinces.*[base_tax >= 5][0].{name, owner, x}"), vec![
+            vec!["provinces", "-1", "Stockholm", "SWE", "-"],
+        ]);
+        assert_eq!(rows("trade.node[definitions=english_channel]\
+                         .country_info.*.val"), vec![
+            vec!["trade", "node", "country_info", "ENG", "val", "2"],
+            vec!["trade", "node", "country_info", "FRA", "val", "3"],
+        ]);
+        assert_eq!(rows("countries.*[treasury < 50]"), vec![
+            vec!["countries", "ENG", "{ treasury = 20.000 }"],
+        ]);
+    }
+
+    #[test]
+    fn invalid_queries() {
+        assert!(Query::parse("").is_err());
+        assert!(Query::parse("a..b").is_err());
+        assert!(Query::parse("a[b=c").is_err());
+        assert!(Query::parse("a.{b, c").is_err());
+        assert!(Query::parse("a[b ~ c]").is_err());
+        assert!(Query::parse("a.*.b[\"x\"]").is_err());
+    }
+}
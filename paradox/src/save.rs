@@ -1,195 +1,1241 @@
 use crate::*;
+use std::convert::TryFrom;
 use std::fs::File;
-use std::io::{BufReader, Read, Seek};
-use std::path::Path;
+use std::collections::VecDeque;
+use std::io::{BufReader, Cursor, Read, Seek};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use std::sync::Arc;
 use string_cache::Atom;
 use zip::{ZipArchive, result::ZipError};
 
 impl From<ZipError> for ParseError {
     fn from(err: ZipError) -> Self {
         match err {
-            ZipError::Io(err) => Self::Io(err),
-            _ => Self::Conversion(Box::new(err))
+            ZipError::Io(err) => err.into(),
+            _ => Box::<dyn std::error::Error + Send + Sync>::from(err).into()
         }
     }
 }
 
-struct ZipLexer<'a, G: 'static + GameTrait, R: Read + Seek> {
-    archive: &'a mut ZipArchive<R>,
-    path: &'a Path,
-    cur_index: usize,
-    cur_lexer: Box<dyn Lexer<G>>
+/// A file from a save game archive, decompressed into memory.
+struct SaveEntry {
+    /// The name used in error messages, including the path of the save.
+    name: String,
+    /// The name of the file in the archive. Plain saves have a single file,
+    /// called `gamestate`.
+    file: String,
+    data: Vec<u8>
 }
 
-impl <'a, G: 'static + GameTrait, R: Read + Seek> ZipLexer<'a, G, R> {
-    fn new(archive: &'a mut ZipArchive<R>,
-           path: &'a Path) -> Result<Self, ParseError> {
-        let entry = archive.by_index(0)?;
-        let lexer = get_lexer(entry, path)?;
-        Ok(Self { archive, cur_index: 0, cur_lexer: lexer, path })
+/// Decompress all of the gamestate files in the archive, each on its own
+/// thread.
+fn read_entries(data: &[u8], path: &str) -> Result<Vec<SaveEntry>, ParseError> {
+    let archive = ZipArchive::new(Cursor::new(data))?;
+    let entries = std::thread::scope(|scope| {
+        let threads: Vec<_> = (0..archive.len()).map(|i| {
+            let mut archive = archive.clone();
+            scope.spawn(move || -> Result<_, ParseError> {
+                let mut entry = archive.by_index(i)?;
+                // Anything after an embedded archive isn't part of the save.
+                if i > 0 && entry.name().ends_with(".zip") {
+                    return Ok(None);
+                }
+                let file = entry.name().to_owned();
+                let name = format!("{}/{}", path, file);
+                let mut data = Vec::with_capacity(entry.size() as usize);
+                entry.read_to_end(&mut data)?;
+                Ok(Some(SaveEntry { name, file, data }))
+            })
+        }).collect();
+        threads.into_iter().map(join_thread).collect::<Vec<_>>()
+    });
+    let mut result = Vec::with_capacity(entries.len());
+    for entry in entries {
+        match entry? {
+            Some(entry) => result.push(entry),
+            None => break
+        }
     }
+    Ok(result)
+}
+
+/// Wait for a thread to finish, passing on its panic if it had one.
+fn join_thread<T>(thread: std::thread::ScopedJoinHandle<'_, T>) -> T {
+    thread.join().unwrap_or_else(|err| std::panic::resume_unwind(err))
+}
 
-    fn advance_to_next_file(&mut self) -> Result<bool, ParseError> {
-        self.cur_index += 1;
-        if self.cur_index >= self.archive.len() {
-            return Ok(false);
+/// Split a save into its gamestate files. Saves can be zip archives, or plain
+/// text or binary files when compression is turned off; the magic bytes at
+/// the start of the data tell them apart.
+fn read_save(data: Vec<u8>, path: &str) -> Result<Vec<SaveEntry>, ParseError> {
+    if data.starts_with(b"PK\x03\x04") {
+        return read_entries(&data, path);
+    }
+    match data.get(3..6) {
+        Some(b"txt") | Some(b"bin") => Ok(vec![SaveEntry {
+            name: path.to_owned(),
+            file: "gamestate".to_owned(),
+            data
+        }]),
+        _ => Err(unknown_magic(&data[..data.len().min(6)], path))
+    }
+}
+
+fn unknown_magic(magic: &[u8], path: &str) -> ParseError {
+    ParseError::Parse(
+        format!("{} is not a save game: unknown magic bytes {:?}",
+                path, String::from_utf8_lossy(magic)),
+        ErrorLocation::default())
+}
+
+/// Get a lexer for the contents of a gamestate file, using its magic bytes to
+/// choose between the text and binary formats. Binary files use the token
+/// table, if one is given, and text files use typed literals if `typed` is
+/// set.
+fn get_lexer<'a, G: 'static + GameTrait>(data: &'a [u8], entry_name: String,
+        table: Option<Arc<TokenTable<G::Static>>>, typed: bool)
+        -> Result<Box<dyn Lexer<'a, G> + 'a>, ParseError> {
+    if data.len() < 6 {
+        return Err(ParseError::Parse(String::from_utf8_lossy(data).into(),
+                                     ErrorLocation::default()));
+    }
+    let (magic, data) = data.split_at(6);
+
+    // Use the magic bytes to choose a text or a binary lexer.
+    if &magic[3..] == b"txt" {
+        Ok(Box::new(TextSliceLexer::new(data, entry_name)
+                    .with_encoding(G::ENCODING)
+                    .with_typed_literals(typed)))
+    } else if &magic[3..] == b"bin" {
+        let lexer = BinarySliceLexer::<G>::new(data, entry_name)
+            .with_encoding(G::ENCODING);
+        Ok(match table {
+            Some(table) => Box::new(lexer.with_token_table(table)),
+            None => Box::new(lexer)
+        })
+    } else {
+        Err(ParseError::Parse(String::from_utf8_lossy(magic).into(),
+                              ErrorLocation::default()))
+    }
+}
+
+/// Find the version of the game that wrote a save, from the first entry of its
+/// `savegame_versions` list.
+fn find_savegame_version<G: 'static + GameTrait>(entries: &[SaveEntry],
+        table: Option<Arc<TokenTable<G::Static>>>)
+        -> Result<Option<String>, ParseError> {
+    for entry in entries {
+        let mut lexer = get_lexer::<G>(&entry.data, entry.name.clone(),
+                                       table.clone(), false)?;
+        if let Some(version) = find_version(&mut *lexer)? {
+            return Ok(Some(version));
         }
+    }
+    Ok(None)
+}
 
-        let entry = self.archive.by_index(self.cur_index);
-        if entry?.name().ends_with(".zip") {
-            return Ok(false);
+/// Find the first entry of the `savegame_versions` list in a file.
+fn find_version<'a, G: GameTrait>(lexer: &mut dyn Lexer<'a, G>)
+        -> Result<Option<String>, ParseError> {
+    let mut in_versions = false;
+    while let Some(token) = lexer.get_token()? {
+        match token {
+            Token::Word(key) if key == "savegame_versions" =>
+                in_versions = true,
+            Token::Atom(key) if &*key == "savegame_versions" =>
+                in_versions = true,
+            Token::Eq | Token::LBrace if in_versions => {},
+            Token::String(version) if in_versions =>
+                return Ok(Some(version.into_owned())),
+            _ => in_versions = false
         }
+    }
+    Ok(None)
+}
+
+/// A lexer for the files of a save, one after the other, as they are parsed
+/// without threads. This also lets the parser borrow the lexers for a shorter
+/// lifetime than that of the data.
+struct EntryLexer<'a, G: 'static + GameTrait>(
+    VecDeque<Box<dyn Lexer<'a, G> + 'a>>);
 
-        Ok(true)
+impl <'a, G: 'static + GameTrait> EntryLexer<'a, G> {
+    fn new(entries: &'a [SaveEntry],
+           table: Option<Arc<TokenTable<G::Static>>>)
+            -> Result<Self, ParseError> {
+        let mut lexers = entries.iter()
+            .map(|entry| get_lexer::<G>(&entry.data, entry.name.clone(),
+                                        table.clone(), false))
+            .collect::<Result<VecDeque<_>, _>>()?;
+        if lexers.is_empty() {
+            lexers.push_back(Box::new(TextSliceLexer::new(&[], String::new())));
+        }
+        Ok(Self(lexers))
     }
 }
 
-impl <G: GameTrait, R: Read + Seek> Lexer<G> for ZipLexer<'_, G, R> {
-    fn get_token(&mut self) -> Result<Option<Token<G::Static>>, ParseError> {
-        loop {
-            match (*self.cur_lexer).get_token()? {
-                None => {
-                    if !self.advance_to_next_file()? {
-                        return Ok(None);
-                    }
-                    self.cur_lexer = get_lexer(
-                        self.archive.by_index(self.cur_index)?,
-                        self.path)?;
-                    continue;
-                },
-                t => return Ok(t)
+impl <'a, 'b: 'a, G: 'static + GameTrait> Lexer<'a, G> for EntryLexer<'b, G> {
+    fn get_token(&mut self) -> Result<Option<Token<'a, G::Static>>, ParseError> {
+        // The last lexer is kept at the end, for the span of errors there.
+        while let Some(lexer) = self.0.front_mut() {
+            match lexer.get_token()? {
+                None if self.0.len() > 1 => { self.0.pop_front(); },
+                token => return Ok(token)
             }
         }
+        Ok(None)
+    }
+
+    fn get_span(&self) -> Span {
+        self.0[0].get_span()
+    }
+}
+
+/// Parse the files of a save one after the other, as a single file.
+fn parse_entry<G, T>(entries: &[SaveEntry], game_data: &mut GameData,
+                     table: Option<Arc<TokenTable<G::Static>>>,
+                     options: ParseOptions)
+        -> Result<(T, Diagnostics), ParseError>
+    where G: 'static + GameTrait, T: ParadoxParse<G> + Default
+{
+    let mut lexer = EntryLexer::<G>::new(entries, table)?;
+    let mut result = T::default();
+    let diagnostics = Parser::new(&mut lexer, game_data)
+        .with_options(options)
+        .parse_with_diagnostics(&mut result)?;
+    Ok((result, diagnostics))
+}
+
+/// Parse a file that is only one part of a save, returning the fields found
+/// at its top level so that the missing ones can be checked once all of the
+/// parts have been merged.
+fn parse_part<G, T>(entry: &SaveEntry, game_data: &mut GameData,
+                    table: Option<Arc<TokenTable<G::Static>>>,
+                    options: ParseOptions)
+        -> Result<(T, Diagnostics, SeenFields), ParseError>
+    where G: 'static + GameTrait, T: ParadoxParse<G> + Default
+{
+    let mut lexer = EntryLexer::<G>::new(std::slice::from_ref(entry), table)?;
+    let mut result = T::default();
+    let (diagnostics, seen) = Parser::new(&mut lexer, game_data)
+        .with_options(options)
+        .with_partial_input(true)
+        .parse_partial(&mut result)?;
+    Ok((result, diagnostics, seen))
+}
+
+/// Checks the fields missing from all of the parts of a save, by finishing
+/// the top-level block with the fields seen in any of them.
+struct FinishFields<'a, T>(&'a mut T, &'a SeenFields);
+
+impl <G: GameTrait, T: ParadoxParseFields<G>> ParadoxParse<G>
+        for FinishFields<'_, T> {
+    fn read(&mut self, parser: &mut Parser<G>) -> Result<(), ParseError> {
+        self.0.finish_fields(parser, self.1)
     }
+}
 
-    fn get_location_info(&self) -> String {
-        (*self.cur_lexer).get_location_info()
+/// Parse a save that has been split into its files, using the token table
+/// that matches the version of the game that wrote it, if tables are given.
+/// The files are parsed one after the other.
+///
+/// The version is found using the newest table, on the assumption that the
+/// tokens for the version information itself do not change between versions.
+fn parse_entries<G, T>(entries: &[SaveEntry], game_data: &mut GameData,
+                       tables: Option<&TokenTables<G::Static>>,
+                       options: ParseOptions)
+        -> Result<(T, Diagnostics), ParseError>
+    where G: 'static + GameTrait, T: ParadoxParse<G> + Default
+{
+    let table = match tables {
+        Some(tables) => select_table::<G>(entries, tables)?,
+        None => None
+    };
+    parse_entry(entries, game_data, table, options)
+}
+
+/// Parse a save like [`parse_entries`], but with each file on its own thread.
+/// The results are merged in the order of the files, which gives the same
+/// result as parsing the files one after the other. Missing fields are
+/// checked once everything is merged.
+///
+/// Each thread has its own copy of the game data, without the diagnostics
+/// collected so far, and the diagnostics each one adds are passed back in the
+/// order of the files.
+fn parse_entries_parallel<G, T>(entries: &[SaveEntry], game_data: &mut GameData,
+                                tables: Option<&TokenTables<G::Static>>,
+                                options: ParseOptions)
+        -> Result<(T, Diagnostics), ParseError>
+    where G: 'static + GameTrait,
+          T: ParadoxParse<G> + ParadoxParseFields<G> + ParadoxMerge + Default +
+             Send
+{
+    let table = match tables {
+        Some(tables) => select_table::<G>(entries, tables)?,
+        None => None
+    };
+    if entries.len() == 1 {
+        return parse_entry(entries, game_data, table, options);
+    }
+    let parts = std::thread::scope(|scope| {
+        let threads: Vec<_> = entries.iter().map(|entry| {
+            let mut game_data = game_data.fork();
+            let table = table.clone();
+            scope.spawn(move || {
+                let part = parse_part::<G, T>(entry, &mut game_data, table,
+                                              options);
+                (part, game_data)
+            })
+        }).collect();
+        threads.into_iter().map(join_thread).collect::<Vec<_>>()
+    });
+    let mut result = T::default();
+    let mut diagnostics = Diagnostics::default();
+    let mut seen = SeenFields::default();
+    for (part, part_game_data) in parts {
+        game_data.join(part_game_data);
+        let (part, part_diagnostics, part_seen) = part?;
+        result.merge_seen(part, &part_seen);
+        diagnostics.extend(part_diagnostics);
+        seen.extend(part_seen);
     }
+
+    let name = entries.first().map_or_else(String::new, |entry| entry.name.clone());
+    let mut lexer = TextSliceLexer::new(&[], name);
+    diagnostics.extend(Parser::new(&mut lexer, game_data)
+                       .with_options(options)
+                       .parse_with_diagnostics(
+                           &mut FinishFields(&mut result, &seen))?);
+    Ok((result, diagnostics))
+}
+
+/// Choose the token table for the version of the game that wrote a save.
+fn select_table<G: 'static + GameTrait>(entries: &[SaveEntry],
+                                        tables: &TokenTables<G::Static>)
+        -> Result<Option<Arc<TokenTable<G::Static>>>, ParseError> {
+    Ok(match find_savegame_version::<G>(entries, tables.latest())? {
+        Some(version) => tables.select(&version),
+        None => tables.latest()
+    })
 }
 
-fn get_lexer<G: 'static + GameTrait>(mut entry: zip::read::ZipFile,
-             path: &Path) -> Result<Box<dyn Lexer<G>>, ParseError> {
-    let entry_name = format!("{}/{}", path.display(), entry.name());
+/// Load a save game. Zipped saves and plain text or binary saves are all
+/// supported. See [`load_savegame_parallel`] to parse the files of zipped
+/// saves in parallel.
+pub fn load_savegame<G, T>(path: &Path, game_data: &mut GameData)
+        -> Result<T, ParseError>
+    where G: 'static + GameTrait, T: ParadoxParse<G> + Default
+{
+    let entries = read_save(std::fs::read(path)?, &path.display().to_string())?;
+    Ok(parse_entries(&entries, game_data, None, ParseOptions::default())?.0)
+}
+
+/// Load a save game, using the token table that matches the version of the
+/// game that wrote it to read binary files.
+pub fn load_savegame_with_tokens<G, T>(path: &Path, game_data: &mut GameData,
+                                       tables: &TokenTables<G::Static>)
+        -> Result<T, ParseError>
+    where G: 'static + GameTrait, T: ParadoxParse<G> + Default
+{
+    let entries = read_save(std::fs::read(path)?, &path.display().to_string())?;
+    Ok(parse_entries(&entries, game_data, Some(tables),
+                     ParseOptions::default())?.0)
+}
 
-    // Read the first 6 bytes, to determine if it's a text or binary file.
-    let mut magic = [0u8; 6];
-    entry.read_exact(&mut magic)?;
+/// Load a save game, choosing how invalid input is handled. The diagnostics
+/// for the input that was skipped over are returned along with the save.
+/// Binary saves use the matching token table from `tables`, if given.
+pub fn load_savegame_with_options<G, T>(path: &Path, game_data: &mut GameData,
+                                        tables: Option<&TokenTables<G::Static>>,
+                                        options: ParseOptions)
+        -> Result<(T, Diagnostics), ParseError>
+    where G: 'static + GameTrait, T: ParadoxParse<G> + Default
+{
+    let entries = read_save(std::fs::read(path)?, &path.display().to_string())?;
+    parse_entries(&entries, game_data, tables, options)
+}
+
+/// Load a save game like [`load_savegame_with_options`], but parse the files
+/// of zipped saves in parallel, each on its own thread, and merge them with
+/// [`ParadoxMerge`]. The result is the same as parsing them one after the
+/// other.
+pub fn load_savegame_parallel<G, T>(path: &Path, game_data: &mut GameData,
+                                    tables: Option<&TokenTables<G::Static>>,
+                                    options: ParseOptions)
+        -> Result<(T, Diagnostics), ParseError>
+    where G: 'static + GameTrait,
+          T: ParadoxParse<G> + ParadoxParseFields<G> + ParadoxMerge + Default +
+             Send
+{
+    let entries = read_save(std::fs::read(path)?, &path.display().to_string())?;
+    parse_entries_parallel(&entries, game_data, tables, options)
+}
 
-    // Read the rest of the file to a vector. It sucks that we have to do
-    // this, but we can't make the lifetimes work out with entry.
-    let mut data = Vec::with_capacity(entry.size() as usize - 6);
+/// Load a save game from memory. `name` is used in error messages. Binary
+/// saves use the matching token table from `tables`, if given.
+pub fn load_savegame_from_slice<G, T>(data: &[u8], name: &str,
+                                      game_data: &mut GameData,
+                                      tables: Option<&TokenTables<G::Static>>)
+        -> Result<T, ParseError>
+    where G: 'static + GameTrait, T: ParadoxParse<G> + Default
+{
+    let entries = read_save(data.to_vec(), name)?;
+    Ok(parse_entries(&entries, game_data, tables, ParseOptions::default())?.0)
+}
+
+/// Load a save game from a reader, such as stdin or a network stream. The
+/// whole save is read into memory first. See [`load_savegame_from_slice`].
+pub fn load_savegame_from_reader<G, T>(mut reader: impl Read, name: &str,
+                                       game_data: &mut GameData,
+                                       tables: Option<&TokenTables<G::Static>>)
+        -> Result<T, ParseError>
+    where G: 'static + GameTrait, T: ParadoxParse<G> + Default
+{
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    let entries = read_save(data, name)?;
+    Ok(parse_entries(&entries, game_data, tables, ParseOptions::default())?.0)
+}
+
+/// Read only the `meta` file of a zipped save, without decompressing the rest
+/// of the archive.
+fn read_meta(file: File, name: &str) -> Result<SaveEntry, ParseError> {
+    let mut archive = ZipArchive::new(BufReader::new(file))?;
+    let mut entry = archive.by_name("meta")?;
+    let mut data = Vec::with_capacity(entry.size() as usize);
     entry.read_to_end(&mut data)?;
-    let file = std::io::Cursor::new(data);
+    Ok(SaveEntry {
+        name: format!("{}/meta", name),
+        file: "meta".to_owned(),
+        data
+    })
+}
 
-    // Use the magic bytes to choose a text or a binary lexer.
-    if &magic[3..] == b"txt" {
-        Ok(Box::new(TextLexer::new(file, entry_name)) as Box::<dyn Lexer<G>>)
-    } else if &magic[3..] == b"bin" {
-        Ok(Box::new(BinaryLexer::new(file, entry_name)) as Box::<dyn Lexer<G>>)
-    } else {
-        Err(ParseError::Parse(String::from_utf8_lossy(&magic).into()))
+/// Get a lexer that reads a plain save from its file as it goes, so that
+/// only as much of the file is read as is lexed.
+fn plain_lexer<G: 'static + GameTrait>(path: &Path, name: &str,
+        table: Option<Arc<TokenTable<G::Static>>>)
+        -> Result<Box<dyn Lexer<'static, G>>, ParseError> {
+    let mut file = File::open(path)?;
+    let mut magic = [0; 6];
+    file.read_exact(&mut magic)?;
+    match &magic[3..] {
+        b"txt" => Ok(Box::new(TextLexer::new(file, name.to_owned())
+                              .with_encoding(G::ENCODING))),
+        b"bin" => {
+            let lexer = BinaryLexer::<G, _>::new(file, name.to_owned())
+                .with_encoding(G::ENCODING);
+            Ok(match table {
+                Some(table) => Box::new(lexer.with_token_table(table)),
+                None => Box::new(lexer)
+            })
+        },
+        _ => Err(unknown_magic(&magic, name))
+    }
+}
+
+/// Where a [`MetaLexer`] is in the `key = value` entries at the top level.
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum MetaState {
+    Key,
+    Operator,
+    Value,
+    /// Inside the block of a value, with the given depth of braces.
+    Block(u32),
+    Done,
+}
+
+/// A lexer for the start of a plain save, which ends at the first top-level
+/// key that isn't in the schema of the metadata. Plain saves have no separate
+/// `meta` file, but begin with the same keys, so this stops the rest of the
+/// gamestate from being read.
+struct MetaLexer<'a, G: 'static + GameTrait> {
+    lexer: Box<dyn Lexer<'a, G> + 'a>,
+    schema: Schema,
+    state: MetaState,
+}
+
+impl <'a, G: 'static + GameTrait> MetaLexer<'a, G> {
+    fn new(lexer: Box<dyn Lexer<'a, G> + 'a>, schema: Schema) -> Self {
+        Self { lexer, schema, state: MetaState::Key }
+    }
+}
+
+impl <'a, 'b: 'a, G: 'static + GameTrait> Lexer<'a, G> for MetaLexer<'b, G> {
+    fn get_token(&mut self) -> Result<Option<Token<'a, G::Static>>, ParseError> {
+        if self.state == MetaState::Done {
+            return Ok(None);
+        }
+        let token = match self.lexer.get_token()? {
+            Some(token) => token,
+            None => return Ok(None)
+        };
+        let key = match &token {
+            Token::Atom(key) => Some(&**key),
+            Token::String(key) | Token::Word(key) => Some(&**key),
+            _ => None
+        };
+        self.state = match (self.state, &token) {
+            (MetaState::Key, _) if key.is_some_and(
+                    |key| self.schema.child(key).is_some()) =>
+                MetaState::Operator,
+            (MetaState::Key, _) => {
+                self.state = MetaState::Done;
+                return Ok(None);
+            },
+            (MetaState::Operator, Token::LBrace) |
+                (MetaState::Value, Token::LBrace) => MetaState::Block(1),
+            (MetaState::Operator, _) => MetaState::Value,
+            (MetaState::Value, _) => MetaState::Key,
+            (MetaState::Block(1), Token::RBrace) => MetaState::Key,
+            (MetaState::Block(depth), Token::RBrace) =>
+                MetaState::Block(depth - 1),
+            (MetaState::Block(depth), Token::LBrace) =>
+                MetaState::Block(depth + 1),
+            (state, _) => state
+        };
+        Ok(Some(token))
+    }
+
+    fn get_span(&self) -> Span {
+        self.lexer.get_span()
+    }
+}
+
+/// Load only the metadata of a save game, such as its date and the player's
+/// country, which is much faster than loading the whole save. The metadata
+/// is read from the `meta` file of zipped saves, and plain saves are only
+/// read up to the first key that isn't part of the metadata (as described by
+/// the schema of `T`).
+///
+/// Keys that `T` doesn't know are skipped silently, since the metadata of a
+/// plain save may be followed by the rest of the gamestate.
+pub fn load_savegame_meta<G, T>(path: &Path, game_data: &mut GameData,
+                                tables: Option<&TokenTables<G::Static>>)
+        -> Result<T, ParseError>
+    where G: 'static + GameTrait, T: ParadoxParse<G> + ParadoxSchema + Default
+{
+    let name = path.display().to_string();
+    let mut file = File::open(path)?;
+    let mut magic = [0; 4];
+    let is_zip = file.read_exact(&mut magic).is_ok() && &magic == b"PK\x03\x04";
+    if is_zip {
+        file.rewind()?;
+        let entries = [read_meta(file, &name)?];
+        let table = match tables {
+            Some(tables) => select_table::<G>(&entries, tables)?,
+            None => None
+        };
+        return Ok(parse_entry(&entries, game_data, table,
+                              ParseOptions::silent())?.0);
+    }
+
+    let schema = T::schema();
+    let table = match tables {
+        Some(tables) => {
+            let lexer = plain_lexer::<G>(path, &name, tables.latest())?;
+            match find_version(&mut MetaLexer::new(lexer, schema.clone()))? {
+                Some(version) => tables.select(&version),
+                None => tables.latest()
+            }
+        },
+        None => None
+    };
+    let mut lexer = MetaLexer::new(plain_lexer::<G>(path, &name, table)?,
+                                   schema);
+    let mut result = T::default();
+    Parser::new(&mut lexer, game_data)
+        .with_options(ParseOptions::silent())
+        .parse(&mut result)?;
+    Ok(result)
+}
+
+/// A save game found by [`scan_save_dir`].
+pub struct SaveFile<T> {
+    pub path: PathBuf,
+    pub modified: SystemTime,
+    /// The metadata of the save, or the error from loading it.
+    pub meta: Result<T, ParseError>
+}
+
+/// Load the metadata of every save game in a directory, newest first. Saves
+/// that fail to load are still listed, along with their error. Files are
+/// recognized as saves by their extension, like `eu4`.
+pub fn scan_save_dir<G, T>(dir: &Path, extension: &str,
+                           game_data: &mut GameData,
+                           tables: Option<&TokenTables<G::Static>>)
+        -> Result<Vec<SaveFile<T>>, ParseError>
+    where G: 'static + GameTrait, T: ParadoxParse<G> + ParadoxSchema + Default
+{
+    let mut saves = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if path.extension().is_none_or(|ext| ext != extension) {
+            continue;
+        }
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        let meta = load_savegame_meta::<G, T>(&path, game_data, tables);
+        saves.push(SaveFile { path, modified: metadata.modified()?, meta });
+    }
+    saves.sort_by_key(|save| std::cmp::Reverse(save.modified));
+    Ok(saves)
+}
+
+/// Attach the current position of a lexer to an error from a writer.
+fn locate_error<'a, G: GameTrait>(err: ParseError, lexer: &dyn Lexer<'a, G>)
+        -> ParseError {
+    match err {
+        ParseError::Parse(msg, _) =>
+            ParseError::Parse(msg, ErrorLocation::at(lexer.get_span())),
+        err => err
+    }
+}
+
+/// Whether a value described by `schema` is a date, or `None` if the schema
+/// doesn't say, as for unknown keys.
+fn schema_is_date(schema: Option<&Schema>) -> Option<bool> {
+    match schema {
+        Some(Schema::Scalar(name)) => Some(*name == "Date"),
+        // A repeated key, or a list written without braces.
+        Some(Schema::List(element)) => schema_is_date(Some(element)),
+        Some(Schema::Any) | None => None,
+        Some(_) => Some(false)
+    }
+}
+
+/// The schema of the values in a list, given the schema of the block.
+fn element_schema(block: Option<&Schema>) -> Option<Schema> {
+    match block {
+        Some(Schema::List(element)) => Some((**element).clone()),
+        Some(Schema::Any) => Some(Schema::Any),
+        _ => None
     }
 }
 
-pub fn load_savegame<G: 'static + GameTrait, T: ParadoxParse<G> + Default>(
-    path: &Path, game_data: &mut GameData)
-        -> Result<T, ParseError> {
-    let mut archive = ZipArchive::new(BufReader::new(File::open(path)?))?;
-    let mut lexer = ZipLexer::new(&mut archive, path)?;
-    let mut gamestate = T::default();
-    Parser::new(&mut lexer, game_data).parse(&mut gamestate)?;
+/// Convert a binary gamestate file into the text format. Entries that are not
+/// binary files are returned unchanged.
+///
+/// Dates are stored as integers in the binary format, so `schema` (the type
+/// the file is parsed into) is used to find the values that are dates. Where
+/// it doesn't know a key, integers are written as dates when they are the
+/// value of a date key (see [`GameTrait::is_date_key`]). Integers used as
+/// keys, as in history blocks, are always written as dates. In every case the
+/// integer must be a valid date.
+fn melt_entry<G: 'static + GameTrait>(data: &[u8], entry_name: String,
+        table: Option<Arc<TokenTable<G::Static>>>, schema: &Schema)
+        -> Result<Vec<u8>, ParseError> {
+    if data.len() < 6 || &data[3..6] != b"bin" {
+        return Ok(data.to_vec());
+    }
+    let mut output = Vec::with_capacity(data.len() * 2);
+    output.extend_from_slice(&data[..3]);
+    output.extend_from_slice(b"txt\n");
 
-    Ok(gamestate)
+    let mut lexer = get_lexer::<G>(data, entry_name, table, false)?;
+    let mut writer = TextWriter::new(&mut output).with_encoding(G::ENCODING);
+    // The keys and schemas of the enclosing blocks, and of the entry being
+    // read.
+    let mut blocks = vec![(None, Some(schema.clone()))];
+    let mut entry: Option<(Atom<G::Static>, Option<Schema>)> = None;
+    let mut next = lexer.get_token()?;
+    while let Some(token) = next.take() {
+        next = lexer.get_token()?;
+        let is_key = next.as_ref()
+            .is_some_and(|next| next.as_operator().is_some());
+        let token = match token {
+            Token::LBrace => {
+                let block = match entry.take() {
+                    Some((key, schema)) => (Some(key), schema),
+                    None => (None, element_schema(
+                            blocks.last().and_then(|(_, schema)| schema.as_ref())))
+                };
+                blocks.push(block);
+                token
+            },
+            Token::RBrace => {
+                blocks.pop();
+                entry = None;
+                token
+            },
+            _ if token.as_operator().is_some() => token,
+            Token::Rgb | Token::Hsv => token,
+            token => {
+                let (block_key, block_schema) = match blocks.last() {
+                    Some((key, schema)) => (key.as_ref(), schema.as_ref()),
+                    None => (None, None)
+                };
+                let is_date = if is_key {
+                    let key = Atom::try_from(token.clone())?;
+                    let schema = block_schema.and_then(|block| block.child(&key));
+                    entry = Some((key, schema));
+                    true
+                } else {
+                    let (key, schema) = match entry.take() {
+                        Some((key, schema)) => (Some(key), schema),
+                        None => (block_key.cloned(), element_schema(block_schema))
+                    };
+                    schema_is_date(schema.as_ref()).unwrap_or_else(||
+                        key.is_some_and(|key| G::is_date_key(&key)))
+                };
+                match token {
+                    Token::Integer(value) if is_date => {
+                        match Date::from_binary(value) {
+                            Some(date) => Token::Date(date),
+                            None => token
+                        }
+                    },
+                    token => token
+                }
+            }
+        };
+        Emitter::<G>::emit(&mut writer, token).map_err(|err| locate_error(err, &*lexer))?;
+    }
+    Emitter::<G>::finish(&mut writer)
+        .map_err(|err| locate_error(err, &*lexer))?;
+    Ok(output)
 }
 
-pub fn ironmelt<G: 'static + GameTrait>(in_path: &Path, out_path: &Path) -> Result<(), ParseError> {
+/// Convert a save game in the binary format, such as an ironman save, into
+/// the text format. Token names are looked up in the given table first, then
+/// with [`GameTrait::get_binary_token`].
+///
+/// Without the type of the save, dates are only found by the names of their
+/// keys. Use [`ironmelt_typed`] to find them from the type instead.
+pub fn ironmelt<G: 'static + GameTrait>(in_path: &Path, out_path: &Path,
+        table: Option<Arc<TokenTable<G::Static>>>) -> Result<(), ParseError> {
+    ironmelt_with_schema::<G>(in_path, out_path, table, &Schema::Any)
+}
+
+/// Convert a binary save game into the text format, like [`ironmelt`], using
+/// the type the save is loaded into to tell which integers are dates.
+pub fn ironmelt_typed<G, T>(in_path: &Path, out_path: &Path,
+        table: Option<Arc<TokenTable<G::Static>>>) -> Result<(), ParseError>
+    where G: 'static + GameTrait, T: ParadoxSchema
+{
+    ironmelt_with_schema::<G>(in_path, out_path, table, &T::schema())
+}
+
+fn ironmelt_with_schema<G: 'static + GameTrait>(in_path: &Path,
+        out_path: &Path, table: Option<Arc<TokenTable<G::Static>>>,
+        schema: &Schema) -> Result<(), ParseError> {
+    use std::io::Write;
+    use zip::{CompressionMethod, ZipWriter};
+    use zip::write::FileOptions;
+    let mut archive = ZipArchive::new(File::open(in_path)?)?;
+    let mut writer = ZipWriter::new(File::create(out_path)?);
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let name = entry.name().to_owned();
+        let file_opts = FileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .last_modified_time(entry.last_modified())
+            .unix_permissions(entry.unix_mode().unwrap_or(0o644));
+
+        let mut data = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut data)?;
+        let entry_name = format!("{}/{}", in_path.display(), name);
+        let data = melt_entry::<G>(&data, entry_name, table.clone(), schema)?;
+        writer.start_file(&name, file_opts)?;
+        writer.write_all(&data)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+/// Convert a save game into JSON, as an object with a member for each file in
+/// the archive, like `{"meta": {...}, "gamestate": {...}, "ai": {...}}`. Plain
+/// saves have a single `gamestate` member. Both text and binary saves are
+/// supported. Binary saves use the token table for
+/// the version of the game that wrote them, as in
+/// [`load_savegame_with_tokens`]. See [`write_json`] for how values are
+/// converted.
+pub fn savegame_to_json<G: 'static + GameTrait>(path: &Path,
+        writer: &mut dyn std::io::Write, tables: &TokenTables<G::Static>)
+        -> Result<(), ParseError> {
+    let entries = read_save(std::fs::read(path)?, &path.display().to_string())?;
+    let table = select_table::<G>(&entries, tables)?;
+    writer.write_all(b"{")?;
+    for (i, entry) in entries.iter().enumerate() {
+        if i > 0 {
+            writer.write_all(b",")?;
+        }
+        let key = Token::String(entry.file.clone().into());
+        write_json::<G>(&Value::Scalar(key), writer)?;
+        writer.write_all(b":")?;
+        let mut lexer = get_lexer::<G>(&entry.data, entry.name.clone(),
+                                       table.clone(), true)?;
+        write_json::<G>(&Value::from_lexer(&mut *lexer)?, writer)?;
+    }
+    writer.write_all(b"}")?;
+    Ok(())
+}
+
+/// Convert a text file, such as one from the game's `common` directory, into
+/// JSON. See [`write_json`] for how values are converted.
+pub fn file_to_json<G: GameTrait>(path: &Path, writer: &mut dyn std::io::Write)
+        -> Result<(), ParseError> {
+    let contents = std::fs::read(path)?;
+    let mut lexer = TextSliceLexer::new(&contents, path.display().to_string())
+        .with_encoding(G::ENCODING)
+        .with_typed_literals(true);
+    let value = Value::from_lexer::<G>(&mut lexer)?;
+    write_json::<G>(&value, writer)?;
+    Ok(())
+}
+
+/// Convert a text gamestate file into the binary format. Entries that are not
+/// text files are returned unchanged.
+fn unmelt_entry<G: 'static + GameTrait>(data: &[u8], entry_name: String,
+        table: Option<Arc<TokenTable<G::Static>>>)
+        -> Result<Vec<u8>, ParseError> {
+    if data.len() < 6 || &data[3..6] != b"txt" {
+        return Ok(data.to_vec());
+    }
+    let (magic, data) = data.split_at(6);
+    let mut output = Vec::with_capacity(data.len());
+    output.extend_from_slice(&magic[..3]);
+    output.extend_from_slice(b"bin");
+
+    let mut lexer = TextSliceLexer::new(data, entry_name)
+        .with_encoding(G::ENCODING)
+        .with_typed_literals(true);
+    let mut writer = BinaryWriter::<G, _>::new(&mut output)
+        .with_encoding(G::ENCODING);
+    if let Some(table) = table {
+        writer = writer.with_token_table(table);
+    }
+    while let Some(token) = Lexer::<G>::get_token(&mut lexer)? {
+        writer.emit(token).map_err(|err| locate_error::<G>(err, &lexer))?;
+    }
+    Emitter::<G>::finish(&mut writer)?;
+    Ok(output)
+}
+
+/// Convert a save game in the text format into the binary format, which is
+/// the reverse of [`ironmelt`]. Token IDs are looked up in the given table
+/// first, then with [`GameTrait::get_binary_id`].
+///
+/// Lookups and unknown tokens written by `ironmelt` get their original codes
+/// back. Integers are not always the same: the text format doesn't say
+/// whether a number was signed or unsigned, so see [`BinaryWriter`] for how
+/// they are encoded.
+pub fn unmelt<G: 'static + GameTrait>(in_path: &Path, out_path: &Path,
+        table: Option<Arc<TokenTable<G::Static>>>) -> Result<(), ParseError> {
     use std::io::Write;
-    use zip::ZipWriter;
+    use zip::{CompressionMethod, ZipWriter};
     use zip::write::FileOptions;
     let mut archive = ZipArchive::new(File::open(in_path)?)?;
     let mut writer = ZipWriter::new(File::create(out_path)?);
     for i in 0..archive.len() {
-        let entry = archive.by_index(i)?;
-        let name = entry.name();
+        let mut entry = archive.by_index(i)?;
+        let name = entry.name().to_owned();
         let file_opts = FileOptions::default()
-            .compression_method(entry.compression())
+            .compression_method(CompressionMethod::Deflated)
             .last_modified_time(entry.last_modified())
             .unix_permissions(entry.unix_mode().unwrap_or(0o644));
-        writer.start_file(name, file_opts)?;
-        writeln!(writer, "EU4txt")?;
-
-        let mut lexer = get_lexer::<G>(entry, in_path)?;
-        let mut is_array = false;
-        let mut is_key = true;
-        let mut is_array_known = true;
-        let mut indent = String::new();
-        let mut saved_token = None;
-        let mut stack = Vec::new();
-        loop {
-            let token = match lexer.get_token()? {
-                Some(t) => t,
-                None => break
+
+        let mut data = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut data)?;
+        let entry_name = format!("{}/{}", in_path.display(), name);
+        let data = unmelt_entry::<G>(&data, entry_name, table.clone())?;
+        writer.start_file(&name, file_opts)?;
+        writer.write_all(&data)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use string_cache::EmptyStaticAtomSet;
+
+    struct TestGame;
+    impl GameTrait for TestGame {
+        type Static = EmptyStaticAtomSet;
+        fn get_binary_token(id: u16) -> Option<Atom<Self::Static>> {
+            match id {
+                0x2000 => Some("date".into()),
+                0x2001 => Some("player".into()),
+                _ => None
+            }
+        }
+
+        fn get_binary_id(name: &str) -> Option<u16> {
+            match name {
+                "date" => Some(0x2000),
+                "player" => Some(0x2001),
+                _ => None
+            }
+        }
+    }
+
+    fn melt(data: &[u8]) -> Vec<u8> {
+        melt_entry::<TestGame>(data, "input".into(), None, &Schema::Any)
+            .unwrap()
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let mut binary = b"EU4bin".to_vec();
+        let mut add = |bytes: &[u8]| binary.extend_from_slice(bytes);
+        // date = 1444.11.11
+        add(&[0x00, 0x20, 0x01, 0x00, 0x0c, 0x00]);
+        add(&56456976i32.to_le_bytes());
+        // player = "FRA"
+        add(&[0x01, 0x20, 0x01, 0x00, 0x0f, 0x00, 0x03, 0x00]);
+        add(b"FRA");
+        // id = { 1.500 0.25 yes -3 }
+        add(&[0x0b, 0x00, 0x01, 0x00, 0x03, 0x00, 0x0d, 0x00]);
+        add(&1500i32.to_le_bytes());
+        add(&[0x67, 0x01]);
+        add(&16384i64.to_le_bytes());
+        add(&[0x0e, 0x00, 0x01, 0x0c, 0x00]);
+        add(&(-3i32).to_le_bytes());
+        add(&[0x04, 0x00]);
+        // flag = { unquoted }
+        add(&[0x17, 0x00, 0x04, 0x00]);
+        add(b"flag");
+        add(&[0x01, 0x00, 0x03, 0x00, 0x17, 0x00, 0x08, 0x00]);
+        add(b"unquoted");
+        add(&[0x04, 0x00]);
+        // lookups = { with each of the lookup codes }
+        add(&[0x17, 0x00, 0x07, 0x00]);
+        add(b"lookups");
+        add(&[0x01, 0x00, 0x03, 0x00, 0x3e, 0x0d, 0x05, 0x40, 0x0d, 0x06]);
+        add(&[0x3d, 0x0d, 0x07, 0x00, 0x43, 0x0d, 0x08, 0x01]);
+        add(&[0x44, 0x0d, 0x01, 0x02, 0x03, 0x04, 0x00]);
+        // big = { u64::MAX -5000000000 }
+        add(&[0x17, 0x00, 0x03, 0x00]);
+        add(b"big");
+        add(&[0x01, 0x00, 0x03, 0x00, 0x9c, 0x02]);
+        add(&u64::MAX.to_le_bytes());
+        add(&[0x17, 0x03]);
+        add(&(-5000000000i64).to_le_bytes());
+        add(&[0x04, 0x00]);
+        // A token missing from the table.
+        add(&[0x01, 0x20, 0x01, 0x00, 0x00, 0x30]);
+
+        let text = melt(&binary);
+        assert_eq!(String::from_utf8_lossy(&text),
+                   "EU4txt\ndate = 1444.11.11\nplayer = \"FRA\"\n\
+                    id = { 1.500 0.25000 yes -3 }\nflag = { unquoted }\n\
+                    lookups = { __lookup_0x0d3e_5 __lookup_0x0d40_6 \
+                    __lookup_0x0d3d_7 __lookup_0x0d43_264 \
+                    __lookup_0x0d44_197121 }\n\
+                    big = { 18446744073709551615 -5000000000 }\n\
+                    player = __unknown_0x3000\n");
+        let result = unmelt_entry::<TestGame>(&text, "text".into(), None)
+            .unwrap();
+        assert_eq!(result, binary);
+    }
+
+    #[test]
+    fn melt_blocks() {
+        let mut binary = b"EU4bin".to_vec();
+        let mut add = |bytes: &[u8]| binary.extend_from_slice(bytes);
+        // player = { { } { date = 56456976 } }
+        add(&[0x01, 0x20, 0x01, 0x00, 0x03, 0x00, 0x03, 0x00, 0x04, 0x00]);
+        add(&[0x03, 0x00, 0x00, 0x20, 0x01, 0x00, 0x0c, 0x00]);
+        add(&56456976i32.to_le_bytes());
+        add(&[0x04, 0x00, 0x04, 0x00]);
+        // 56456976 = { player = 56456976 }
+        add(&[0x0c, 0x00]);
+        add(&56456976i32.to_le_bytes());
+        add(&[0x01, 0x00, 0x03, 0x00, 0x01, 0x20, 0x01, 0x00, 0x0c, 0x00]);
+        add(&56456976i32.to_le_bytes());
+        add(&[0x04, 0x00]);
+
+        assert_eq!(String::from_utf8_lossy(&melt(&binary)),
+                   "EU4txt\nplayer = { { } {\n\t\tdate = 1444.11.11\n\t} }\n\
+                    1444.11.11 = {\n\tplayer = 56456976\n}\n");
+
+        // Unbalanced braces are an error rather than a panic.
+        let result = melt_entry::<TestGame>(b"EU4bin\x04\x00", "input".into(),
+                                            None, &Schema::Any);
+        assert!(result.is_err());
+        assert_eq!(melt(b"EU4txt\nfoo = bar"), b"EU4txt\nfoo = bar");
+    }
+
+    #[test]
+    fn melt_with_schema() {
+        #[derive(ParadoxParse, ParadoxSchema, Default)]
+        #[paradox(game = "TestGame")]
+        struct Save {
+            start: Date,
+            founding: Vec<Date>,
+            #[repeated] war: Vec<Date>,
+            id_date: i32,
+            history: std::collections::BTreeMap<Date, Value<EmptyStaticAtomSet>>,
+        }
+
+        let text = b"EU4txt\nstart = 1444.11.11\nfounding = { 1444.11.11 }\n\
+                     war = 1444.11.11\nwar = 1444.11.12\nid_date = 56456976\n\
+                     history = {\n\t1444.11.11 = {\n\t\towner = \"FRA\"\n\t}\n}\n\
+                     other_date = 1444.11.11\n";
+        let binary = unmelt_entry::<TestGame>(text, "text".into(), None)
+            .unwrap();
+        let melted = melt_entry::<TestGame>(&binary, "input".into(), None,
+                                            &Save::schema())
+            .unwrap();
+        // The i32 stays an integer despite its name, and the key the struct
+        // doesn't know falls back to the name.
+        assert_eq!(String::from_utf8_lossy(&melted),
+                   String::from_utf8_lossy(text));
+        assert_eq!(String::from_utf8_lossy(&melt(&binary)),
+                   String::from_utf8_lossy(text)
+                       .replace("start = 1444.11.11", "start = 56456976")
+                       .replace("founding = { 1444.11.11 }",
+                                "founding = { 56456976 }")
+                       .replace("war = 1444.11.11", "war = 56456976")
+                       .replace("war = 1444.11.12", "war = 56457000")
+                       .replace("id_date = 56456976",
+                                "id_date = 1444.11.11"));
+    }
+
+    #[test]
+    fn plain_and_zipped_saves() {
+        use std::io::Write;
+        type Tree = Value<EmptyStaticAtomSet>;
+        let load = |data: &[u8]| {
+            let mut game_data = GameData::load(Path::new(".")).unwrap();
+            load_savegame_from_slice::<TestGame, Tree>(data, "save",
+                                                       &mut game_data, None)
+        };
+
+        let text = load(b"EU4txt\nplayer = \"FRA\"").unwrap();
+        assert_eq!(text.get("player").and_then(Value::as_str), Some("FRA"));
+        let binary = load(b"EU4bin\x01\x20\x01\x00\x0f\x00\x03\x00FRA")
+            .unwrap();
+        assert_eq!(binary, text);
+
+        let mut zipped = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::FileOptions::default();
+        zipped.start_file("meta", options).unwrap();
+        zipped.write_all(b"EU4txt\nplayer = \"FRA\"").unwrap();
+        zipped.start_file("gamestate", options).unwrap();
+        zipped.write_all(b"EU4txt\ndate = 1444.11.11").unwrap();
+        let zipped = zipped.finish().unwrap().into_inner();
+        let mut game_data = GameData::load(Path::new(".")).unwrap();
+        let tree: Tree = load_savegame_from_reader::<TestGame, _>(
+            &zipped[..], "save", &mut game_data, None).unwrap();
+        assert_eq!(tree.get("player").and_then(Value::as_str), Some("FRA"));
+        assert_eq!(tree.get("date").and_then(Value::as_str), Some("1444.11.11"));
+
+        assert!(load(b"not a save").is_err());
+        assert!(load(b"").is_err());
+    }
+
+    #[test]
+    fn scan_saves() {
+        use std::io::Write;
+        type Tree = Value<EmptyStaticAtomSet>;
+        let dir = std::env::temp_dir()
+            .join(format!("paradox-scan-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut zipped = zip::ZipWriter::new(
+            File::create(dir.join("old.eu4")).unwrap());
+        let options = zip::write::FileOptions::default();
+        zipped.start_file("meta", options).unwrap();
+        zipped.write_all(b"EU4txt\nplayer = \"FRA\"").unwrap();
+        zipped.start_file("gamestate", options).unwrap();
+        zipped.write_all(b"EU4txt\nunused = yes").unwrap();
+        zipped.finish().unwrap();
+        std::fs::write(dir.join("new.eu4"), b"EU4txt\nplayer = \"ENG\"")
+            .unwrap();
+        std::fs::write(dir.join("broken.eu4"), b"garbage").unwrap();
+        std::fs::write(dir.join("notes.txt"), b"EU4txt\n").unwrap();
+        for (i, name) in ["old.eu4", "new.eu4", "broken.eu4"].iter().enumerate() {
+            let time = SystemTime::UNIX_EPOCH +
+                std::time::Duration::from_secs(1000 * (i as u64 + 1));
+            File::options().write(true).open(dir.join(name)).unwrap()
+                .set_modified(time).unwrap();
+        }
+
+        let mut game_data = GameData::load(Path::new(".")).unwrap();
+        let saves = scan_save_dir::<TestGame, Tree>(&dir, "eu4",
+                                                    &mut game_data, None)
+            .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let names: Vec<_> = saves.iter()
+            .map(|save| save.path.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(names, ["broken.eu4", "new.eu4", "old.eu4"]);
+        assert!(saves[0].meta.is_err());
+        let players: Vec<_> = saves[1..].iter()
+            .map(|save| save.meta.as_ref().unwrap().get("player")
+                 .and_then(Value::as_str))
+            .collect();
+        assert_eq!(players, [Some("ENG"), Some("FRA")]);
+    }
+
+    #[test]
+    fn threaded_matches_sequential() {
+        use std::io::Write;
+
+        #[derive(ParadoxParse, ParadoxWrite, ParadoxMerge, Default, Debug,
+                 PartialEq)]
+        #[paradox(game = "TestGame")]
+        struct Save {
+            player: String,
+            #[optional] speed: i32,
+            #[paradox(default = 5)] size: i32,
+            #[repeated] flag: Vec<String>,
+            missing: i32,
+            #[optional] common: Common,
+        }
+
+        /// A value that loads the `common` directory of the game, like the
+        /// country tags of EU4 do.
+        #[derive(ParadoxWrite, ParadoxMerge, Default, Debug, PartialEq)]
+        #[paradox(game = "TestGame")]
+        struct Common {
+            #[optional] loaded: bool,
+        }
+
+        impl ParadoxParse<TestGame> for Common {
+            fn read(&mut self, parser: &mut Parser<TestGame>)
+                    -> Result<(), ParseError> {
+                parser.skip_value()?;
+                let mut value = Value::<EmptyStaticAtomSet>::default();
+                parser.get_game_data()
+                    .parse_directory::<TestGame>("common", &mut value)?;
+                self.loaded = true;
+                Ok(())
+            }
+        }
+
+        // The speed is set in the meta file, and back to its default value in
+        // the gamestate, as the last value is the one that counts.
+        let meta = b"player = \"FRA\" speed = 3 flag = a";
+        let gamestate = b"speed = 0 flag = b unknown = yes common = yes";
+
+        let mut zipped = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::FileOptions::default();
+        zipped.start_file("meta", options).unwrap();
+        zipped.write_all(b"EU4txt\n").unwrap();
+        zipped.write_all(meta).unwrap();
+        zipped.start_file("gamestate", options).unwrap();
+        zipped.write_all(b"EU4txt\n").unwrap();
+        zipped.write_all(gamestate).unwrap();
+        let zipped = zipped.finish().unwrap().into_inner();
+
+        // The game directory has a file that isn't a .txt file, which the
+        // game data reports.
+        let dir = std::env::temp_dir()
+            .join(format!("paradox-threaded-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("common")).unwrap();
+        std::fs::write(dir.join("common/notes.md"), "not game data").unwrap();
+
+        let entries = read_save(zipped, "save").unwrap();
+        let load = |parallel: bool| {
+            let mut game_data = GameData::load(&dir).unwrap();
+            let parse = if parallel {
+                parse_entries_parallel::<TestGame, Save>
+            } else {
+                parse_entries::<TestGame, Save>
             };
-            match token {
-                Token::LBrace => {
-                    assert!(saved_token.is_none(), "Fuuuckk");
-                    write!(writer, " {{")?;
-                    stack.push((is_array_known, is_array));
-                    is_array_known = false;
-                    is_array = true;
-                },
-                Token::RBrace => {
-                    if let Some(t) = saved_token.take() {
-                        write!(writer, "{}", t)?;
-                        is_array = true;
-                    }
-                    if is_array_known && !is_array {
-                        indent.pop();
-                        indent.pop();
-                    }
-                    writeln!(writer, "{}}}", if is_array { " " } else { "" })?;
-                    write!(writer, "{}", indent)?;
-                    let entry = stack.pop().unwrap();
-                    is_array_known = entry.0;
-                    is_array = entry.1;
-                    is_key = true;
-                },
-                Token::Eq => {
-                    if let Some(t) = saved_token.take() {
-                        is_array = false;
-                        is_array_known = true;
-                        indent.push_str("  ");
-                        write!(writer, "\n{}{}", indent, t)?;
-                    } else {
-                        assert!(is_array_known && !is_array, "Fuck?");
-                    }
-                    write!(writer, " =")?;
-                    is_key = false;
-                },
-                t => {
-                    let as_string = Atom::<G::Static>::from(t);
-                    if is_array_known {
-                        if is_array {
-                            write!(writer, " {}", as_string)?;
-                        } else if is_key {
-                            write!(writer, "{}", as_string)?;
-                            is_key = false;
-                        } else {
-                            write!(writer, " {}\n{}", as_string, indent)?;
-                            is_key = true;
-                        }
-                    } else if let Some(t) = saved_token.take() {
-                        is_array_known = true;
-                        is_array = true;
-                        write!(writer, " {} {}", t, as_string)?;
-                    } else {
-                        saved_token = Some(as_string);
+            let (save, diagnostics) = parse(&entries, &mut game_data, None,
+                                            ParseOptions::lenient())
+                .unwrap();
+            let diagnostics: Vec<_> = diagnostics.iter()
+                .chain(game_data.diagnostics().iter())
+                .map(|d| (d.kind, d.field.clone()))
+                .collect();
+            (save, diagnostics)
+        };
+        let (threaded, threaded_diagnostics) = load(true);
+        let (expected, expected_diagnostics) = load(false);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(threaded, expected);
+        assert_eq!(threaded, Save {
+            player: "FRA".into(), speed: 0, size: 5,
+            flag: vec!["a".into(), "b".into()], missing: 0,
+            common: Common { loaded: true }
+        });
+        assert_eq!(threaded_diagnostics, expected_diagnostics);
+        assert_eq!(threaded_diagnostics, [
+            (DiagnosticKind::UnknownField, "unknown".to_owned()),
+            (DiagnosticKind::MissingField, "missing".to_owned()),
+            (DiagnosticKind::UnexpectedFile, "notes.md".to_owned()),
+        ]);
+    }
+
+    #[test]
+    fn scan_plain_save_meta() {
+        /// Metadata that keeps the keys it doesn't know, to check that the
+        /// rest of the gamestate isn't read.
+        #[derive(Default)]
+        struct Meta {
+            player: String,
+            unknown: Vec<String>,
+        }
+
+        impl ParadoxParse<TestGame> for Meta {
+            fn read(&mut self, parser: &mut Parser<TestGame>)
+                    -> Result<(), ParseError> {
+                parser.parse_key_scope(|key, _, parser| {
+                    if &*key == "player" {
+                        return self.player.read(parser);
                     }
-                },
+                    self.unknown.push(key.to_string());
+                    let token = parser.get_token()?;
+                    parser.validation_error(DiagnosticKind::UnknownField,
+                                            "Meta", &key, "unknown field",
+                                            false, token)
+                })
+            }
+        }
+
+        impl ParadoxSchema for Meta {
+            fn schema() -> Schema {
+                Schema::Struct(StructSchema {
+                    name: "Meta",
+                    fields: vec![FieldSchema {
+                        name: "player",
+                        key: "player",
+                        aliases: vec![],
+                        ty: "String",
+                        cardinality: Cardinality::Required,
+                        has_default: false,
+                        schema: String::schema,
+                    }],
+                })
             }
         }
-        assert!(saved_token.is_none(), "Not going to happen");
+
+        let dir = std::env::temp_dir()
+            .join(format!("paradox-scan-plain-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // The unbalanced brace after the metadata would be an error if the
+        // gamestate were parsed.
+        std::fs::write(dir.join("plain.eu4"),
+                       b"EU4txt\nplayer = \"ENG\"\ncountries = { ENG = { } }\n}")
+            .unwrap();
+        std::fs::write(dir.join("binary.eu4"),
+                       b"EU4bin\x01\x20\x01\x00\x0f\x00\x03\x00FRA\
+                         \x00\x20\x01\x00\x04\x00").unwrap();
+
+        let mut game_data = GameData::load(Path::new(".")).unwrap();
+        let saves = scan_save_dir::<TestGame, Meta>(&dir, "eu4",
+                                                    &mut game_data, None)
+            .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let mut found: Vec<_> = saves.iter()
+            .map(|save| {
+                let meta = save.meta.as_ref().unwrap();
+                (meta.player.as_str(), meta.unknown.len())
+            })
+            .collect();
+        found.sort();
+        assert_eq!(found, [("ENG", 0), ("FRA", 0)]);
     }
-    writer.finish()?;
-    Ok(())
 }
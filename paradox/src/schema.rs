@@ -0,0 +1,240 @@
+use crate::{BoxedValue, Date, FixedPoint, MultiMap, ParadoxParse, TypeDefinition};
+use crate::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use string_cache::{Atom, StaticAtomSet};
+
+/// Types that can describe how they are written in the game files. This can
+/// be derived for types that derive `ParadoxParse`, reading the same
+/// attributes, and is meant for tools such as documentation generators,
+/// editors and coverage reports. The fields of a derived type need to
+/// implement it too.
+pub trait ParadoxSchema {
+    fn schema() -> Schema;
+}
+
+/// A description of how values of a type are written.
+#[derive(Clone, Debug)]
+pub enum Schema {
+    /// A single value, named by its Rust type, like `i32` or `Date`.
+    Scalar(&'static str),
+    /// A list of values in braces.
+    List(Box<Schema>),
+    /// A block of `key = value` entries, whose keys aren't known in advance.
+    Map(Box<Schema>),
+    /// A block with a known set of fields.
+    Struct(StructSchema),
+    /// One of a known set of values, or a block with one of a known set of
+    /// keys.
+    Enum(EnumSchema),
+    /// Any value at all, for types that skip or keep whatever they are given.
+    Any,
+}
+
+/// How many times a field may appear in a block.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Cardinality {
+    /// Exactly once.
+    Required,
+    /// At most once.
+    Optional,
+    /// Any number of times.
+    Repeated,
+    /// Every key that no other field takes, with `#[collect]`, `#[modifiers]`
+    /// or `#[extra]`.
+    CatchAll,
+    /// The fields of another struct, which are part of this block.
+    Flattened,
+}
+
+#[derive(Clone, Debug)]
+pub struct StructSchema {
+    /// The name of the Rust type.
+    pub name: &'static str,
+    pub fields: Vec<FieldSchema>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EnumSchema {
+    /// The name of the Rust type.
+    pub name: &'static str,
+    /// The values of the unit variants.
+    pub values: Vec<&'static str>,
+    /// The newtype variants, which are blocks with the variant's key.
+    pub variants: Vec<FieldSchema>,
+    /// Whether values not in `values` are kept by an `Other` variant.
+    pub has_other: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct FieldSchema {
+    /// The name of the field in Rust.
+    pub name: &'static str,
+    /// The key of the field in the game files. This is empty for fields that
+    /// aren't read from a single key, like `#[collect]` or flattened fields.
+    pub key: &'static str,
+    /// Other keys that are read into the field.
+    pub aliases: Vec<&'static str>,
+    /// The Rust type of the field, as written in the struct.
+    pub ty: &'static str,
+    pub cardinality: Cardinality,
+    /// Whether a missing field is given a value with `#[paradox(default)]`.
+    pub has_default: bool,
+    /// The schema of the field's type. This is a function so that types can
+    /// contain themselves.
+    pub schema: fn() -> Schema,
+}
+
+impl FieldSchema {
+    pub fn schema(&self) -> Schema {
+        (self.schema)()
+    }
+}
+
+impl Schema {
+    /// The schema of the value of `key` in a block of this type, or `None` if
+    /// this type doesn't read that key. This is what tells a melter that the
+    /// integer in `start = 43800` is a date.
+    pub fn child(&self, key: &str) -> Option<Schema> {
+        match self {
+            Schema::Map(value) => Some((**value).clone()),
+            Schema::Struct(schema) => match schema.field(key) {
+                Some(field) => Some(field.schema()),
+                None => schema.catch_all().map(|schema| match schema {
+                    Schema::Map(value) => *value,
+                    _ => Schema::Any
+                }),
+            },
+            Schema::Enum(schema) => schema.variants.iter()
+                .find(|variant| variant.key == key)
+                .map(FieldSchema::schema),
+            Schema::Any => Some(Schema::Any),
+            Schema::Scalar(_) | Schema::List(_) => None,
+        }
+    }
+}
+
+impl StructSchema {
+    /// The schema of the field that takes the keys no other field does, if
+    /// there is one, looking through flattened structs.
+    pub fn catch_all(&self) -> Option<Schema> {
+        self.fields.iter().find_map(|field| match field.cardinality {
+            Cardinality::CatchAll => Some(field.schema()),
+            Cardinality::Flattened => match field.schema() {
+                Schema::Struct(schema) => schema.catch_all(),
+                _ => None
+            },
+            _ => None
+        })
+    }
+
+    /// Find the field that reads a key, looking through flattened structs.
+    /// Fields that take any key are not returned.
+    pub fn field(&self, key: &str) -> Option<FieldSchema> {
+        self.fields.iter().find_map(|field| match field.cardinality {
+            Cardinality::CatchAll => None,
+            Cardinality::Flattened => match field.schema() {
+                Schema::Struct(schema) => schema.field(key),
+                _ => None
+            },
+            _ if field.key == key || field.aliases.contains(&key) =>
+                Some(field.clone()),
+            _ => None
+        })
+    }
+}
+
+macro_rules! scalar_schema {
+    {$($T:ty),*} => {
+        $(
+            impl ParadoxSchema for $T {
+                fn schema() -> Schema {
+                    Schema::Scalar(stringify!($T))
+                }
+            }
+        )*
+    }
+}
+
+scalar_schema!{i32, u32, i64, u64, f32, f64, bool, String, FixedPoint, Date}
+
+impl <Static: StaticAtomSet> ParadoxSchema for Atom<Static> {
+    fn schema() -> Schema {
+        Schema::Scalar("Atom")
+    }
+}
+
+impl ParadoxSchema for () {
+    fn schema() -> Schema {
+        Schema::Any
+    }
+}
+
+impl <Static: StaticAtomSet> ParadoxSchema for Value<Static> {
+    fn schema() -> Schema {
+        Schema::Any
+    }
+}
+
+impl <T: ParadoxSchema, const N: usize> ParadoxSchema for [T; N] {
+    fn schema() -> Schema {
+        Schema::List(Box::new(T::schema()))
+    }
+}
+
+impl <T: ParadoxSchema> ParadoxSchema for Vec<T> {
+    fn schema() -> Schema {
+        Schema::List(Box::new(T::schema()))
+    }
+}
+
+impl <T: ParadoxSchema> ParadoxSchema for HashSet<T> {
+    fn schema() -> Schema {
+        Schema::List(Box::new(T::schema()))
+    }
+}
+
+impl <K, T: ParadoxSchema> ParadoxSchema for HashMap<K, T> {
+    fn schema() -> Schema {
+        Schema::Map(Box::new(T::schema()))
+    }
+}
+
+impl <K, T: ParadoxSchema> ParadoxSchema for BTreeMap<K, T> {
+    fn schema() -> Schema {
+        Schema::Map(Box::new(T::schema()))
+    }
+}
+
+impl <K, T: ParadoxSchema> ParadoxSchema for MultiMap<K, T> {
+    fn schema() -> Schema {
+        Schema::Map(Box::new(T::schema()))
+    }
+}
+
+impl <T> ParadoxSchema for TypeDefinition<T>
+    where T: BoxedValue + ParadoxParse<T::Trait> + ParadoxSchema
+{
+    fn schema() -> Schema {
+        Schema::Map(Box::new(T::schema()))
+    }
+}
+
+/// A `key = value` pair is described by its value, since the key can be
+/// anything.
+impl <K, T: ParadoxSchema> ParadoxSchema for (K, T) {
+    fn schema() -> Schema {
+        T::schema()
+    }
+}
+
+impl <T: ParadoxSchema> ParadoxSchema for Option<T> {
+    fn schema() -> Schema {
+        T::schema()
+    }
+}
+
+impl <T: ParadoxSchema> ParadoxSchema for Box<T> {
+    fn schema() -> Schema {
+        T::schema()
+    }
+}
@@ -0,0 +1,236 @@
+use crate::{ErrorLocation, ParseError, Position, Span};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use string_cache::{Atom, StaticAtomSet};
+
+/// A mapping from the IDs of binary tokens to their names.
+///
+/// The binary format replaces the keys (and some values) in a file with 16-bit
+/// IDs, which can change with each patch of the game. Tables are read from text
+/// files with one token per line, like `0x2c4a name`; IDs are hexadecimal and
+/// need the `0x` prefix. Blank lines and lines starting with `#` are ignored.
+pub struct TokenTable<Static: StaticAtomSet> {
+    names: HashMap<u16, Atom<Static>>,
+    ids: HashMap<Atom<Static>, u16>
+}
+
+impl <Static: StaticAtomSet> Default for TokenTable<Static> {
+    fn default() -> Self {
+        Self { names: HashMap::new(), ids: HashMap::new() }
+    }
+}
+
+impl <Static: StaticAtomSet> TokenTable<Static> {
+    /// Parse a token table from the contents of a token file. The filename is
+    /// only used for error messages.
+    pub fn parse(text: &str, filename: &str) -> Result<Self, ParseError> {
+        let mut table = Self::default();
+        for (line_no, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |msg: &str| ParseError::Parse(msg.into(),
+                ErrorLocation::at(Span {
+                    filename: filename.into(),
+                    position: Position::Text {
+                        line: line_no as u32 + 1, column: 1
+                    }
+                }));
+            let mut pieces = line.split_whitespace();
+            let (id, name) = match (pieces.next(), pieces.next(), pieces.next()) {
+                (Some(id), Some(name), None) => (id, name),
+                _ => return Err(error("expected an ID and a name")),
+            };
+            let id = id.strip_prefix("0x")
+                .and_then(|hex| u16::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| error("invalid token ID, expected 0x and a \
+                                      hexadecimal number"))?;
+            if table.names.insert(id, name.into()).is_some() {
+                return Err(error(&format!("duplicate token ID {:#06x}", id)));
+            }
+            table.ids.entry(name.into()).or_insert(id);
+        }
+        Ok(table)
+    }
+
+    /// Load a token table from the given file.
+    pub fn load(path: &Path) -> Result<Self, ParseError> {
+        let text = std::fs::read_to_string(path)?;
+        Self::parse(&text, &path.display().to_string())
+    }
+
+    /// Get the name of the token with the given ID.
+    pub fn get(&self, id: u16) -> Option<Atom<Static>> {
+        self.names.get(&id).cloned()
+    }
+
+    /// Get the ID of the token with the given name. If a name is listed more
+    /// than once, this is its first ID.
+    pub fn id(&self, name: &str) -> Option<u16> {
+        self.ids.get(&Atom::from(name)).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+/// Split a version like `1.34.5.0` into its numeric components, so that
+/// versions can be compared. Trailing zeros are dropped, so `1.34` and
+/// `1.34.0` are the same version. Returns `None` if it isn't a version.
+fn version_key(version: &str) -> Option<Vec<u32>> {
+    let mut key: Vec<u32> = version.split('.')
+        .map(|piece| piece.trim().parse().ok())
+        .collect::<Option<_>>()?;
+    while key.last() == Some(&0) {
+        key.pop();
+    }
+    Some(key)
+}
+
+/// A set of token tables for different versions of a game.
+pub struct TokenTables<Static: StaticAtomSet> {
+    /// The tables, sorted by version.
+    tables: Vec<(Vec<u32>, Arc<TokenTable<Static>>)>
+}
+
+impl <Static: StaticAtomSet> Default for TokenTables<Static> {
+    fn default() -> Self {
+        Self { tables: Vec::new() }
+    }
+}
+
+impl <Static: StaticAtomSet> TokenTables<Static> {
+    /// Load every table in a directory. Each file is named after the first
+    /// game version it applies to, such as `1.34.txt`; other `.txt` files are
+    /// an error.
+    pub fn load_directory(dir: &Path) -> Result<Self, ParseError> {
+        let mut tables = Self::default();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension() != Some("txt".as_ref()) {
+                continue;
+            }
+            let version = path.file_stem().and_then(|s| s.to_str())
+                .unwrap_or_default();
+            tables.add(version, TokenTable::load(&path)?).map_err(|err| {
+                ParseError::Parse(format!("token table {}: {}", path.display(),
+                                          err), ErrorLocation::default())
+            })?;
+        }
+        Ok(tables)
+    }
+
+    /// Add a table for the given version and all later versions. The version
+    /// must be made of numbers separated by dots, like `1.34`, and must not
+    /// have a table already.
+    pub fn add(&mut self, version: &str, table: TokenTable<Static>)
+            -> Result<(), ParseError> {
+        let error = |msg: &str| ParseError::Parse(
+            format!("{} game version {}", msg, version),
+            ErrorLocation::default());
+        let key = version_key(version).ok_or_else(|| error("invalid"))?;
+        let index = self.tables.partition_point(|(v, _)| v <= &key);
+        if index > 0 && self.tables[index - 1].0 == key {
+            return Err(error("duplicate"));
+        }
+        self.tables.insert(index, (key, Arc::new(table)));
+        Ok(())
+    }
+
+    /// Choose the table to use for a save with the given version, as found in
+    /// its `savegame_versions`. This is the newest table that is not newer
+    /// than the save, or the oldest table if the save predates all of them.
+    /// If the version can't be read, this is the newest table.
+    pub fn select(&self, version: &str) -> Option<Arc<TokenTable<Static>>> {
+        let key = match version_key(version) {
+            Some(key) => key,
+            None => return self.latest()
+        };
+        let index = self.tables.partition_point(|(v, _)| v <= &key);
+        self.tables.get(index.saturating_sub(1))
+            .map(|(_, table)| table.clone())
+    }
+
+    /// Get the table for the newest version of the game.
+    pub fn latest(&self) -> Option<Arc<TokenTable<Static>>> {
+        self.tables.last().map(|(_, table)| table.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use string_cache::EmptyStaticAtomSet;
+
+    type Table = TokenTable<EmptyStaticAtomSet>;
+
+    #[test]
+    fn parse_table() {
+        let table = Table::parse("# comment\n0x2c4a name\n\n0x0010  other\n",
+                                 "tokens.txt").unwrap();
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.get(0x2c4a), Some("name".into()));
+        assert_eq!(table.get(0x0010), Some("other".into()));
+        assert_eq!(table.get(0x0011), None);
+        assert_eq!(table.id("other"), Some(0x0010));
+        assert_eq!(table.id("missing"), None);
+
+        assert!(Table::parse("0x2c4a name\n0x2c4a dup", "t").is_err());
+        assert!(Table::parse("0x2c4a", "t").is_err());
+        assert!(Table::parse("0xfffff name", "t").is_err());
+        assert!(Table::parse("2c4a name", "t").is_err());
+        assert!(Table::parse("10 name", "t").is_err());
+    }
+
+    #[test]
+    fn select_table() {
+        let mut tables = TokenTables::default();
+        tables.add("1.30", Table::parse("0x0020 thirty", "t").unwrap())
+            .unwrap();
+        tables.add("1.34.2", Table::parse("0x0020 thirty_four", "t").unwrap())
+            .unwrap();
+        tables.add("1.31", Table::parse("0x0020 thirty_one", "t").unwrap())
+            .unwrap();
+        assert!(tables.add("latest", Table::default()).is_err());
+        assert!(tables.add("1.x", Table::default()).is_err());
+        let err = tables.add("1.31.0", Table::default()).err().unwrap();
+        assert!(err.to_string().contains("duplicate game version 1.31.0"),
+                "{}", err);
+        let name = |version| tables.select(version).unwrap().get(0x0020);
+        assert_eq!(name("1.29.1.0"), Some("thirty".into()));
+        assert_eq!(name("1.30.4.0"), Some("thirty".into()));
+        assert_eq!(name("1.33.3.0"), Some("thirty_one".into()));
+        assert_eq!(name("1.34.2.0"), Some("thirty_four".into()));
+        assert_eq!(name("1.35.1.0"), Some("thirty_four".into()));
+        assert_eq!(name("unknown"), Some("thirty_four".into()));
+        assert_eq!(tables.latest().unwrap().get(0x0020),
+                   Some("thirty_four".into()));
+    }
+
+    #[test]
+    fn load_table_directory() {
+        let dir = std::env::temp_dir()
+            .join(format!("paradox-tokens-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("1.30.txt"), "0x0020 thirty").unwrap();
+        std::fs::write(dir.join("notes.md"), "not a table").unwrap();
+        let tables = TokenTables::<EmptyStaticAtomSet>::load_directory(&dir)
+            .unwrap();
+        assert_eq!(tables.latest().unwrap().get(0x0020),
+                   Some("thirty".into()));
+
+        std::fs::write(dir.join("latest.txt"), "0x0020 latest").unwrap();
+        let err = TokenTables::<EmptyStaticAtomSet>::load_directory(&dir)
+            .err().unwrap();
+        assert!(err.to_string().contains("latest.txt"));
+        assert!(err.to_string().contains("invalid game version"), "{}", err);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
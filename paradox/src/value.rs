@@ -0,0 +1,456 @@
+use crate::{GameTrait, Lexer, Operator, ParadoxMerge, ParadoxParse, ParadoxWrite};
+use crate::{ParadoxParseFields, ParadoxWriteFields, ParseError, SeenFields};
+use crate::{Parser, Token, Writer};
+use derivative::Derivative;
+use std::convert::TryFrom;
+use string_cache::{Atom, StaticAtomSet};
+
+type Result<T> = std::result::Result<T, ParseError>;
+
+/// A generic document tree, which can hold the contents of any file.
+///
+/// Blocks in braces are classified by their contents: `Object` if they only
+/// hold `key = value` entries, `Array` if they only hold bare values, and
+/// `Mixed` if they hold both. Empty blocks are arrays. Entries are kept in
+/// order, including any duplicate keys.
+#[derive(Derivative)]
+#[derivative(Debug(bound=""), Clone(bound=""), PartialEq(bound=""))]
+pub enum Value<Static: StaticAtomSet> {
+    /// A single token, such as a string, a number or a bare word.
+    Scalar(Token<'static, Static>),
+    /// A color, like `rgb { 255 0 0 }`. The token is `Rgb` or `Hsv`.
+    Color(Token<'static, Static>, Vec<Value<Static>>),
+    Object(Vec<Entry<Static>>),
+    Array(Vec<Value<Static>>),
+    Mixed(Vec<Item<Static>>),
+}
+
+/// A `key = value` entry in a block. The operator is usually `=`, but trigger
+/// blocks may use comparisons.
+#[derive(Derivative)]
+#[derivative(Debug(bound=""), Clone(bound=""), PartialEq(bound=""))]
+pub struct Entry<Static: StaticAtomSet> {
+    pub key: Atom<Static>,
+    pub op: Operator,
+    pub value: Value<Static>,
+}
+
+/// An element of a block that holds both entries and bare values.
+#[derive(Derivative)]
+#[derivative(Debug(bound=""), Clone(bound=""), PartialEq(bound=""))]
+pub enum Item<Static: StaticAtomSet> {
+    Entry(Entry<Static>),
+    Value(Value<Static>),
+}
+
+impl <Static: StaticAtomSet> Default for Value<Static> {
+    fn default() -> Self {
+        Value::Object(Vec::new())
+    }
+}
+
+impl <Static: StaticAtomSet> Value<Static> {
+    /// Read an entire file from a lexer.
+    pub fn from_lexer<'a, G>(lexer: &mut dyn Lexer<'a, G>) -> Result<Self>
+        where G: GameTrait<Static = Static>
+    {
+        let mut source = LexerSource { lexer, saved: Vec::new() };
+        read_block(&mut source, true).map_err(|mut err| {
+            let location = err.location_mut();
+            if location.span.is_none() {
+                location.span = Some(source.lexer.get_span());
+            }
+            err
+        })
+    }
+
+    /// The entries of an object or mixed block, in order.
+    pub fn entries(&self) -> impl Iterator<Item = &Entry<Static>> {
+        let (entries, items) = match self {
+            Value::Object(entries) => (&entries[..], &[][..]),
+            Value::Mixed(items) => (&[][..], &items[..]),
+            _ => (&[][..], &[][..])
+        };
+        entries.iter().chain(items.iter().filter_map(|item| match item {
+            Item::Entry(entry) => Some(entry),
+            Item::Value(_) => None
+        }))
+    }
+
+    /// Get the value of the first entry with the given key.
+    pub fn get<'s>(&'s self, key: &str) -> Option<&'s Value<Static>> {
+        self.get_all(key).next()
+    }
+
+    /// Get the values of every entry with the given key, in order.
+    pub fn get_all<'s, 'k>(&'s self, key: &'k str)
+            -> impl Iterator<Item = &'s Value<Static>> + 'k where 's: 'k {
+        self.entries()
+            .filter(move |entry| &*entry.key == key)
+            .map(|entry| &entry.value)
+    }
+
+    pub fn as_scalar(&self) -> Option<&Token<'static, Static>> {
+        match self {
+            Value::Scalar(token) => Some(token),
+            _ => None
+        }
+    }
+
+    /// Get the text of a string or bare word.
+    pub fn as_str(&self) -> Option<&str> {
+        self.as_scalar().and_then(|token| token.try_to_string().ok())
+    }
+
+    /// Add an entry to the end of a block. Other values become a mixed block
+    /// holding the value and then the entry.
+    pub fn push_entry(&mut self, entry: Entry<Static>) {
+        match self {
+            Value::Object(entries) => entries.push(entry),
+            Value::Mixed(items) => items.push(Item::Entry(entry)),
+            _ => {
+                let mut items = into_items(std::mem::take(self));
+                items.push(Item::Entry(entry));
+                *self = from_items(items);
+            }
+        }
+    }
+
+    /// Get the elements of an array or a color.
+    pub fn as_array(&self) -> Option<&[Value<Static>]> {
+        match self {
+            Value::Array(values) | Value::Color(_, values) => Some(values),
+            _ => None
+        }
+    }
+}
+
+/// Where the tree builder gets its tokens from: either a parser, when reading
+/// a field of a typed struct, or a bare lexer.
+trait TokenSource<'a, Static: StaticAtomSet> {
+    fn next_token(&mut self) -> Result<Option<Token<'a, Static>>>;
+    fn unget(&mut self, token: Token<'a, Static>) -> Result<()>;
+}
+
+impl <'a, G: GameTrait> TokenSource<'a, G::Static> for Parser<'a, G> {
+    fn next_token(&mut self) -> Result<Option<Token<'a, G::Static>>> {
+        self.get_token()
+    }
+
+    fn unget(&mut self, token: Token<'a, G::Static>) -> Result<()> {
+        Parser::unget(self, token)
+    }
+}
+
+struct LexerSource<'l, 'a, G: GameTrait> {
+    lexer: &'l mut dyn Lexer<'a, G>,
+    /// Tokens that were put back, the next one last.
+    saved: Vec<Token<'a, G::Static>>
+}
+
+impl <'a, G: GameTrait> TokenSource<'a, G::Static> for LexerSource<'_, 'a, G> {
+    fn next_token(&mut self) -> Result<Option<Token<'a, G::Static>>> {
+        match self.saved.pop() {
+            Some(token) => Ok(Some(token)),
+            None => self.lexer.get_token()
+        }
+    }
+
+    fn unget(&mut self, token: Token<'a, G::Static>) -> Result<()> {
+        self.saved.push(token);
+        Ok(())
+    }
+}
+
+/// Check if a token is the prefix of a color. Lexers without typed literals
+/// return these as atoms.
+pub(crate) fn color_space<Static: StaticAtomSet>(token: &Token<'_, Static>)
+        -> Option<Token<'static, Static>> {
+    match token {
+        Token::Rgb => Some(Token::Rgb),
+        Token::Hsv => Some(Token::Hsv),
+        Token::Word(word) if word == "rgb" => Some(Token::Rgb),
+        Token::Word(word) if word == "hsv" => Some(Token::Hsv),
+        Token::Atom(atom) if &**atom == "rgb" => Some(Token::Rgb),
+        Token::Atom(atom) if &**atom == "hsv" => Some(Token::Hsv),
+        _ => None
+    }
+}
+
+/// Read a value, given its first token.
+fn read_value<'a, Static: StaticAtomSet>(
+    first: Token<'a, Static>, source: &mut dyn TokenSource<'a, Static>)
+        -> Result<Value<Static>> {
+    if let Some(space) = color_space(&first) {
+        match source.next_token()? {
+            Some(Token::LBrace) => {},
+            Some(token) => {
+                source.unget(token)?;
+                return Ok(Value::Scalar(first.into_owned()));
+            },
+            None => return Ok(Value::Scalar(first.into_owned())),
+        }
+        return match read_block(source, false)? {
+            Value::Array(values) => Ok(Value::Color(space, values)),
+            _ => Err(ParseError::Parse(
+                    "expected a list of color components".into(),
+                    Default::default()))
+        };
+    }
+    match first {
+        Token::LBrace => read_block(source, false),
+        token if token.as_operator().is_some() => Err(token.into()),
+        Token::RBrace => Err(first.into()),
+        token => Ok(Value::Scalar(token.into_owned())),
+    }
+}
+
+/// Read the contents of a block, after its opening brace. The top level of a
+/// file is a block without braces, which ends at the end of the file instead.
+fn read_block<'a, Static: StaticAtomSet>(
+    source: &mut dyn TokenSource<'a, Static>, top: bool)
+        -> Result<Value<Static>> {
+    let mut items = Vec::new();
+    loop {
+        let first = match (source.next_token()?, top) {
+            (None, true) | (Some(Token::RBrace), false) => break,
+            (None, false) => return Err(ParseError::eof()),
+            (Some(token @ Token::RBrace), true) => return Err(token.into()),
+            (Some(token), _) => token,
+        };
+        if first == Token::LBrace || first.as_operator().is_some() {
+            items.push(Item::Value(read_value(first, source)?));
+            continue;
+        }
+        match source.next_token()? {
+            Some(token) if token.as_operator().is_some() => {
+                let op = token.as_operator().unwrap();
+                let value = source.next_token()?.ok_or_else(ParseError::eof)?;
+                items.push(Item::Entry(Entry {
+                    key: Atom::try_from(first)?,
+                    op,
+                    value: read_value(value, source)?
+                }));
+            },
+            // A `key { ... }` entry, without the `=`.
+            Some(Token::LBrace) if color_space(&first).is_none() => {
+                items.push(Item::Entry(Entry {
+                    key: Atom::try_from(first)?,
+                    op: Operator::Eq,
+                    value: read_block(source, false)?
+                }));
+            },
+            Some(token) => {
+                source.unget(token)?;
+                items.push(Item::Value(read_value(first, source)?));
+            },
+            None => items.push(Item::Value(read_value(first, source)?)),
+        }
+    }
+
+    Ok(from_items(items))
+}
+
+/// Build a block from its items: an object if they are all entries, an array
+/// if none of them are, and a mixed block otherwise.
+fn from_items<Static: StaticAtomSet>(items: Vec<Item<Static>>) -> Value<Static> {
+    let is_entry = |item: &Item<Static>| matches!(item, Item::Entry(_));
+    if items.iter().all(|item| !is_entry(item)) {
+        Value::Array(items.into_iter().filter_map(|item| match item {
+            Item::Value(value) => Some(value),
+            Item::Entry(_) => None
+        }).collect())
+    } else if items.iter().all(is_entry) {
+        Value::Object(items.into_iter().filter_map(|item| match item {
+            Item::Entry(entry) => Some(entry),
+            Item::Value(_) => None
+        }).collect())
+    } else {
+        Value::Mixed(items)
+    }
+}
+
+/// The items of a block, or the value itself if it isn't one.
+fn into_items<Static: StaticAtomSet>(value: Value<Static>) -> Vec<Item<Static>> {
+    match value {
+        Value::Object(entries) => entries.into_iter().map(Item::Entry).collect(),
+        Value::Array(values) => values.into_iter().map(Item::Value).collect(),
+        Value::Mixed(items) => items,
+        value => vec![Item::Value(value)]
+    }
+}
+
+/// Merging two blocks appends the items of `other`, keeping duplicate keys.
+impl <Static: StaticAtomSet> ParadoxMerge for Value<Static> {
+    fn merge(&mut self, other: Self) {
+        let mut items = into_items(std::mem::take(self));
+        items.extend(into_items(other));
+        *self = from_items(items);
+    }
+}
+
+impl <G: GameTrait> ParadoxParse<G> for Value<G::Static> {
+    fn read(&mut self, parser: &mut Parser<G>) -> Result<()> {
+        *self = if parser.is_top_level() {
+            read_block(parser, true)?
+        } else {
+            let first = parser.get_token()?.ok_or_else(ParseError::eof)?;
+            read_value(first, parser)?
+        };
+        Ok(())
+    }
+}
+
+/// A block can be the `#[extra]` field of a struct, which keeps every entry
+/// that the struct has no field for, in order.
+impl <G: GameTrait> ParadoxParseFields<G> for Value<G::Static> {
+    fn read_field(&mut self, key: &Atom<G::Static>, op: Operator,
+                  parser: &mut Parser<G>, _seen: &mut SeenFields)
+            -> Result<bool> {
+        let mut value = Value::default();
+        ParadoxParse::read(&mut value, parser)?;
+        self.push_entry(Entry { key: key.clone(), op, value });
+        Ok(true)
+    }
+
+    fn finish_fields(&mut self, _parser: &mut Parser<G>, _seen: &SeenFields)
+            -> Result<()> {
+        Ok(())
+    }
+}
+
+impl <G: GameTrait> ParadoxWriteFields<G> for Value<G::Static> {
+    fn write_fields(&self, writer: &mut Writer<G>) -> Result<()> {
+        match self {
+            Value::Object(entries) =>
+                entries.iter().try_for_each(|entry| entry.write(writer)),
+            Value::Array(values) =>
+                values.iter().try_for_each(|value| value.write(writer)),
+            Value::Mixed(items) => items.iter().try_for_each(|item| match item {
+                Item::Entry(entry) => entry.write(writer),
+                Item::Value(value) => value.write(writer),
+            }),
+            value => value.write(writer),
+        }
+    }
+}
+
+impl <G: GameTrait> ParadoxWrite<G> for Value<G::Static> {
+    fn write(&self, writer: &mut Writer<G>) -> Result<()> {
+        match self {
+            Value::Scalar(token) => writer.write_token(token.clone()),
+            Value::Color(space, values) => {
+                writer.write_token(space.clone())?;
+                writer.with_scope(|writer| {
+                    values.iter().try_for_each(|value| value.write(writer))
+                })
+            },
+            Value::Object(_) | Value::Mixed(_) => writer.write_key_scope(|writer| {
+                self.write_fields(writer)
+            }),
+            Value::Array(_) => writer.with_scope(|writer| {
+                self.write_fields(writer)
+            }),
+        }
+    }
+
+    fn is_default(&self) -> bool {
+        matches!(self, Value::Object(entries) if entries.is_empty())
+    }
+}
+
+impl <Static: StaticAtomSet> Entry<Static> {
+    fn write<G>(&self, writer: &mut Writer<G>) -> Result<()>
+        where G: GameTrait<Static = Static>
+    {
+        writer.write_field_with(&self.key, self.op, &self.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TextSliceLexer, TextWriter};
+    use string_cache::EmptyStaticAtomSet;
+
+    struct TestGame;
+    impl GameTrait for TestGame {
+        type Static = EmptyStaticAtomSet;
+        fn get_binary_token(_: u16) -> Option<Atom<Self::Static>> { None }
+    }
+
+    type TestValue = Value<EmptyStaticAtomSet>;
+
+    fn parse(input: &str) -> TestValue {
+        let mut lexer = TextSliceLexer::new(input.as_bytes(), "input".into());
+        Value::from_lexer::<TestGame>(&mut lexer).unwrap()
+    }
+
+    #[test]
+    fn build_tree() {
+        let value = parse("a = 1 b = { x y } a = { c > 2 }\n\
+                           d = { 1 e = f } color = rgb { 1 2 3 } g { }");
+        assert_eq!(value.get_all("a").count(), 2);
+        assert_eq!(value.get("a").unwrap().as_str(), Some("1"));
+        let b = value.get("b").unwrap().as_array().unwrap();
+        assert_eq!(b.iter().map(|v| v.as_str().unwrap()).collect::<Vec<_>>(),
+                   vec!["x", "y"]);
+        let c = value.get_all("a").nth(1).unwrap().entries().next().unwrap();
+        assert_eq!(c.op, Operator::Gt);
+        assert!(matches!(value.get("d"), Some(Value::Mixed(items))
+                         if items.len() == 2));
+        assert!(matches!(value.get("color"),
+                         Some(Value::Color(Token::Rgb, values))
+                         if values.len() == 3));
+        assert_eq!(value.get("g"), Some(&Value::Array(vec![])));
+
+        let mut lexer = TextSliceLexer::new(b"a = { 1", "input".into());
+        let err = Value::from_lexer::<TestGame>(&mut lexer).unwrap_err();
+        assert!(matches!(err, ParseError::Eof(_)));
+
+        // Put back tokens come out again in order, however many there are.
+        let mut lexer = TextSliceLexer::new(b"c", "input".into());
+        let mut source = LexerSource::<TestGame> {
+            lexer: &mut lexer, saved: Vec::new()
+        };
+        source.unget(Token::Word("b".into())).unwrap();
+        source.unget(Token::Word("a".into())).unwrap();
+        let tokens: Vec<_> = std::iter::from_fn(|| source.next_token().unwrap())
+            .collect();
+        assert_eq!(tokens, ["a", "b", "c"].map(|t| Token::Word(t.into())));
+    }
+
+    #[test]
+    fn typed_field() {
+        let mut data = crate::GameData::load(std::path::Path::new(".")).unwrap();
+        let mut lexer = TextSliceLexer::new(b"a = { b = 1 b = 2 } c = 3",
+                                            "input".into());
+        let mut map: std::collections::HashMap<String, TestValue> =
+            Default::default();
+        Parser::<TestGame>::new(&mut lexer, &mut data).parse(&mut map)
+            .unwrap();
+        assert_eq!(map["a"].get_all("b").count(), 2);
+        assert_eq!(map["c"].as_str(), Some("3"));
+    }
+
+    #[test]
+    fn write_tree() {
+        let input = "a = 1\nb = { x y }\na = {\n\tc > 2\n}\n\
+                     d = { 1 e = f }\ncolor = rgb { 1 2 3 }\n";
+        let value = parse(input);
+        let mut output = Vec::new();
+        let mut emitter = TextWriter::new(&mut output);
+        Writer::<TestGame>::new(&mut emitter).write(&value).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), input);
+
+        // Dates from typed literals are written back as dates.
+        let input = "start = 1444.11.11\nn = 5\n";
+        let mut lexer = TextSliceLexer::new(input.as_bytes(), "input".into())
+            .with_typed_literals(true);
+        let value = Value::from_lexer::<TestGame>(&mut lexer).unwrap();
+        let mut output = Vec::new();
+        let mut emitter = TextWriter::new(&mut output);
+        Writer::<TestGame>::new(&mut emitter).write(&value).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), input);
+    }
+}
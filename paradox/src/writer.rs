@@ -0,0 +1,495 @@
+use crate::{escape, BinaryF32, Encoding, GameTrait, Operator, ParseError};
+use crate::{Token, TokenTable};
+use std::convert::TryFrom;
+use std::io::Write;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use string_cache::StaticAtomSet;
+
+type Result<T> = std::result::Result<T, ParseError>;
+
+/// The inverse of [`ParadoxParse`](crate::ParadoxParse): a type that can be
+/// written back out in the format of the game files.
+pub trait ParadoxWrite<G: GameTrait> {
+    fn write(&self, writer: &mut Writer<G>) -> Result<()>;
+
+    /// Whether this value is the same as its default. Optional fields are not
+    /// written out if this is true.
+    fn is_default(&self) -> bool {
+        false
+    }
+}
+
+/// The counterpart to [`ParadoxParseFields`](crate::ParadoxParseFields):
+/// writes the fields of a struct without the braces around them, so that they
+/// can be part of another struct's block. This is derived along with
+/// `ParadoxWrite`.
+pub trait ParadoxWriteFields<G: GameTrait> {
+    fn write_fields(&self, writer: &mut Writer<G>) -> Result<()>;
+}
+
+/// Something that consumes a stream of tokens, such as a file in the text or
+/// binary formats.
+pub trait Emitter<G: GameTrait> {
+    fn emit(&mut self, token: Token<'_, G::Static>) -> Result<()>;
+
+    /// Called once all of the tokens have been emitted.
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The counterpart to [`Parser`](crate::Parser), which turns values into a
+/// stream of tokens for an [`Emitter`].
+pub struct Writer<'a, G: GameTrait> {
+    emitter: &'a mut dyn Emitter<G>,
+    depth: u32
+}
+
+impl <'a, G: GameTrait> Writer<'a, G> {
+    pub fn new(emitter: &'a mut dyn Emitter<G>) -> Self {
+        Self { emitter, depth: 0 }
+    }
+
+    /// Write out an entire file.
+    pub fn write(mut self, value: &dyn ParadoxWrite<G>) -> Result<()> {
+        value.write(&mut self)?;
+        self.emitter.finish()
+    }
+
+    pub fn write_token(&mut self, token: Token<'_, G::Static>) -> Result<()> {
+        self.emitter.emit(token)
+    }
+
+    /// Write a bare string, such as a key or an enum value.
+    pub fn write_atom(&mut self, atom: &str) -> Result<()> {
+        self.write_token(Token::Word(atom.into()))
+    }
+
+    /// Write a `key = value` entry. This is the inverse of the callback of
+    /// [`Parser::parse_key_scope`](crate::Parser::parse_key_scope).
+    pub fn write_field(&mut self, key: &str, value: &dyn ParadoxWrite<G>)
+            -> Result<()> {
+        self.write_field_with(key, Operator::Eq, value)
+    }
+
+    /// Write an entry that compares the key to the value, like `key > value`.
+    pub fn write_field_with(&mut self, key: &str, op: Operator,
+                            value: &dyn ParadoxWrite<G>) -> Result<()> {
+        self.write_atom(key)?;
+        self.write_token(match op {
+            Operator::Eq => Token::Eq,
+            Operator::Lt => Token::Lt,
+            Operator::Le => Token::Le,
+            Operator::Gt => Token::Gt,
+            Operator::Ge => Token::Ge,
+            Operator::Ne => Token::Ne,
+            Operator::QEq => Token::QEq,
+        })?;
+        value.write(self)
+    }
+
+    /// Write a block of `key = value` entries. The braces are left off at the
+    /// top level of the file, matching `Parser::parse_key_scope`.
+    pub fn write_key_scope<F>(&mut self, func: F) -> Result<()>
+        where F: FnOnce(&mut Self) -> Result<()>
+    {
+        if self.depth == 0 {
+            self.depth += 1;
+            let result = func(self);
+            self.depth -= 1;
+            result
+        } else {
+            self.with_scope(func)
+        }
+    }
+
+    /// Write a block surrounded by braces, such as a list.
+    pub fn with_scope<F>(&mut self, func: F) -> Result<()>
+        where F: FnOnce(&mut Self) -> Result<()>
+    {
+        self.write_token(Token::LBrace)?;
+        self.depth += 1;
+        let result = func(self);
+        self.depth -= 1;
+        result?;
+        self.write_token(Token::RBrace)
+    }
+}
+
+/// Format a float with more decimal places than a [`FixedPoint`], so that the
+/// two can be told apart when reading typed literals.
+fn format_float(value: f64) -> String {
+    let text = value.to_string();
+    match text.find('.') {
+        Some(dot) if text.len() - dot > 4 => text,
+        _ => format!("{:.5}", value)
+    }
+}
+
+/// Whether a block holds `key = value` entries or a list of values.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Block {
+    Unknown,
+    Object,
+    List
+}
+
+/// An emitter that writes the text format, with one entry per line and lists
+/// on a single line.
+pub struct TextWriter<W: Write> {
+    writer: W,
+    encoding: Encoding,
+    /// The kinds of the enclosing blocks. The top level is always an object.
+    blocks: Vec<Block>,
+    /// The first token of a block, which is held back until we know whether
+    /// it's a key or a list element.
+    pending: Option<String>,
+    /// Whether the last token written was a key or operator, which means the
+    /// next token is a value.
+    in_entry: bool,
+    at_start: bool
+}
+
+impl <W: Write> TextWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            encoding: Encoding::default(),
+            blocks: vec![Block::Object],
+            pending: None,
+            in_entry: false,
+            at_start: true
+        }
+    }
+
+    /// Set the encoding to use for strings.
+    pub fn with_encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Get the text of a value token. Returns `None` for braces and operators.
+    fn format_value<S: StaticAtomSet>(&self, token: &Token<'_, S>)
+            -> Option<String> {
+        Some(match token {
+            Token::LBrace | Token::RBrace | Token::Eq | Token::Lt |
+                Token::Le | Token::Gt | Token::Ge | Token::Ne |
+                Token::QEq => return None,
+            Token::String(s) => format!("\"{}\"", escape(s)),
+            Token::Word(s) => s.to_string(),
+            Token::Atom(s) => s.to_string(),
+            Token::Rgb => "rgb".into(),
+            Token::Hsv => "hsv".into(),
+            Token::Bool(b) => (if *b { "yes" } else { "no" }).into(),
+            Token::Fixed(f) => f.to_string(),
+            Token::Float(f) => format_float(*f),
+            Token::Integer(i) => i.to_string(),
+            Token::Unsigned(i) => i.to_string(),
+            Token::Long(i) => i.to_string(),
+            Token::UnsignedLong(i) => i.to_string(),
+            Token::Date(date) => date.to_string(),
+            Token::Lookup(code, i) => format!("__lookup_0x{:04x}_{}", code, i),
+            Token::Unknown(code) => format!("__unknown_0x{:04x}", code),
+        })
+    }
+
+    fn write_str(&mut self, text: &str) -> Result<()> {
+        self.at_start = false;
+        self.writer.write_all(&self.encoding.encode(text))?;
+        Ok(())
+    }
+
+    /// Start a new line at the indentation of the current block.
+    fn newline(&mut self) -> Result<()> {
+        if !self.at_start {
+            self.writer.write_all(b"\n")?;
+        }
+        for _ in 1..self.blocks.len() {
+            self.writer.write_all(b"\t")?;
+        }
+        Ok(())
+    }
+
+    fn set_block(&mut self, block: Block) {
+        *self.blocks.last_mut().unwrap() = block;
+    }
+
+    fn open_block(&mut self) -> Result<()> {
+        self.write_str(if self.at_start { "{" } else { " {" })?;
+        self.blocks.push(Block::Unknown);
+        self.in_entry = false;
+        Ok(())
+    }
+
+    fn close_block(&mut self) -> Result<()> {
+        let block = self.blocks.pop()
+            .filter(|_| !self.blocks.is_empty())
+            .ok_or_else(|| ParseError::Parse("unmatched }".into(),
+                                             Default::default()))?;
+        if block == Block::Object {
+            self.newline()?;
+            self.write_str("}")
+        } else {
+            self.write_str(" }")
+        }
+    }
+}
+
+impl <G: GameTrait, W: Write> Emitter<G> for TextWriter<W> {
+    fn emit(&mut self, token: Token<'_, G::Static>) -> Result<()> {
+        let block = *self.blocks.last().unwrap();
+        let text = self.format_value(&token);
+
+        // The first token of a block decides what kind of block it is.
+        if let Some(pending) = self.pending.take() {
+            match (&token, token.as_operator()) {
+                (_, Some(op)) => {
+                    self.set_block(Block::Object);
+                    self.newline()?;
+                    self.write_str(&format!("{} {}", pending, op))?;
+                    self.in_entry = true;
+                    return Ok(());
+                },
+                (Token::LBrace, None) => {
+                    // A `key { ... }` entry, without the `=`.
+                    self.set_block(Block::Object);
+                    self.newline()?;
+                    self.write_str(&pending)?;
+                    return self.open_block();
+                },
+                _ => {
+                    self.set_block(Block::List);
+                    self.write_str(&format!(" {}", pending))?;
+                    return Emitter::<G>::emit(self, token);
+                }
+            }
+        }
+
+        if self.in_entry {
+            return match (token, text) {
+                (Token::LBrace, _) => self.open_block(),
+                // A colour is followed by its list of components.
+                (Token::Rgb, Some(text)) | (Token::Hsv, Some(text)) =>
+                    self.write_str(&format!(" {}", text)),
+                (_, Some(text)) => {
+                    self.in_entry = false;
+                    self.write_str(&format!(" {}", text))
+                },
+                (token, None) => Err(token.into()),
+            };
+        }
+
+        match (block, token, text) {
+            (_, Token::RBrace, _) => self.close_block(),
+            (Block::Unknown, Token::LBrace, _) => {
+                self.set_block(Block::List);
+                self.open_block()
+            },
+            (Block::List, Token::LBrace, _) => self.open_block(),
+            (Block::Unknown, _, Some(text)) => {
+                self.pending = Some(text);
+                Ok(())
+            },
+            (Block::List, _, Some(text)) => self.write_str(&format!(" {}", text)),
+            (Block::Object, _, Some(text)) => {
+                self.newline()?;
+                self.write_str(&text)
+            },
+            (_, token, None) if token.as_operator().is_some() => {
+                let op = token.as_operator().unwrap();
+                self.write_str(&format!(" {}", op))?;
+                self.in_entry = true;
+                Ok(())
+            },
+            (Block::Object, Token::LBrace, None) => self.open_block(),
+            (_, token, None) => Err(token.into()),
+        }
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(pending) = self.pending.take() {
+            self.write_str(&format!(" {}", pending))?;
+        }
+        if self.blocks.len() != 1 {
+            return Err(ParseError::eof());
+        }
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// An emitter that writes the binary format, mirroring [`BinaryLexer`].
+///
+/// Names are turned back into token IDs using the token table (if given) and
+/// then [`GameTrait::get_binary_id`]. Names that aren't tokens are written as
+/// unquoted strings.
+///
+/// Lookups and unknown tokens are written with the code they were read with.
+/// Text has no way to tell the different kinds of integers apart, though, so
+/// numbers read from text are written with the first of `i32`, `u32`, `i64`
+/// and `u64` that can hold them, and a value that was a `u32` in the original
+/// binary file comes back as an `i32` if it fits. Quoted strings always use
+/// `0x000f`.
+///
+/// [`BinaryLexer`]: crate::BinaryLexer
+pub struct BinaryWriter<G: GameTrait, W: Write> {
+    writer: W,
+    encoding: Encoding,
+    table: Option<Arc<TokenTable<G::Static>>>,
+    _trait: PhantomData<G>
+}
+
+impl <G: GameTrait, W: Write> BinaryWriter<G, W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            encoding: Encoding::default(),
+            table: None,
+            _trait: PhantomData
+        }
+    }
+
+    /// Set the encoding to use for strings.
+    pub fn with_encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Look up token IDs in the given table. Names missing from it fall back
+    /// to [`GameTrait::get_binary_id`].
+    pub fn with_token_table(mut self, table: Arc<TokenTable<G::Static>>)
+            -> Self {
+        self.table = Some(table);
+        self
+    }
+
+    /// Get the underlying writer back.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_code(&mut self, code: u16) -> Result<()> {
+        self.writer.write_all(&code.to_le_bytes())?;
+        Ok(())
+    }
+
+    fn write_string(&mut self, code: u16, text: &str) -> Result<()> {
+        let data = self.encoding.encode(text);
+        let len = u16::try_from(data.len()).map_err(|_| ParseError::Parse(
+                format!("string too long for the binary format: {}", text),
+                Default::default()))?;
+        self.write_code(code)?;
+        self.writer.write_all(&len.to_le_bytes())?;
+        self.writer.write_all(&data)?;
+        Ok(())
+    }
+
+    /// Write a bare word, using its token ID if it has one.
+    fn write_word(&mut self, name: &str) -> Result<()> {
+        let id = match name {
+            "id" => Some(0x000b),
+            "name" => Some(0x001b),
+            name => self.table.as_ref()
+                .and_then(|table| table.id(name))
+                .or_else(|| G::get_binary_id(name))
+        };
+        match id {
+            Some(id) => self.write_code(id),
+            None => self.write_string(0x0017, name)
+        }
+    }
+
+    fn unsupported(token: &Token<'_, G::Static>) -> ParseError {
+        ParseError::Parse(
+            format!("{:?} can't be written in the binary format", token),
+            Default::default())
+    }
+}
+
+impl <G: GameTrait, W: Write> Emitter<G> for BinaryWriter<G, W> {
+    fn emit(&mut self, token: Token<'_, G::Static>) -> Result<()> {
+        match token {
+            Token::Eq => self.write_code(0x0001),
+            Token::LBrace => self.write_code(0x0003),
+            Token::RBrace => self.write_code(0x0004),
+            Token::Lt | Token::Le | Token::Gt | Token::Ge | Token::Ne |
+                Token::QEq | Token::Hsv => Err(Self::unsupported(&token)),
+            Token::String(s) => self.write_string(0x000f, &s),
+            Token::Word(ref name) => self.write_word(name),
+            Token::Atom(ref name) => self.write_word(name),
+            Token::Rgb => self.write_code(0x0243),
+            Token::Bool(b) => {
+                self.write_code(0x000e)?;
+                self.writer.write_all(&[b as u8])?;
+                Ok(())
+            },
+            Token::Fixed(f) => {
+                self.write_code(0x000d)?;
+                let data = match G::BINARY_F32 {
+                    BinaryF32::Fixed => f.0.to_le_bytes(),
+                    BinaryF32::Float => (f64::from(f) as f32).to_le_bytes(),
+                };
+                self.writer.write_all(&data)?;
+                Ok(())
+            },
+            Token::Float(f) => {
+                // The inverse of the conversion in the lexer, which is exact
+                // for any value it produced.
+                let val = (f * 65536.0).round();
+                if val.is_nan() || val.abs() >= i64::MAX as f64 {
+                    return Err(Self::unsupported(&token));
+                }
+                self.write_code(0x0167)?;
+                self.writer.write_all(&(val as i64).to_le_bytes())?;
+                Ok(())
+            },
+            Token::Integer(i) => {
+                self.write_code(0x000c)?;
+                self.writer.write_all(&i.to_le_bytes())?;
+                Ok(())
+            },
+            Token::Date(date) => {
+                self.write_code(0x000c)?;
+                self.writer.write_all(&date.to_binary().to_le_bytes())?;
+                Ok(())
+            },
+            Token::Unsigned(i) => {
+                self.write_code(0x0014)?;
+                self.writer.write_all(&i.to_le_bytes())?;
+                Ok(())
+            },
+            Token::Long(i) => {
+                self.write_code(0x0317)?;
+                self.writer.write_all(&i.to_le_bytes())?;
+                Ok(())
+            },
+            Token::UnsignedLong(i) => {
+                self.write_code(0x029c)?;
+                self.writer.write_all(&i.to_le_bytes())?;
+                Ok(())
+            },
+            Token::Lookup(code, index) => {
+                let size = match code {
+                    0x0d3e | 0x0d40 => 1,
+                    0x0d3d | 0x0d43 => 2,
+                    0x0d44 => 3,
+                    _ => return Err(Self::unsupported(&token))
+                };
+                if index >> (size * 8) != 0 {
+                    return Err(Self::unsupported(&token));
+                }
+                self.write_code(code)?;
+                self.writer.write_all(&index.to_le_bytes()[..size])?;
+                Ok(())
+            },
+            Token::Unknown(code) => self.write_code(code),
+        }
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
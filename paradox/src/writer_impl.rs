@@ -0,0 +1,279 @@
+use crate::{Date, FixedPoint, GameTrait, Token};
+use crate::parser::ParseError;
+use crate::writer::*;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Display;
+use std::hash::Hash;
+use string_cache::{Atom, StaticAtomSet};
+
+type WriteResult = Result<(), ParseError>;
+
+macro_rules! to_token {
+    {$T:ty, $arm:ident} => {
+        impl <G: GameTrait> ParadoxWrite<G> for $T {
+            fn write(&self, writer: &mut Writer<G>) -> WriteResult {
+                writer.write_token(Token::$arm((*self).into()))
+            }
+
+            fn is_default(&self) -> bool {
+                *self == <$T>::default()
+            }
+        }
+    }
+}
+
+to_token!{i32, Integer}
+to_token!{u32, Unsigned}
+to_token!{i64, Long}
+to_token!{u64, UnsignedLong}
+to_token!{f64, Float}
+to_token!{FixedPoint, Fixed}
+to_token!{bool, Bool}
+
+impl <G: GameTrait> ParadoxWrite<G> for f32 {
+    fn write(&self, writer: &mut Writer<G>) -> WriteResult {
+        // Go through the shortest decimal form, so that 0.1f32 isn't written
+        // as 0.10000000149011612.
+        writer.write_token(Token::Float(self.to_string().parse().unwrap()))
+    }
+
+    fn is_default(&self) -> bool {
+        *self == 0.0
+    }
+}
+
+impl <G: GameTrait> ParadoxWrite<G> for String {
+    fn write(&self, writer: &mut Writer<G>) -> WriteResult {
+        writer.write_token(Token::String(self.as_str().into()))
+    }
+
+    fn is_default(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+impl <G: GameTrait> ParadoxWrite<G> for Date {
+    fn write(&self, writer: &mut Writer<G>) -> WriteResult {
+        writer.write_token(Token::Date(*self))
+    }
+
+    fn is_default(&self) -> bool {
+        *self == Date::default()
+    }
+}
+
+impl <G: GameTrait, Static: StaticAtomSet> ParadoxWrite<G> for Atom<Static> {
+    fn write(&self, writer: &mut Writer<G>) -> WriteResult {
+        writer.write_atom(self)
+    }
+
+    fn is_default(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+impl <G: GameTrait, T: ParadoxWrite<G>, const N: usize> ParadoxWrite<G> for [T; N] {
+    fn write(&self, writer: &mut Writer<G>) -> WriteResult {
+        writer.with_scope(|writer| {
+            self.iter().try_for_each(|value| value.write(writer))
+        })
+    }
+
+    fn is_default(&self) -> bool {
+        self.iter().all(T::is_default)
+    }
+}
+
+impl <G: GameTrait, T: ParadoxWrite<G>> ParadoxWrite<G> for Vec<T> {
+    fn write(&self, writer: &mut Writer<G>) -> WriteResult {
+        writer.with_scope(|writer| {
+            self.iter().try_for_each(|value| value.write(writer))
+        })
+    }
+
+    fn is_default(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+/// Maps are written sorted by key, so that the output doesn't depend on the
+/// iteration order of the map.
+impl <G, K, T> ParadoxWrite<G> for HashMap<K, T>
+    where G: GameTrait, K: Display + Eq + Hash, T: ParadoxWrite<G>
+{
+    fn write(&self, writer: &mut Writer<G>) -> WriteResult {
+        let mut entries: Vec<_> = self.iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        writer.write_key_scope(|writer| {
+            entries.into_iter()
+                .try_for_each(|(key, value)| writer.write_field(&key, value))
+        })
+    }
+
+    fn is_default(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+impl <G, K, T> ParadoxWrite<G> for BTreeMap<K, T>
+    where G: GameTrait, K: Display, T: ParadoxWrite<G>
+{
+    fn write(&self, writer: &mut Writer<G>) -> WriteResult {
+        writer.write_key_scope(|writer| {
+            self.iter().try_for_each(|(key, value)| {
+                writer.write_field(&key.to_string(), value)
+            })
+        })
+    }
+
+    fn is_default(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+/// A `key = value` pair, the inverse of reading one.
+impl <G, K, T> ParadoxWrite<G> for (K, T)
+    where G: GameTrait, K: Display, T: ParadoxWrite<G>
+{
+    fn write(&self, writer: &mut Writer<G>) -> WriteResult {
+        writer.write_field(&self.0.to_string(), &self.1)
+    }
+}
+
+/// Sets are written in sorted order, like maps.
+impl <G, T> ParadoxWrite<G> for HashSet<T>
+    where G: GameTrait, T: ParadoxWrite<G> + Ord
+{
+    fn write(&self, writer: &mut Writer<G>) -> WriteResult {
+        let mut values: Vec<_> = self.iter().collect();
+        values.sort();
+        writer.with_scope(|writer| {
+            values.into_iter().try_for_each(|value| value.write(writer))
+        })
+    }
+
+    fn is_default(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+/// `None` can't be written, so fields holding one are left out.
+impl <G: GameTrait, T: ParadoxWrite<G>> ParadoxWrite<G> for Option<T> {
+    fn write(&self, writer: &mut Writer<G>) -> WriteResult {
+        match self {
+            Some(value) => value.write(writer),
+            None => Err(ParseError::Parse("can't write a missing value".into(),
+                                          Default::default()))
+        }
+    }
+
+    fn is_default(&self) -> bool {
+        self.is_none()
+    }
+}
+
+impl <G: GameTrait, T: ParadoxWrite<G> + ?Sized> ParadoxWrite<G> for Box<T> {
+    fn write(&self, writer: &mut Writer<G>) -> WriteResult {
+        (**self).write(writer)
+    }
+
+    fn is_default(&self) -> bool {
+        (**self).is_default()
+    }
+}
+
+impl <G: GameTrait> ParadoxWrite<G> for () {
+    fn write(&self, writer: &mut Writer<G>) -> WriteResult {
+        writer.with_scope(|_| Ok(()))
+    }
+
+    fn is_default(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::collections::{BTreeMap, HashMap, HashSet};
+    use std::path::Path;
+    use string_cache::{Atom, EmptyStaticAtomSet};
+
+    struct TestGame;
+    impl GameTrait for TestGame {
+        type Static = EmptyStaticAtomSet;
+        fn get_binary_token(_: u16) -> Option<Atom<EmptyStaticAtomSet>> {
+            None
+        }
+    }
+
+    fn to_text(value: &dyn ParadoxWrite<TestGame>) -> String {
+        let mut output = Vec::new();
+        let mut emitter = TextWriter::new(&mut output);
+        Writer::new(&mut emitter).write(value).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    fn from_text<T: ParadoxParse<TestGame> + Default>(text: &str) -> T {
+        let mut lexer = TextLexer::new(text.as_bytes(), "input".into());
+        let mut data = GameData::load(Path::new(".")).unwrap();
+        let mut value = T::default();
+        Parser::new(&mut lexer, &mut data).parse(&mut value).unwrap();
+        value
+    }
+
+    #[test]
+    fn write_map() {
+        let mut map = HashMap::new();
+        map.insert("b".to_owned(), vec![1, 2, 3]);
+        map.insert("a".to_owned(), vec![]);
+        map.insert("c".to_owned(), vec![4]);
+        let text = to_text(&map);
+        assert_eq!(text, "a = { }\nb = { 1 2 3 }\nc = { 4 }\n");
+        assert_eq!(from_text::<HashMap<String, Vec<i32>>>(&text), map);
+    }
+
+    #[test]
+    fn write_nested() {
+        let mut inner = HashMap::new();
+        inner.insert("date", vec!["1444.11.11".parse::<Date>().unwrap()]);
+        let mut map = HashMap::new();
+        map.insert("outer", inner);
+        assert_eq!(to_text(&map), "outer = {\n\tdate = { 1444.11.11 }\n}\n");
+
+        let mut map = HashMap::new();
+        map.insert("name", "Jean \"le Bon\"".to_owned());
+        let text = to_text(&map);
+        assert_eq!(text, "name = \"Jean \\\"le Bon\\\"\"\n");
+        assert_eq!(from_text::<HashMap<String, String>>(&text)["name"],
+                   map["name"]);
+    }
+
+    #[test]
+    fn containers() {
+        type Entries = Vec<(String, Option<i32>)>;
+        let text = "a = 1\nb = 2\na = 3\n";
+        let map: MultiMap<String, i32> = from_text(text);
+        assert_eq!(map.get_all(&"a".to_owned()).collect::<Vec<_>>(), [&1, &3]);
+        assert_eq!(map.get_last(&"a".to_owned()), Some(&3));
+        assert_eq!(to_text(&map), text);
+
+        let tree: BTreeMap<i32, Box<i32>> = from_text("2 = 1 1 = 2 2 = 3");
+        assert_eq!(tree.values().map(|v| **v).collect::<Vec<_>>(), [2, 3]);
+        assert_eq!(to_text(&tree), "1 = 2\n2 = 3\n");
+
+        let mut map: HashMap<String, Entries> =
+            from_text("x = { b = 1 a = 2 b = 3 }");
+        assert_eq!(map["x"], vec![("b".into(), Some(1)), ("a".into(), Some(2)),
+                                  ("b".into(), Some(3))]);
+        map.get_mut("x").unwrap().truncate(2);
+        assert_eq!(to_text(&map), "x = {\n\tb = 1\n\ta = 2\n}\n");
+
+        let set: HashMap<String, HashSet<String>> =
+            from_text("tags = { SWE FRA SWE }");
+        assert_eq!(set["tags"].len(), 2);
+        assert_eq!(to_text(&set), "tags = { \"FRA\" \"SWE\" }\n");
+    }
+}
@@ -1,7 +1,6 @@
-use eu4::{Eu4Atom, GameData, Gamestate};
 mod trade;
 
-/*fn get_tier(gamedata: &GameData, gamestate: &Gamestate) {
+/*fn get_tier(gamedata: &eu4::GameData, gamestate: &eu4::Gamestate) {
     // Modifiers:
     let country_id = gamedata.tags.get_index("BRB".into());
 }*/
@@ -9,12 +8,19 @@ mod trade;
 fn main() -> Result<(), paradox::ParseError> {
     let mut eu4data = eu4::GameData::new(
         &paradox::get_default_steam_dir().join("Europa Universalis IV"))?;
-    let gamestate = paradox::load_savegame::<eu4::Eu4Trait, eu4::Gamestate>(
-        &std::path::Path::new("/tmp/TrailOfTears.eu4-37"),
+    for diagnostic in eu4data.base_info.diagnostics() {
+        eprintln!("warning: {}", diagnostic);
+    }
+    let (gamestate, diagnostics) =
+        paradox::load_savegame_with_options::<eu4::Eu4Trait, eu4::Gamestate>(
+        std::path::Path::new("/tmp/TrailOfTears.eu4-37"),
 //        &paradox::get_default_save_dir().join("Europa Universalis IV/save games/Mamluks.eu4"),
-        &mut eu4data.base_info
+        &mut eu4data.base_info, None, paradox::ParseOptions::default()
         )?;
-    trade::optimize_trade(&eu4data, &gamestate, &gamestate.player);
+    for diagnostic in &diagnostics {
+        eprintln!("warning: {}", diagnostic);
+    }
+    trade::optimize_trade(&eu4data, &gamestate, &gamestate.meta.player);
     // Debugging: trade power for everybody
     //for (tag, country) in &gamestate.countries {
     //    if country.num_of_cities == 0 { continue; }
@@ -22,8 +28,8 @@ fn main() -> Result<(), paradox::ParseError> {
     //    println!("{} global_prov_trade_power_modifier = {:?}", tag,
     //             modifiers[&eu4::eu4_atom!("global_prov_trade_power_modifier")]);
     //}
-    //let country = &gamestate.countries[&gamestate.player];
-    //let modifiers = country.get_modifiers(&eu4data, &gamestate, &gamestate.player);
+    //let country = &gamestate.countries[&gamestate.meta.player];
+    //let modifiers = country.get_modifiers(&eu4data, &gamestate, &gamestate.meta.player);
     //for (key, value) in modifiers.modifiers {
     //    println!("Modifier {} = {:?}", key, value);
     //}
@@ -1,4 +1,4 @@
-use eu4::{eu4_atom, Eu4Atom, GameData, Gamestate};
+use eu4::{eu4_atom, Eu4Atom, GameData, Gamestate, TradeNodeAction};
 use paradox::FixedPoint;
 use petgraph::graph::{EdgeIndex, Graph, NodeIndex};
 use petgraph::visit::{EdgeRef, Topo, Walker};
@@ -26,7 +26,7 @@ impl <'a> ModifierCache<'a> {
             .entry(country.clone())
             .or_insert_with(|| {
                 self.gamestate.countries[country]
-                    .get_modifiers(self.gamedata, self.gamestate, &country)
+                    .get_modifiers(self.gamedata, self.gamestate, country)
             })[modifier].as_fixed_point()
     }
 }
@@ -87,7 +87,7 @@ impl TradeNetwork {
     fn new(data: &GameData, gamestate: &Gamestate, us: &Eu4Atom) -> Self {
         // Build the graph structure from the game data.
         let names : Vec<_> = data.trade.get_names()
-            .map(|name| name.clone())
+            .cloned()
             .collect();
         let mut graph = SimpleTradeGraph::with_capacity(names.len(),
             names.len() * 4);
@@ -129,7 +129,7 @@ impl TradeNetwork {
         for gs_node in &gamestate.trade.node {
             let tn_idx = get_index(&gs_node.definitions);
             {
-                let mut node = &mut graph[tn_idx];
+                let node = &mut graph[tn_idx];
                 node.local_trade_value = gs_node.local_value;
                 node.collecting_trade_power = gs_node.collector_power;
                 node.transfer_trade_power = gs_node.pull_power;
@@ -143,11 +143,12 @@ impl TradeNetwork {
                 let trade_power = country_trade.val + country_trade.t_in -
                     country_trade.t_out;
                 if trade_power == FixedPoint::ZERO { continue; }
-                let merchant_type = country_trade.r#type;
+                let merchant_type = country_trade.action;
                 let steering = country_trade.has_trader &&
-                    merchant_type == 1;
+                    merchant_type == TradeNodeAction::Steer;
                 let collecting = country_trade.has_capital ||
-                    (country_trade.has_trader && merchant_type == 0);
+                    (country_trade.has_trader &&
+                     merchant_type == TradeNodeAction::Collect);
                 //let is_domestic = country_trade.has_capital ||
                 //    country_trade.province_power == max_p_power;
 
@@ -357,6 +358,7 @@ impl TradeNetwork {
            * f64::from(node.trade_power_modifier)
     }
 
+    #[allow(dead_code)] // Useful for debugging.
     fn display_dot(&self) {
         let disp_graph = self.graph.map(
             |idx, weight| {
@@ -437,7 +439,7 @@ pub fn optimize_trade(data: &GameData, gamestate: &Gamestate,
             }
         }
     }
-    ship_power = ship_power / FixedPoint::from(num_ships);
+    ship_power /= FixedPoint::from(num_ships);
     println!("{} ships of average {} power each", num_ships, ship_power);
     println!("global_ship_power: {}",
              modifiers[&eu4_atom!("global_ship_trade_power")]